@@ -1,7 +1,32 @@
 //! The integration of [futures](core::future::Future) based on asymmetric
 //! stackful coroutines.
 
+mod arena;
+mod cancel;
+mod detach;
+#[cfg(feature = "std")]
+mod inline;
+#[cfg(feature = "std")]
+mod join;
+#[cfg(feature = "std")]
+mod pool;
+#[cfg(all(feature = "signal", target_os = "linux"))]
+mod preempt;
+mod saver;
+mod select;
+#[cfg(feature = "std")]
+mod stream;
+mod timeout;
+#[cfg(feature = "watchdog")]
+mod watchdog;
+
+use alloc::boxed::Box;
+#[cfg(feature = "std")]
+use alloc::{collections::VecDeque, vec::Vec};
+#[cfg(feature = "std")]
+use core::num::NonZeroUsize;
 use core::{
+    fmt,
     future::{Future, IntoFuture},
     marker::PhantomData,
     ops::CoroutineState,
@@ -9,18 +34,58 @@ use core::{
     ptr::NonNull,
     task::{Context, Poll, Waker},
 };
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
 
+use futures_core::future::FusedFuture;
 use unico_ful::{
     asym::{Gn, YieldHandle},
-    sym::PanicHook,
+    sym::{AbortHook, PanicHook},
     Build, BuildUnchecked, Builder, NewError,
 };
-use unico_stack::Stack;
+use unico_stack::{Global, IntoStack};
+
+#[cfg(feature = "tokio")]
+pub use self::detach::TokioSpawner;
+#[cfg(feature = "std")]
+pub use self::inline::{sync_inline, SyncInline};
+#[cfg(feature = "std")]
+pub use self::join::{join_all, select_completed, Completed, JoinAll};
+#[cfg(feature = "std")]
+pub use self::pool::{CoPool, PoolRun};
+#[cfg(all(feature = "signal", target_os = "linux"))]
+pub use self::preempt::{maybe_yield, should_yield, start_yield_timer, YieldTimer};
+#[cfg(feature = "std")]
+pub use self::stream::{stream_from_blocking, BlockingStream, Emitter};
+#[cfg(feature = "watchdog")]
+pub use self::watchdog::{start_watchdog, Hang, Watchdog};
+pub use self::{
+    arena::{with_arena, Arena},
+    cancel::{cancellable, Cancellable, CancellationToken, Cancelled},
+    detach::{detach, set_spawner, Detached, Spawner},
+    saver::{register_context_saver, ContextSaver},
+    select::{wait_select, SelectNext, WaitSelect},
+    timeout::{timeout, TimeSource, TimedOut, Timeout},
+};
 
 /// A [`Future`] based on a stackful generator.
 ///
 /// This structure cannot be created directly. [`sync`] should be used instead.
-pub struct Asym<'a, T>(Gn<'a, T, (), NonNull<Waker>>);
+///
+/// Once it resolves, or resumes the panic of its code, the finished coroutine
+/// is never resumed again. Polling it any further panics in debug builds to
+/// catch the misbehaving executor, and returns [`Poll::Pending`] forever
+/// otherwise, see [`FusedFuture`].
+///
+/// A panic of its code unwinds the frames of the coroutine first, running
+/// their destructors, e.g. releasing the guards held, and is only then resumed
+/// in the task polling it. So once the panic is caught, e.g. by the executor,
+/// the state shared with the other coroutines is left unlocked for them.
+pub struct Asym<'a, T>(
+    Gn<'a, T, (), NonNull<Waker>>,
+    Option<saver::Saved>,
+    #[cfg(feature = "watchdog")] u64,
+);
 
 /// The context of the execution of the current [`Asym`].
 ///
@@ -34,7 +99,7 @@ pub struct AsymContext<'y> {
 impl<'a, F, T, S, P> Build<F, S, P> for Asym<'a, T>
 where
     F: FnOnce(AsymContext<'_>) -> T + Send + 'a,
-    S: Into<Stack>,
+    S: IntoStack,
     P: PanicHook,
 {
     fn build(builder: Builder<S, P>, arg: F) -> Result<Self, Self::Error> {
@@ -46,7 +111,7 @@ where
 impl<F, T, S, P> BuildUnchecked<F, S, P> for Asym<'_, T>
 where
     F: FnOnce(AsymContext<'_>) -> T,
-    S: Into<Stack>,
+    S: IntoStack,
     P: PanicHook,
 {
     type Error = NewError;
@@ -59,9 +124,32 @@ where
         arg: F,
     ) -> Result<Self, Self::Error> {
         // SAFETY: The contract is the same.
-        Ok(Asym(unsafe {
+        let gn = unsafe {
             Gn::build_unchecked(builder, |y, waker| arg(AsymContext { y, waker }))?
-        }))
+        };
+        Ok(Asym(
+            gn,
+            None,
+            #[cfg(feature = "watchdog")]
+            watchdog::next_id(),
+        ))
+    }
+}
+
+impl<T> Asym<'_, T> {
+    /// The name of the block of code set by [`AsymBuilder::named`], if any.
+    pub fn name(&self) -> Option<&'static str> {
+        self.0.name()
+    }
+}
+
+impl<T> fmt::Debug for Asym<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut f = f.debug_struct("Asym");
+        f.field("name", &self.name());
+        #[cfg(feature = "watchdog")]
+        f.field("id", &self.2);
+        f.field("finished", &self.0.is_finished()).finish()
     }
 }
 
@@ -69,15 +157,50 @@ impl<T> Future for Asym<'_, T> {
     type Output = T;
 
     fn poll<'x, 'y>(mut self: Pin<&'x mut Self>, cx: &mut Context<'y>) -> Poll<T> {
-        match self.0.resume(cx.waker().into()) {
+        if self.0.is_finished() {
+            debug_assert!(false, "`Asym` polled after completion");
+            return Poll::Pending;
+        }
+        let outer = saver::Restore(saver::save());
+        if let Some(inner) = self.1.take() {
+            saver::restore(inner);
+        }
+        #[cfg(feature = "watchdog")]
+        let running = watchdog::running(self.2, self.0.name());
+        // Entered after the inner context is restored and exited before it's
+        // saved, so resuming never nests the span in its previous entrance.
+        #[cfg(feature = "tracing")]
+        let span = tracing::trace_span!("sync", name = self.0.name()).entered();
+        let state = self.0.resume(cx.waker().into());
+        #[cfg(feature = "tracing")]
+        drop(span);
+        #[cfg(feature = "watchdog")]
+        drop(running);
+        if let CoroutineState::Yielded(()) = state {
+            self.1 = Some(saver::save());
+        }
+        drop(outer);
+
+        match state {
             CoroutineState::Yielded(()) => Poll::Pending,
             CoroutineState::Complete(output) => Poll::Ready(output),
         }
     }
 }
 
+impl<T> FusedFuture for Asym<'_, T> {
+    fn is_terminated(&self) -> bool {
+        self.0.is_finished()
+    }
+}
+
 pub trait AsymWait: IntoFuture + Sized {
     /// Wait on a future "synchronously" with a specified yielding context.
+    ///
+    /// The future is polled with the waker of the task polling the current
+    /// [`Asym`], so waking it from anywhere gets the task re-polled. It's
+    /// pinned in place on the stack of the coroutine, so waiting never
+    /// allocates, whether the future is [`Unpin`] or not.
     fn wait_with(self, cx: &mut AsymContext<'_>) -> Self::Output
     where
         <Self as IntoFuture>::IntoFuture: Send,
@@ -94,19 +217,108 @@ pub trait AsymWait: IntoFuture + Sized {
     }
 
     /// Wait on a future "synchronously".
+    ///
+    /// Inside a coroutine created by [`sync`], the coroutine is suspended back
+    /// to the executor until the future is ready, which also works in the
+    /// destructors run as the block of code completes. Outside of any
+    /// coroutine, the current thread is blocked instead, unless in a block of
+    /// [`sync_inline`], which escalates to a coroutine if the future is
+    /// pending.
+    ///
+    /// While the coroutine is unwinding, either from a panic or because its
+    /// [`Asym`] is dropped before completion, there is no executor to suspend
+    /// back to, so the destructors waiting on some future block the current
+    /// thread as well.
+    ///
+    /// Blocking the thread of an executor may stall the other tasks on it, or
+    /// even deadlock if the future is driven by that executor. Calling it
+    /// outside a coroutine is still not an error, since the destructors above
+    /// and the plain threads rely on it, so it never panics for forgetting to
+    /// wrap the code in [`sync`]. Code expecting to be wrapped can assert it
+    /// with [`in_coroutine`] first.
     #[cfg(feature = "std")]
     fn wait(self) -> Self::Output
     where
         <Self as IntoFuture>::IntoFuture: Send,
     {
+        // Suspending in the middle of unwinding would also leak the panicking
+        // state of this thread to the executor.
+        if std::thread::panicking() {
+            return block_on::block_on(core::pin::pin!(self.into_future()));
+        }
         match CX.take() {
             Some(cx) => {
                 let mut guard = SetCxGuard(None);
                 self.wait_with(guard.0.insert(cx))
             }
-            None => block_on::block_on(core::pin::pin!(self.into_future())),
+            None => {
+                let mut future = core::pin::pin!(self.into_future());
+                match inline::poll_inline(future.as_mut()) {
+                    Some(output) => output,
+                    None => block_on::block_on(future),
+                }
+            }
         }
     }
+
+    /// Similar to [`AsymWait::wait_with`], but gives up after `ticks` of
+    /// `source`.
+    ///
+    /// See [`timeout`] for how the deadline is checked.
+    fn wait_timeout_with<S>(
+        self,
+        cx: &mut AsymContext<'_>,
+        source: S,
+        ticks: u64,
+    ) -> Result<Self::Output, TimedOut>
+    where
+        <Self as IntoFuture>::IntoFuture: Send,
+        S: TimeSource + Send,
+    {
+        timeout(self, source, ticks).wait_with(cx)
+    }
+
+    /// Similar to [`AsymWait::wait`], but gives up after `duration`.
+    ///
+    /// See [`timeout`] for how the deadline is checked.
+    #[cfg(feature = "std")]
+    fn wait_timeout(self, duration: Duration) -> Result<Self::Output, TimedOut>
+    where
+        <Self as IntoFuture>::IntoFuture: Send,
+    {
+        let ticks = duration.as_nanos().try_into().unwrap_or(u64::MAX);
+        timeout(self, Instant::now(), ticks).wait()
+    }
+
+    /// Similar to [`AsymWait::wait_with`], but gives up once `token` is
+    /// cancelled, dropping the future before returning.
+    ///
+    /// See [`cancellable`] for more information.
+    fn wait_cancellable_with(
+        self,
+        cx: &mut AsymContext<'_>,
+        token: &CancellationToken,
+    ) -> Result<Self::Output, Cancelled>
+    where
+        <Self as IntoFuture>::IntoFuture: Send,
+    {
+        cancellable(self, token).wait_with(cx)
+    }
+
+    /// Similar to [`AsymWait::wait`], but gives up once `token` is cancelled,
+    /// dropping the future before returning.
+    ///
+    /// See [`cancellable`] for more information.
+    #[cfg(feature = "std")]
+    fn wait_cancellable(
+        self,
+        token: &CancellationToken,
+    ) -> Result<Self::Output, Cancelled>
+    where
+        <Self as IntoFuture>::IntoFuture: Send,
+    {
+        cancellable(self, token).wait()
+    }
 }
 
 impl<F: Future + Send + Sized> AsymWait for F {}
@@ -119,31 +331,125 @@ where
 {
     AsymBuilder {
         func,
+        name: None,
         marker: PhantomData,
     }
 }
 
+/// A block of sync code waiting to be turned into a future, returned by
+/// [`sync`] and [`sync_with`].
+///
+/// The stackful coroutine is not created until [`IntoFuture::into_future`] is
+/// called, which is done implicitly by `.await`. To store it in a collection of
+/// futures, call `.into_future()` or [`AsymBuilder::boxed`] explicitly.
+///
+/// This structure and its future are both [`Send`] if the block of code is.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
 pub struct AsymBuilder<'a, T, F>
 where
     F: FnOnce(AsymContext<'_>) -> T + Send + 'a,
 {
     func: F,
+    name: Option<&'static str>,
     marker: PhantomData<&'a ()>,
 }
 
+impl<'a, T, F> AsymBuilder<'a, T, F>
+where
+    F: FnOnce(AsymContext<'_>) -> T + Send + 'a,
+{
+    /// Maps the output of the block of code with `map`, which is executed on
+    /// the same coroutine right after the block.
+    pub fn map<U, G>(
+        self,
+        map: G,
+    ) -> AsymBuilder<'a, U, impl FnOnce(AsymContext<'_>) -> U + Send + 'a>
+    where
+        G: FnOnce(T) -> U + Send + 'a,
+    {
+        let func = self.func;
+        AsymBuilder {
+            name: self.name,
+            ..sync_with(move |cx| map(func(cx)))
+        }
+    }
+
+    /// Names the coroutine of the block of code, e.g. `"fatfs-job-3"`, which
+    /// is reported along with its panics and hangs, see [`Builder::name`].
+    /// With the `tracing` feature, every resumption of the block also runs in
+    /// a `sync` span at the trace level, which records the name.
+    pub fn named(self, name: &'static str) -> Self {
+        AsymBuilder {
+            name: Some(name),
+            ..self
+        }
+    }
+
+    /// The builder of the coroutine, with the name if any.
+    fn builder(&self) -> Builder<&'static Global, AbortHook> {
+        Builder {
+            name: self.name,
+            ..Builder::new()
+        }
+    }
+
+    /// Like [`IntoFuture::into_future`], but returns the error instead of
+    /// panicking if the coroutine cannot be created, e.g. when its stack cannot
+    /// be allocated.
+    pub fn try_into_future(self) -> Result<Asym<'a, T>, NewError> {
+        self.builder().build(self.func)
+    }
+
+    /// Like [`IntoFuture::into_future`], but runs the block of code on `stack`
+    /// instead of one from the global stack allocator.
+    ///
+    /// This overrides the stack of this block only, e.g. to put a block
+    /// suspected of overflowing on a guarded stack from a `&dyn StackAllocator`
+    /// while the others keep using the global one.
+    #[track_caller]
+    pub fn into_future_on(self, stack: impl IntoStack) -> Asym<'a, T> {
+        match self.try_into_future_on(stack) {
+            Ok(asym) => asym,
+            Err(err) => panic!("failed to build a stackful future: {err}"),
+        }
+    }
+
+    /// Like [`AsymBuilder::into_future_on`], but returns the error instead of
+    /// panicking if the coroutine cannot be created.
+    pub fn try_into_future_on(
+        self,
+        stack: impl IntoStack,
+    ) -> Result<Asym<'a, T>, NewError> {
+        self.builder().on(stack).build(self.func)
+    }
+
+    /// Turns the block of code into a boxed future for dynamic dispatch.
+    pub fn boxed(self) -> Pin<Box<dyn Future<Output = T> + Send + 'a>>
+    where
+        T: 'a,
+    {
+        Box::pin(self.into_future())
+    }
+}
+
 impl<'a, T, F: FnOnce(AsymContext<'_>) -> T + Send> IntoFuture for AsymBuilder<'a, T, F> {
     type Output = T;
 
     type IntoFuture = Asym<'a, T>;
 
     fn into_future(self) -> Self::IntoFuture {
-        Builder::new()
-            .build(self.func)
-            .expect("failed to build a stackful future")
+        match self.try_into_future() {
+            Ok(asym) => asym,
+            Err(err) => panic!("failed to build a stackful future: {err}"),
+        }
     }
 }
 
 /// Turns a block of sync code into a future.
+///
+/// The future relies on nothing but the waker of the task polling it, so it
+/// runs on any executor, e.g. `tokio`, `smol` or a plain `block_on`, and the
+/// futures waited inside are driven by their own reactors as usual.
 #[cfg(feature = "std")]
 pub fn sync<'a, T: 'a>(
     func: impl FnOnce() -> T + Send + 'a,
@@ -158,6 +464,287 @@ pub fn sync<'a, T: 'a>(
     })
 }
 
+/// Like [`sync`], but names the coroutine, see [`AsymBuilder::named`].
+#[cfg(feature = "std")]
+pub fn sync_named<'a, T: 'a>(
+    name: &'static str,
+    func: impl FnOnce() -> T + Send + 'a,
+) -> AsymBuilder<'a, T, impl FnOnce(AsymContext<'_>) -> T> {
+    sync(func).named(name)
+}
+
+/// Similar to [`sync`], but asserts that the returned future can be resumed on
+/// another thread after it's suspended, e.g. when a work-stealing executor
+/// moves the task between its workers.
+///
+/// The future should be awaited directly by the task instead of inside another
+/// stackful coroutine, whose context is local to the thread it runs on.
+///
+/// The state of the coroutine kept in thread-local storage is carried along:
+/// [`AsymWait::wait`] holds the current [`AsymContext`] on the stack of the
+/// coroutine while suspended, the [`ContextSaver`]s move their context to the
+/// thread resuming it, and the watchdog watches the thread polling it.
+/// Any other thread-local storage, e.g. a `thread_local!` of the block itself,
+/// must not be used across a wait, since the code after it may run on another
+/// thread.
+///
+/// # Panics
+///
+/// Panics if the backend of the global resumer is thread-affine, like
+/// `Ucontext`, whose contexts must be resumed on the thread creating them.
+#[cfg(feature = "std")]
+#[track_caller]
+pub fn sync_migratable<'a, T: 'a>(
+    func: impl FnOnce() -> T + Send + 'a,
+) -> AsymBuilder<'a, T, impl FnOnce(AsymContext<'_>) -> T> {
+    let info = unico_context::backend_info();
+    assert!(
+        !info.thread_affine,
+        "the `{}` backend is thread-affine, so its coroutines cannot migrate \
+         between threads",
+        info.name,
+    );
+    sync(func)
+}
+
+/// Whether the current code runs in a block of sync code created by [`sync`],
+/// where [`AsymWait::wait`] suspends the coroutine instead of blocking the
+/// current thread.
+///
+/// This is `false` in the futures polled by the block, which are not part of
+/// the block itself.
+#[cfg(feature = "std")]
+pub fn in_coroutine() -> bool {
+    let cx = CX.take();
+    let inside = cx.is_some();
+    CX.set(cx);
+    inside
+}
+
+/// Awaits `setup` asynchronously, and then turns a block of sync code into a
+/// future with the output of `setup` as its argument.
+///
+/// This is useful when some async preparation (like opening files) is needed
+/// before entering a big synchronous procedure.
+#[cfg(feature = "std")]
+pub async fn sync_after<'a, Fut, F, T>(setup: Fut, func: F) -> T
+where
+    Fut: Future,
+    Fut::Output: Send + 'a,
+    F: FnOnce(Fut::Output) -> T + Send + 'a,
+    T: 'a,
+{
+    let output = setup.await;
+    sync(move || func(output)).await
+}
+
+/// Runs `func` on a new `stack`, which is typically larger than the current
+/// one, and returns its output.
+///
+/// This is a checkpoint for occasional deep recursion without over-provisioning
+/// every stack. Note that the current stack is not relocated, since references
+/// into it cannot be fixed up; instead, `func` is executed in a nested
+/// coroutine, which is waited on with [`AsymWait::wait`], so `.wait()` is still
+/// available inside `func`.
+///
+/// This function is unstable and may change in the future.
+#[cfg(all(feature = "std", feature = "unstable-grow"))]
+#[track_caller]
+pub fn grow_stack<'a, T: 'a>(
+    stack: impl IntoStack,
+    func: impl FnOnce() -> T + Send + 'a,
+) -> T {
+    let asym: Asym<'a, T> = match Builder::new().on(stack).build(sync(func).func) {
+        Ok(asym) => asym,
+        Err(err) => panic!("failed to build a stackful future: {err}"),
+    };
+    asym.wait()
+}
+
+/// Runs a short block of blocking code from async code, without creating a
+/// coroutine if possible.
+///
+/// If the `tokio` feature is enabled and the current runtime is multi-threaded,
+/// the block is executed in place with `tokio::task::block_in_place`, which
+/// hands the other tasks off to another worker thread. Otherwise, it falls back
+/// to [`sync`].
+///
+/// Since the block may not be executed in a coroutine, `.wait()` must not be
+/// used inside it, or the whole worker thread may be blocked.
+#[cfg(feature = "std")]
+pub async fn block_in_place<'a, T: 'a>(func: impl FnOnce() -> T + Send + 'a) -> T {
+    #[cfg(feature = "tokio")]
+    if let Ok(handle) = tokio::runtime::Handle::try_current() {
+        if handle.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread {
+            return tokio::task::block_in_place(func);
+        }
+    }
+    sync(func).await
+}
+
+/// The context passed to the block of code in [`sync_try`], which can
+/// short-circuit the whole block with an error.
+#[cfg(feature = "std")]
+pub struct TryScope<E> {
+    error: spin::Mutex<Option<E>>,
+}
+
+/// The panic payload used by [`TryScope::bail`] for unwinding.
+#[cfg(feature = "std")]
+struct Bailed;
+
+#[cfg(feature = "std")]
+impl<E> TryScope<E> {
+    /// Stops the execution of the block of code immediately, and resolves the
+    /// future returned by [`sync_try`] with `Err(error)`.
+    ///
+    /// The stack of the coroutine is unwound, so the destructors of all the
+    /// local variables are executed.
+    pub fn bail(&self, error: E) -> ! {
+        *self.error.lock() = Some(error);
+        std::panic::resume_unwind(Box::new(Bailed))
+    }
+
+    /// Unwraps `result`, or [bails](TryScope::bail) with its error.
+    ///
+    /// This is the `?` operator working across nested function calls that
+    /// don't return a [`Result`].
+    pub fn check<U>(&self, result: Result<U, E>) -> U {
+        result.unwrap_or_else(|error| self.bail(error))
+    }
+}
+
+/// Turns a fallible block of sync code into a future, which can be
+/// short-circuited with the [`TryScope`] passed to it.
+///
+/// The scope can be passed down to deeply nested helpers, which may call
+/// [`TryScope::check`] instead of returning a [`Result`] all the way up.
+#[cfg(feature = "std")]
+pub fn sync_try<'a, T: 'a, E: 'a>(
+    func: impl FnOnce(&TryScope<E>) -> Result<T, E> + Send + 'a,
+) -> AsymBuilder<'a, Result<T, E>, impl FnOnce(AsymContext<'_>) -> Result<T, E>> {
+    sync(move || {
+        let scope = TryScope {
+            error: spin::Mutex::new(None),
+        };
+        match std::panic::catch_unwind(core::panic::AssertUnwindSafe(|| func(&scope))) {
+            Ok(output) => output,
+            // Payloads bailed by outer scopes are passed on.
+            Err(payload) => match (payload.is::<Bailed>(), scope.error.into_inner()) {
+                (true, Some(error)) => Err(error),
+                _ => std::panic::resume_unwind(payload),
+            },
+        }
+    })
+}
+
+/// Creates a scope for running multiple blocks of sync code concurrently.
+///
+/// `func` is called immediately to spawn the blocks with [`Scope::spawn`],
+/// each of which is executed in its own stackful coroutine. The returned future
+/// polls all of them on the current executor, and resolves to the return value
+/// of `func` when all of them complete. If any of them panics, the panic is
+/// propagated to the poller of the future.
+///
+/// Unlike spawning tasks in some specific runtime, this function is
+/// runtime-agnostic, and the blocks can borrow from the caller's environment.
+#[cfg(feature = "std")]
+pub fn scope<'a, R>(func: impl FnOnce(&mut Scope<'a>) -> R) -> Scoped<'a, R> {
+    scope_with_limit(NonZeroUsize::MAX, func)
+}
+
+/// Similar to [`scope`], but runs at most `limit` blocks concurrently.
+///
+/// The blocks spawned beyond the limit wait in a queue without creating their
+/// coroutines, and start once some running ones complete. This bounds the
+/// memory used by the stacks when spawning a large number of blocks.
+#[cfg(feature = "std")]
+pub fn scope_with_limit<'a, R>(
+    limit: NonZeroUsize,
+    func: impl FnOnce(&mut Scope<'a>) -> R,
+) -> Scoped<'a, R> {
+    let mut scope = Scope {
+        jobs: Vec::new(),
+        queue: VecDeque::new(),
+        limit: limit.get(),
+    };
+    let output = func(&mut scope);
+    Scoped {
+        scope,
+        output: Some(output),
+    }
+}
+
+/// The scope of sync blocks created by [`scope`].
+#[cfg(feature = "std")]
+pub struct Scope<'a> {
+    jobs: Vec<Asym<'a, ()>>,
+    queue: VecDeque<Box<dyn FnOnce() + Send + 'a>>,
+    limit: usize,
+}
+
+#[cfg(feature = "std")]
+impl<'a> Scope<'a> {
+    /// Spawns a block of sync code in this scope, on its own coroutine and
+    /// stack.
+    ///
+    /// If the scope is created with [`scope_with_limit`] and is full, the
+    /// block is queued until a running one completes.
+    pub fn spawn(&mut self, func: impl FnOnce() + Send + 'a) {
+        if self.jobs.len() < self.limit {
+            self.jobs.push(sync(func).into_future());
+        } else {
+            self.queue.push_back(Box::new(func));
+        }
+    }
+
+    /// Starts the queued blocks as long as the limit allows, returning whether
+    /// any block is started.
+    fn refill(&mut self) -> bool {
+        let free = self.limit - self.jobs.len();
+        let started = free.min(self.queue.len());
+        for func in self.queue.drain(..started) {
+            self.jobs.push(sync(func).into_future());
+        }
+        started > 0
+    }
+}
+
+/// The future returned by [`scope`].
+#[cfg(feature = "std")]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Scoped<'a, R> {
+    scope: Scope<'a>,
+    output: Option<R>,
+}
+
+#[cfg(feature = "std")]
+impl<R> Unpin for Scoped<'_, R> {}
+
+#[cfg(feature = "std")]
+impl<R> Future for Scoped<'_, R> {
+    type Output = R;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<R> {
+        let scope = &mut self.scope;
+        loop {
+            scope
+                .jobs
+                .retain_mut(|job| Pin::new(job).poll(cx).is_pending());
+            // The newly started blocks must be polled before returning.
+            if !scope.refill() {
+                break;
+            }
+        }
+        if self.scope.jobs.is_empty() {
+            let output = self.output.take();
+            Poll::Ready(output.expect("`Scoped` polled after completion"))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
 #[cfg(feature = "std")]
 std::thread_local! {
     static CX: core::cell::Cell<Option<AsymContext<'static>>>
@@ -169,6 +756,9 @@ struct SetCxGuard(Option<AsymContext<'static>>);
 
 #[cfg(feature = "std")]
 impl Drop for SetCxGuard {
+    // Usually dropped after the coroutine yields, which may have migrated it to
+    // another thread, so the address of `CX` must not be reused from before.
+    #[inline(never)]
     fn drop(&mut self) {
         CX.set(self.0.take());
     }
@@ -176,7 +766,7 @@ impl Drop for SetCxGuard {
 
 /// Borrowed from `futures_lite::future::block_on` with minor changes.
 #[cfg(feature = "std")]
-mod block_on {
+pub(crate) mod block_on {
     use core::{
         cell::RefCell,
         future::Future,
@@ -198,19 +788,33 @@ mod block_on {
             }
         }
 
+        fn new_waker() -> Waker {
+            let thread = thread::current();
+            Arc::new(WakerFn(move || thread.unpark())).into()
+        }
+
         thread_local! {
             // Cached waker for efficiency.
-            static CACHE: RefCell<Waker> = RefCell::new({
-                let thread = thread::current();
-                Arc::new(WakerFn(move || thread.unpark())).into()
-            });
+            static CACHE: RefCell<Waker> = RefCell::new(new_waker());
         }
 
-        // Grab the cached waker.
-        //
-        // This function won't be called nestedly, so we don't need to
-        // `try_borrow_mut`.
-        CACHE.with_borrow_mut(|waker| {
+        CACHE.with(|cache| {
+            // Grab the cached waker if it's available, or create a fresh one if
+            // this is a nested call, e.g. from a destructor waiting on some
+            // future while the coroutine polled by the outer call unwinds.
+            let tmp_cached;
+            let tmp_fresh;
+            let waker = match cache.try_borrow_mut() {
+                Ok(cache) => {
+                    tmp_cached = cache;
+                    &*tmp_cached
+                }
+                Err(_) => {
+                    tmp_fresh = new_waker();
+                    &tmp_fresh
+                }
+            };
+
             let cx = &mut Context::from_waker(waker);
             // Keep polling until the future is ready.
             loop {
@@ -222,3 +826,702 @@ mod block_on {
         })
     }
 }
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use alloc::vec::Vec;
+    use core::{
+        future::{pending, Future, IntoFuture},
+        num::NonZeroUsize,
+        pin::Pin,
+        sync::atomic::{AtomicU64, AtomicUsize, Ordering::Relaxed},
+        task::{Context, Poll},
+        time::Duration,
+    };
+
+    use futures_core::future::FusedFuture;
+    use spin::Mutex;
+
+    use super::{
+        block_on::block_on, in_coroutine, scope, scope_with_limit, sync, sync_named,
+        sync_try, sync_with, AsymWait, TimeSource, TimedOut, TryScope,
+    };
+    use crate::tests::{yield_now, DropCounter};
+
+    #[test]
+    fn scoped() {
+        let log = Mutex::new(Vec::new());
+        let ret = block_on(core::pin::pin!(scope(|s| {
+            for id in 0..3 {
+                let log = &log;
+                s.spawn(move || {
+                    for _ in 0..2 {
+                        log.lock().push(id);
+                        yield_now().wait();
+                    }
+                });
+            }
+            1024
+        })));
+        assert_eq!(ret, 1024);
+        assert_eq!(*log.lock(), [0, 1, 2, 0, 1, 2]);
+    }
+
+    #[test]
+    fn inside_coroutine() {
+        assert!(!in_coroutine());
+        let inside = block_on(core::pin::pin!(sync(|| {
+            let polled = async { in_coroutine() }.wait();
+            (in_coroutine(), polled)
+        })
+        .into_future()));
+        assert_eq!(inside, (true, false));
+        assert!(!in_coroutine());
+    }
+
+    #[test]
+    fn waited_outside_coroutine() {
+        use core::{future::poll_fn, sync::atomic::AtomicBool, task::Waker};
+        use std::thread;
+
+        // Blocks the current thread until woken instead of panicking.
+        assert!(!in_coroutine());
+        let done = AtomicBool::new(false);
+        let waker = Mutex::new(None::<Waker>);
+        thread::scope(|s| {
+            s.spawn(|| loop {
+                if let Some(waker) = waker.lock().take() {
+                    done.store(true, Relaxed);
+                    break waker.wake();
+                }
+                thread::yield_now();
+            });
+            poll_fn(|cx| {
+                if done.load(Relaxed) {
+                    return Poll::Ready(());
+                }
+                *waker.lock() = Some(cx.waker().clone());
+                Poll::Pending
+            })
+            .wait();
+        });
+        assert!(done.load(Relaxed));
+    }
+
+    #[test]
+    fn polled_after_completion() {
+        let mut asym = core::pin::pin!(sync(|| 42).into_future());
+        assert!(!asym.is_terminated());
+        assert_eq!(block_on(asym.as_mut()), 42);
+        assert!(asym.is_terminated());
+
+        let waker = crate::tests::noop_waker();
+        let polled = std::panic::catch_unwind(core::panic::AssertUnwindSafe(|| {
+            asym.as_mut().poll(&mut Context::from_waker(&waker))
+        }));
+        if cfg!(debug_assertions) {
+            assert!(polled.is_err());
+        } else {
+            assert!(polled.is_ok_and(|poll| poll.is_pending()));
+        }
+    }
+
+    #[test]
+    fn scoped_with_limit() {
+        let (running, max) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let log = Mutex::new(Vec::new());
+        let limit = NonZeroUsize::new(2).unwrap();
+        block_on(core::pin::pin!(scope_with_limit(limit, |s| {
+            for id in 0..5 {
+                let (running, max, log) = (&running, &max, &log);
+                s.spawn(move || {
+                    max.fetch_max(running.fetch_add(1, Relaxed) + 1, Relaxed);
+                    log.lock().push(id);
+                    yield_now().wait();
+                    running.fetch_sub(1, Relaxed);
+                });
+            }
+        })));
+        assert_eq!(max.into_inner(), 2);
+        assert_eq!(*log.lock(), [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn woken_externally() {
+        use alloc::sync::Arc;
+        use core::task::Waker;
+        use std::{thread, time::Duration};
+
+        /// A oneshot channel that is only woken by the sender.
+        #[derive(Default)]
+        struct Oneshot(Mutex<(Option<u32>, Option<Waker>)>);
+
+        impl Future for &Oneshot {
+            type Output = u32;
+
+            fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u32> {
+                let mut inner = self.0.lock();
+                match inner.0.take() {
+                    Some(value) => Poll::Ready(value),
+                    None => {
+                        inner.1 = Some(cx.waker().clone());
+                        Poll::Pending
+                    }
+                }
+            }
+        }
+
+        let channel = Arc::new(Oneshot::default());
+        let sender = channel.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            let mut inner = sender.0.lock();
+            inner.0 = Some(42);
+            inner.1.take().expect("the waker is not registered").wake();
+        });
+
+        let ret = block_on(core::pin::pin!(sync(|| (&*channel).wait()).into_future()));
+        assert_eq!(ret, 42);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn mapped_and_boxed() {
+        fn assert_send<T: Send>(t: T) -> T {
+            t
+        }
+
+        let data = [1, 2, 3];
+        let futures: Vec<_> = (0..3)
+            .map(|i| {
+                let data = &data;
+                assert_send(sync(move || data[i]).map(|x| x * 10).boxed())
+            })
+            .collect();
+        let outputs: Vec<_> = futures
+            .into_iter()
+            .map(|f| block_on(core::pin::pin!(f)))
+            .collect();
+        assert_eq!(outputs, [10, 20, 30]);
+    }
+
+    #[test]
+    fn bailed() {
+        fn nested(s: &TryScope<&'static str>, drops: &AtomicUsize, n: u32) -> u32 {
+            let _counter = DropCounter(drops);
+            yield_now().wait();
+            match n {
+                0..=2 => nested(s, drops, n + 1) + 1,
+                _ => s.check(Err("too deep")),
+            }
+        }
+
+        let drops = AtomicUsize::new(0);
+        let ret: Result<u32, _> = block_on(core::pin::pin!(sync_try(|s| Ok(nested(
+            s, &drops, 0
+        )))
+        .into_future()));
+        assert_eq!(ret, Err("too deep"));
+        assert_eq!(drops.load(Relaxed), 4);
+
+        let ret = block_on(core::pin::pin!(sync_try(|s| {
+            let inner: Result<(), ()> = sync_try(|_| s.bail(1)).into_future().wait();
+            Ok::<_, i32>(inner.is_ok())
+        })
+        .into_future()));
+        assert_eq!(ret, Err(1));
+    }
+
+    #[cfg(feature = "unstable-grow")]
+    #[test]
+    fn grown() {
+        use core::alloc::Layout;
+
+        use super::grow_stack;
+
+        fn recurse(depth: u32) -> u32 {
+            let buf = core::hint::black_box([depth as u8; 1024]);
+            match depth {
+                0 => buf[0].into(),
+                _ => recurse(depth - 1) + 1,
+            }
+        }
+
+        let layout = Layout::from_size_align(1 << 20, 4096).unwrap();
+        let ret = block_on(core::pin::pin!(sync(|| grow_stack(layout, || {
+            yield_now().wait();
+            recurse(256)
+        }))
+        .into_future()));
+        assert_eq!(ret, 256);
+    }
+
+    #[test]
+    fn named() {
+        let asym = sync_named("fatfs-job-3", || 42).into_future();
+        assert_eq!(asym.name(), Some("fatfs-job-3"));
+        assert!(std::format!("{asym:?}").contains("fatfs-job-3"));
+        assert_eq!(block_on(core::pin::pin!(asym)), 42);
+    }
+
+    #[test]
+    fn on_dyn_stack() {
+        use core::alloc::Layout;
+        use std::alloc::System;
+
+        use unico_stack::StackAllocator;
+
+        let alloc: &dyn StackAllocator = &System;
+        let layout = Layout::from_size_align(1 << 20, 4096).unwrap();
+        let asym = sync(|| async { 42 }.wait()).into_future_on((alloc, layout));
+        assert_eq!(block_on(core::pin::pin!(asym)), 42);
+    }
+
+    #[test]
+    fn stacks_reclaimed() {
+        use core::{
+            alloc::{AllocError, Allocator, Layout},
+            ptr::NonNull,
+        };
+        use std::{alloc::System, panic};
+
+        use unico_ful::sym::Co;
+        use unico_stack::{Stack, StackAllocator, StackDomain, StackPool};
+
+        use super::CoPool;
+
+        static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+        /// Counts the stacks allocated and not deallocated yet.
+        struct Tracking;
+
+        unsafe fn release(pointer: NonNull<u8>, layout: Layout) {
+            LIVE.fetch_sub(1, Relaxed);
+            unsafe { System.deallocate(pointer, layout) };
+        }
+
+        unsafe impl StackAllocator for Tracking {
+            fn allocate(&self, layout: Layout) -> Result<Stack, AllocError> {
+                let memory = Allocator::allocate(&System, layout)?;
+                LIVE.fetch_add(1, Relaxed);
+                Ok(unsafe { Stack::new(memory.cast(), layout, release) })
+            }
+        }
+
+        let contexts = crate::tests::live_contexts();
+        let layout = Layout::from_size_align(1 << 16, 4096).unwrap();
+        for _ in 0..64 {
+            let stack = || (&Tracking, layout);
+            let done = sync(|| yield_now().wait()).into_future_on(stack());
+            block_on(core::pin::pin!(done));
+            drop(sync(|| ()).into_future_on(stack()));
+            let mut suspended = sync(|| yield_now().wait()).into_future_on(stack());
+            let waker = crate::tests::noop_waker();
+            let poll = Pin::new(&mut suspended).poll(&mut Context::from_waker(&waker));
+            assert!(poll.is_pending());
+            drop(suspended);
+            let panicked = sync(|| panic!("reclaimed")).into_future_on(stack());
+            let caught = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                block_on(core::pin::pin!(panicked))
+            }));
+            assert!(caught.is_err());
+            let unstarted = Co::builder().on(stack()).spawn(Option::unwrap);
+            drop(unstarted.unwrap());
+        }
+        assert_eq!(LIVE.load(Relaxed), 0);
+        assert_eq!(crate::tests::live_contexts(), contexts);
+
+        // The pooled coroutines are reused, and released once they panic or the
+        // pool is dropped.
+        let pool = CoPool::new(2);
+        let run = || block_on(core::pin::pin!(pool.run(|| yield_now().wait())));
+        (0..64).for_each(|_| run());
+        assert_eq!(crate::tests::live_contexts(), contexts + 1);
+        let panicked = pool.run(|| panic!("not reused"));
+        let caught = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            block_on(core::pin::pin!(panicked))
+        }));
+        assert!(caught.is_err());
+        assert_eq!(crate::tests::live_contexts(), contexts);
+        run();
+        drop(pool);
+        assert_eq!(crate::tests::live_contexts(), contexts);
+
+        static POOL: StackPool<Tracking> = StackPool::new(Tracking, unsafe {
+            Layout::from_size_align_unchecked(1 << 16, 4096)
+        });
+        static DOMAIN: StackDomain<&StackPool<Tracking>> = StackDomain::pooled(&POOL);
+        for _ in 0..64 {
+            let asym = sync(|| yield_now().wait()).into_future_on(&DOMAIN);
+            block_on(core::pin::pin!(asym));
+        }
+        assert_eq!((LIVE.load(Relaxed), POOL.cached()), (1, 1));
+    }
+
+    #[test]
+    fn timed_out() {
+        /// Ticks once on every query.
+        struct Counter(AtomicU64);
+
+        impl TimeSource for Counter {
+            fn now(&self) -> u64 {
+                self.0.fetch_add(1, Relaxed)
+            }
+        }
+
+        let counter = Counter(AtomicU64::new(0));
+        let ret = block_on(core::pin::pin!(sync_with(|mut cx| {
+            let ready = yield_now().wait_timeout_with(&mut cx, &counter, 5);
+            let pending = pending::<()>().wait_timeout_with(&mut cx, &counter, 5);
+            (ready, pending)
+        })
+        .into_future()));
+        assert_eq!(ret, (Ok(()), Err(TimedOut)));
+        assert_eq!(counter.0.load(Relaxed), 8);
+
+        let ret = block_on(core::pin::pin!(sync(|| {
+            pending::<()>().wait_timeout(Duration::from_millis(10))
+        })
+        .into_future()));
+        assert_eq!(ret, Err(TimedOut));
+
+        // The task is woken at the deadline instead of spinning meanwhile.
+        let mut polls = 0;
+        let counted = core::future::poll_fn(|_| {
+            polls += 1;
+            Poll::<()>::Pending
+        });
+        let ticks = Duration::from_millis(20).as_nanos() as u64;
+        let ret = block_on(core::pin::pin!(super::timeout(
+            counted,
+            std::time::Instant::now(),
+            ticks
+        )));
+        assert_eq!(ret, Err(TimedOut));
+        assert!(polls < 10, "polled {polls} times");
+    }
+
+    #[test]
+    fn timer_cancelled() {
+        use alloc::{boxed::Box, sync::Arc, task::Wake};
+        use core::task::Waker;
+        use std::{thread, time::Instant};
+
+        struct Woken(AtomicUsize);
+
+        impl Wake for Woken {
+            fn wake(self: Arc<Self>) {
+                self.0.fetch_add(1, Relaxed);
+            }
+        }
+
+        let woken = Arc::new(Woken(AtomicUsize::new(0)));
+        let waker = Waker::from(woken.clone());
+        let ticks = Duration::from_millis(20).as_nanos() as u64;
+        let mut timeout =
+            Box::pin(super::timeout(pending::<()>(), Instant::now(), ticks));
+        let poll = timeout.as_mut().poll(&mut Context::from_waker(&waker));
+        assert!(poll.is_pending());
+        assert!(Arc::strong_count(&woken) > 2);
+
+        // Dropped before the deadline, the timer releases the waker at once.
+        drop(timeout);
+        assert_eq!(Arc::strong_count(&woken), 2);
+        thread::sleep(Duration::from_millis(40));
+        assert_eq!(woken.0.load(Relaxed), 0);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn blocking_in_place() {
+        use tokio::runtime::Builder;
+
+        use super::block_in_place;
+
+        let multi = Builder::new_multi_thread()
+            .worker_threads(2)
+            .build()
+            .unwrap();
+        assert_eq!(multi.block_on(async { block_in_place(|| 1).await }), 1);
+
+        let current = Builder::new_current_thread().build().unwrap();
+        let ret = current.block_on(async {
+            block_in_place(|| {
+                yield_now().wait();
+                2
+            })
+            .await
+        });
+        assert_eq!(ret, 2);
+    }
+
+    #[test]
+    fn context_saved() {
+        use alloc::boxed::Box;
+        use core::{any::Any, cell::Cell, future::poll_fn};
+
+        use super::{register_context_saver, ContextSaver};
+
+        std::thread_local! {
+            static CURRENT: Cell<u32> = const { Cell::new(0) };
+        }
+
+        struct Current;
+
+        impl ContextSaver for Current {
+            fn save(&self) -> Box<dyn Any + Send> {
+                Box::new(CURRENT.get())
+            }
+
+            fn restore(&self, saved: Box<dyn Any + Send>) {
+                CURRENT.set(*saved.downcast().unwrap());
+            }
+        }
+
+        register_context_saver(&Current);
+        block_on(core::pin::pin!(async {
+            CURRENT.set(1);
+            let mut future = core::pin::pin!(sync(|| {
+                assert_eq!(CURRENT.get(), 1);
+                CURRENT.set(2);
+                yield_now().wait();
+                CURRENT.get()
+            })
+            .into_future());
+
+            let ret = poll_fn(|cx| Poll::Ready(future.as_mut().poll(cx))).await;
+            assert!(ret.is_pending());
+            assert_eq!(CURRENT.get(), 1);
+
+            // Another task changes the context meanwhile.
+            CURRENT.set(3);
+            let ret = poll_fn(|cx| Poll::Ready(future.as_mut().poll(cx))).await;
+            assert_eq!(ret, Poll::Ready(2));
+            assert_eq!(CURRENT.get(), 3);
+        }));
+
+        // The context of the task is restored even if the block panics.
+        let panicked = sync(|| {
+            CURRENT.set(4);
+            panic!("restored")
+        });
+        let caught = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            block_on(core::pin::pin!(panicked.into_future()))
+        }));
+        assert!(caught.is_err());
+        assert_eq!(CURRENT.get(), 3);
+    }
+
+    #[test]
+    fn pool() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        use super::CoPool;
+
+        let pool = CoPool::new(1);
+        let mut count = 0;
+        for i in 0..4 {
+            let ret = block_on(core::pin::pin!(pool.run(|| {
+                yield_now().wait();
+                count += 1;
+                i * 2
+            })));
+            assert_eq!(ret, i * 2);
+            assert_eq!(pool.idle(), 1);
+        }
+        assert_eq!(count, 4);
+
+        let ret = catch_unwind(AssertUnwindSafe(|| {
+            block_on(core::pin::pin!(pool.run(|| panic!("not reused"))))
+        }));
+        assert!(ret.is_err());
+        assert_eq!(pool.idle(), 0);
+    }
+
+    #[test]
+    fn wait_in_drop() {
+        use core::future::poll_fn;
+        use std::{
+            panic::{catch_unwind, AssertUnwindSafe},
+            thread,
+        };
+
+        use smol::channel::{bounded, Receiver};
+
+        struct Guard<'a>(Receiver<u32>, &'a AtomicU64);
+
+        impl Drop for Guard<'_> {
+            fn drop(&mut self) {
+                let value = self.0.recv().wait().unwrap();
+                self.1.store(value.into(), Relaxed);
+            }
+        }
+
+        // The guard suspends the completing coroutine.
+        let got = AtomicU64::new(0);
+        let (tx, rx) = bounded(1);
+        block_on(core::pin::pin!(async {
+            let mut future =
+                core::pin::pin!(sync(|| drop(Guard(rx, &got))).into_future());
+            let ret = poll_fn(|cx| Poll::Ready(future.as_mut().poll(cx))).await;
+            assert!(ret.is_pending());
+            tx.send(1).await.unwrap();
+            future.await;
+        }));
+        assert_eq!(got.load(Relaxed), 1);
+
+        // The guard blocks the thread while the coroutine is dropped.
+        let (tx, rx) = bounded(1);
+        block_on(core::pin::pin!(async {
+            let mut future = alloc::boxed::Box::pin(
+                sync(|| {
+                    let _guard = Guard(rx, &got);
+                    yield_now().wait();
+                    unreachable!()
+                })
+                .into_future(),
+            );
+            let ret = poll_fn(|cx| Poll::Ready(future.as_mut().poll(cx))).await;
+            assert!(ret.is_pending());
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                tx.send_blocking(2).unwrap();
+            });
+            drop(future);
+        }));
+        assert_eq!(got.load(Relaxed), 2);
+
+        // The guard blocks the thread while the coroutine panics.
+        let (tx, rx) = bounded(1);
+        tx.send_blocking(3).unwrap();
+        let ret = catch_unwind(AssertUnwindSafe(|| {
+            block_on(core::pin::pin!(sync(|| {
+                let _guard = Guard(rx, &got);
+                panic!("waiting while unwinding")
+            })
+            .into_future()))
+        }));
+        assert!(ret.is_err());
+        assert_eq!(got.load(Relaxed), 3);
+    }
+
+    #[test]
+    fn blocking_stream() {
+        use smol::stream::StreamExt;
+
+        use super::stream_from_blocking;
+
+        let stream = stream_from_blocking(|e| {
+            for i in 0..3 {
+                yield_now().wait();
+                e.emit(i);
+            }
+        });
+        let items = block_on(core::pin::pin!(stream.collect::<Vec<_>>()));
+        assert_eq!(items, [0, 1, 2]);
+    }
+
+    #[test]
+    fn smol_timer() {
+        use smol::{Executor, Timer};
+
+        let timer = || Timer::after(Duration::from_millis(10));
+        let ret = smol::block_on(async { sync(|| timer().wait()).await });
+        assert!(ret.elapsed() < Duration::from_secs(1));
+
+        let executor = Executor::new();
+        let task = executor.spawn(
+            sync(|| {
+                timer().wait();
+                timer().wait();
+                1
+            })
+            .into_future(),
+        );
+        assert_eq!(smol::block_on(executor.run(task)), 1);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn migrated() {
+        use tokio::{runtime::Builder, task::yield_now};
+
+        use super::sync_migratable;
+
+        let multi = Builder::new_multi_thread()
+            .worker_threads(4)
+            .build()
+            .unwrap();
+        let sum = multi.block_on(async {
+            let tasks = (0..16).map(|n| {
+                tokio::spawn(
+                    // The workers may steal the task between every yield.
+                    sync_migratable(move || {
+                        (0..64).for_each(|_| yield_now().wait());
+                        n
+                    })
+                    .into_future(),
+                )
+            });
+            let mut sum = 0;
+            for task in tasks.collect::<Vec<_>>() {
+                sum += task.await.unwrap();
+            }
+            sum
+        });
+        assert_eq!(sum, (0..16).sum());
+    }
+
+    #[test]
+    fn migrated_to_another_thread() {
+        use alloc::boxed::Box;
+        use std::thread;
+
+        use super::sync_migratable;
+
+        let mut asym = Box::pin(
+            sync_migratable(|| {
+                let before = thread::current().id();
+                yield_now().wait();
+                // The context is still found by the code after the wait.
+                assert!(in_coroutine());
+                yield_now().wait();
+                (before, thread::current().id())
+            })
+            .into_future(),
+        );
+        let waker = crate::tests::noop_waker();
+        assert!(asym
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_pending());
+        assert!(!in_coroutine());
+
+        let (before, after) = thread::spawn(move || {
+            let waker = crate::tests::noop_waker();
+            let output = loop {
+                if let Poll::Ready(output) =
+                    asym.as_mut().poll(&mut Context::from_waker(&waker))
+                {
+                    break output;
+                }
+            };
+            // Nothing is left behind on the thread resuming it.
+            assert!(!in_coroutine());
+            output
+        })
+        .join()
+        .unwrap();
+        assert_ne!(before, after);
+        assert!(!in_coroutine());
+    }
+
+    #[test]
+    #[should_panic = "What the fuck?"]
+    fn scoped_panicked() {
+        block_on(core::pin::pin!(scope(|s| {
+            s.spawn(|| yield_now().wait());
+            s.spawn(|| panic!("What the fuck?"));
+        })));
+    }
+}
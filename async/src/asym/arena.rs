@@ -0,0 +1,157 @@
+use core::{
+    alloc::{AllocError, Allocator, Layout},
+    cell::Cell,
+    fmt,
+    ptr::NonNull,
+};
+
+use unico_stack::{IntoStack, Stack};
+
+/// The alignment of the region of an [`Arena`], which is page-aligned like the
+/// stacks.
+const ARENA_ALIGN: usize = 4096;
+
+/// A bump allocator over a region of scratch memory, created by [`with_arena`].
+///
+/// Allocations are made by bumping an offset, and the memory is reclaimed all
+/// at once when the arena goes out of scope. Only the last allocation gives
+/// its memory back to the arena when deallocated, and growing it is done in
+/// place.
+pub struct Arena {
+    region: Stack,
+    used: Cell<usize>,
+}
+
+impl Arena {
+    /// The number of bytes of the region.
+    pub fn capacity(&self) -> usize {
+        self.region.layout().size()
+    }
+
+    /// The number of bytes allocated, including the padding in between.
+    pub fn used(&self) -> usize {
+        self.used.get()
+    }
+
+    /// The offset of `ptr` in the region.
+    fn offset(&self, ptr: NonNull<u8>) -> usize {
+        ptr.as_ptr() as usize - self.region.base().as_ptr() as usize
+    }
+}
+
+impl fmt::Debug for Arena {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Arena")
+            .field("capacity", &self.capacity())
+            .field("used", &self.used())
+            .finish()
+    }
+}
+
+// SAFETY: The blocks never overlap, since the offset only goes back when the
+// last block is deallocated or shrunk, and they stay valid until the region
+// is dropped along with the arena, which outlives the references to it.
+unsafe impl Allocator for &Arena {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let base = self.region.base();
+        let start = (base.as_ptr() as usize + self.used.get())
+            .checked_next_multiple_of(layout.align())
+            .ok_or(AllocError)?;
+        let offset = start - base.as_ptr() as usize;
+        let end = (offset.checked_add(layout.size()))
+            .filter(|end| *end <= self.capacity())
+            .ok_or(AllocError)?;
+
+        self.used.set(end);
+        // SAFETY: The block lies in the region by the check above.
+        let ptr = unsafe { base.add(offset) };
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let offset = self.offset(ptr);
+        if offset + layout.size() == self.used.get() {
+            self.used.set(offset);
+        }
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let offset = self.offset(ptr);
+        let last = offset + old_layout.size() == self.used.get();
+        let aligned = ptr.align_offset(new_layout.align()) == 0;
+        if last && aligned && offset + new_layout.size() <= self.capacity() {
+            self.used.set(offset + new_layout.size());
+            return Ok(NonNull::slice_from_raw_parts(ptr, new_layout.size()));
+        }
+
+        let new = self.allocate(new_layout)?;
+        // SAFETY: The old block is valid by contract, and the new one is just
+        // allocated after it, so they never overlap.
+        unsafe {
+            ptr.copy_to_nonoverlapping(new.cast(), old_layout.size());
+        }
+        Ok(new)
+    }
+}
+
+/// Runs `func` with an [`Arena`] over a region of `size` bytes of scratch
+/// memory, which is reclaimed as a whole when `func` returns.
+///
+/// A few bytes of the region may be kept by the stack allocator for its own
+/// bookkeeping, see [`Arena::capacity`] for the actual size.
+///
+/// The region is drawn from the global stack allocator like the stack of a
+/// coroutine, so a pooling stack allocator hands the regions over from one
+/// block of code to the next without touching the heap. This is useful for
+/// the temporary buffers of a hot block of code run by [`sync`](super::sync).
+///
+/// # Panics
+///
+/// Panics if the region cannot be allocated.
+#[track_caller]
+pub fn with_arena<T>(size: usize, func: impl FnOnce(&Arena) -> T) -> T {
+    let region = Layout::from_size_align(size, ARENA_ALIGN)
+        .ok()
+        .and_then(|layout| layout.into_stack().ok());
+    let Some(region) = region else {
+        panic!("failed to allocate an arena of {size} bytes");
+    };
+    func(&Arena {
+        region,
+        used: Cell::new(0),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, vec::Vec};
+
+    use super::with_arena;
+
+    #[test]
+    fn bump() {
+        with_arena(8192, |arena| {
+            let a = Box::new_in(1u64, arena);
+            let b = Box::new_in(2u64, arena);
+            assert_eq!(arena.used(), 16);
+            drop(a);
+            assert_eq!(arena.used(), 16);
+            drop(b);
+            assert_eq!(arena.used(), 8);
+
+            // The last allocation grows in place.
+            let mut buf = Vec::with_capacity_in(1, arena);
+            let base = buf.as_ptr();
+            buf.extend((0..1024).map(|i| i as u8));
+            assert_eq!(buf.as_ptr(), base);
+            assert_eq!(arena.used(), 8 + buf.capacity());
+
+            assert!(Vec::<u8, _>::try_with_capacity_in(8192, arena).is_err());
+        });
+    }
+}
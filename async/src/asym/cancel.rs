@@ -0,0 +1,192 @@
+use alloc::{collections::BTreeMap, sync::Arc};
+use core::{
+    fmt,
+    future::{Future, IntoFuture},
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll, Waker},
+};
+
+use spin::Mutex;
+
+#[derive(Default)]
+struct Waiters {
+    next: u64,
+    wakers: BTreeMap<u64, Waker>,
+}
+
+#[derive(Default)]
+struct State {
+    cancelled: AtomicBool,
+    waiters: Mutex<Waiters>,
+}
+
+/// A token shared by the futures to be cancelled together from anywhere.
+///
+/// The clones of a token refer to the same state, so cancelling any of them
+/// cancels all the futures wrapped by [`cancellable`] with it.
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<State>);
+
+impl CancellationToken {
+    /// Creates a token not cancelled yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the token, waking all the futures waiting on it.
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::Release);
+        let wakers = core::mem::take(&mut self.0.waiters.lock().wakers);
+        wakers.into_values().for_each(Waker::wake);
+    }
+
+    /// Whether the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::Acquire)
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// The error returned when a future is cancelled before it completes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the future was cancelled")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Cancelled {}
+
+/// A future that fails with [`Cancelled`] once its [`CancellationToken`] is
+/// cancelled before the inner one completes.
+///
+/// This structure is created by [`cancellable`].
+#[derive(Debug)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Cancellable<F> {
+    future: F,
+    token: CancellationToken,
+    key: Option<u64>,
+}
+
+/// Races `future` against the cancellation of `token`.
+///
+/// Unlike [`timeout`](super::timeout), the returned future is woken by the
+/// token when it's cancelled, so no busy loop is involved. The inner future is
+/// dropped along with the returned one, so waiting on it with
+/// [`AsymWait::wait_cancellable`](super::AsymWait::wait_cancellable) releases
+/// its resources as soon as it's cancelled.
+pub fn cancellable<F: IntoFuture>(
+    future: F,
+    token: &CancellationToken,
+) -> Cancellable<F::IntoFuture> {
+    Cancellable {
+        future: future.into_future(),
+        token: token.clone(),
+        key: None,
+    }
+}
+
+impl<F: Future> Future for Cancellable<F> {
+    type Output = Result<F::Output, Cancelled>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: `future` is structurally pinned, and never moved out.
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.token.is_cancelled() {
+            return Poll::Ready(Err(Cancelled));
+        }
+        // SAFETY: See above.
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        if let Poll::Ready(output) = future.poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+
+        let mut waiters = this.token.0.waiters.lock();
+        // Checked again under the lock, since the token may be cancelled after
+        // the check above without waking this future.
+        if this.token.is_cancelled() {
+            return Poll::Ready(Err(Cancelled));
+        }
+        let key = *this.key.get_or_insert_with(|| {
+            waiters.next += 1;
+            waiters.next
+        });
+        waiters.wakers.insert(key, cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<F> Drop for Cancellable<F> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            self.token.0.waiters.lock().wakers.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        future::{pending, IntoFuture},
+        pin::pin,
+        sync::atomic::{AtomicUsize, Ordering::Relaxed},
+    };
+
+    use super::{cancellable, CancellationToken, Cancelled};
+    use crate::{
+        asym::{block_on::block_on, sync, AsymWait},
+        tests::DropCounter,
+    };
+
+    #[test]
+    fn cancelled_from_outside() {
+        let token = CancellationToken::new();
+        let drops = &AtomicUsize::new(0);
+        let counter = DropCounter(drops);
+        let waiting = token.clone();
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(core::time::Duration::from_millis(10));
+            token.cancel();
+        });
+
+        let ret = block_on(pin!(sync(move || {
+            let future = async move {
+                let _counter = counter;
+                pending::<()>().await
+            };
+            let ret = future.wait_cancellable(&waiting);
+            // The inner future is dropped before returning.
+            assert_eq!(drops.load(Relaxed), 1);
+            ret
+        })
+        .into_future()));
+        canceller.join().unwrap();
+        assert_eq!(ret, Err(Cancelled));
+        assert_eq!(drops.load(Relaxed), 1);
+    }
+
+    #[test]
+    fn completed() {
+        let token = CancellationToken::new();
+        let ret = block_on(pin!(cancellable(async { 42 }, &token)));
+        assert_eq!(ret, Ok(42));
+        assert!(token.0.waiters.lock().wakers.is_empty());
+
+        token.cancel();
+        assert!(token.is_cancelled());
+        let ret = block_on(pin!(cancellable(async { 42 }, &token)));
+        assert_eq!(ret, Err(Cancelled));
+    }
+}
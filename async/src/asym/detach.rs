@@ -0,0 +1,121 @@
+use alloc::boxed::Box;
+use core::{future::Future, pin::Pin};
+
+use spin::RwLock;
+
+/// A future detached by [`detach`].
+pub type Detached = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// The spawn capability of an executor, which runs the futures detached by
+/// [`detach`] independently of the current task.
+///
+/// Coroutines know nothing about the executor polling them, so the spawner
+/// must be registered with [`set_spawner`] before anything is detached.
+pub trait Spawner: Sync {
+    /// Spawns `future` onto the executor, to be run to completion without
+    /// anyone awaiting it.
+    fn spawn(&self, future: Detached);
+}
+
+/// Spawns the detached futures onto the current `tokio` runtime.
+///
+/// Detaching outside of any runtime panics, as [`tokio::spawn`] does.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioSpawner;
+
+#[cfg(feature = "tokio")]
+impl Spawner for TokioSpawner {
+    fn spawn(&self, future: Detached) {
+        drop(tokio::spawn(future));
+    }
+}
+
+static SPAWNER: RwLock<Option<&'static dyn Spawner>> = RwLock::new(None);
+
+/// Sets the [`Spawner`] used by [`detach`], replacing the previous one.
+pub fn set_spawner(spawner: &'static dyn Spawner) {
+    *SPAWNER.write() = Some(spawner);
+}
+
+/// Schedules `future` on the executor to run independently, without awaiting
+/// it.
+///
+/// Unlike [`AsymWait::wait`](super::AsymWait::wait), the current coroutine is
+/// not suspended, and continues right away, e.g. with its main loop while a
+/// buffer is flushed in the background. The output and the panics of `future`
+/// are handled by the executor, like any other task spawned onto it.
+///
+/// # Panics
+///
+/// Panics if no spawner has been set by [`set_spawner`].
+///
+/// ```rust
+/// # #![feature(allocator_api)]
+/// # unico_stack::global_stack_allocator!(std::alloc::Global);
+/// # unico_context::global_resumer!(unico_context::boost::Boost);
+/// use unico_async::asym::{detach, set_spawner, Detached, Spawner};
+///
+/// /// Runs the detached futures right away, for demonstration only.
+/// struct Inline;
+///
+/// impl Spawner for Inline {
+///     fn spawn(&self, future: Detached) {
+///         unico_async::test::block_on(future);
+///     }
+/// }
+///
+/// set_spawner(&Inline);
+/// detach(async { println!("flushed in the background") });
+/// ```
+#[track_caller]
+pub fn detach(future: impl Future<Output = ()> + Send + 'static) {
+    let spawner = (*SPAWNER.read()).expect("no spawner to detach the future onto");
+    spawner.spawn(Box::pin(future));
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use core::{future::IntoFuture, pin::pin};
+    use std::sync::{
+        atomic::{AtomicU32, Ordering::Relaxed},
+        Mutex,
+    };
+
+    use super::{detach, set_spawner, Detached, Spawner};
+    use crate::asym::{block_on::block_on, sync};
+
+    /// Queues the detached futures, to be run by the test afterwards.
+    struct Queue(Mutex<Vec<Detached>>);
+
+    impl Spawner for Queue {
+        fn spawn(&self, future: Detached) {
+            self.0.lock().unwrap().push(future);
+        }
+    }
+
+    #[test]
+    fn detached() {
+        static QUEUE: Queue = Queue(Mutex::new(Vec::new()));
+        static FLUSHED: AtomicU32 = AtomicU32::new(0);
+
+        set_spawner(&QUEUE);
+        let ret = block_on(pin!(sync(|| {
+            detach(async {
+                FLUSHED.fetch_add(1, Relaxed);
+            });
+            // The coroutine continues without awaiting the future.
+            FLUSHED.load(Relaxed)
+        })
+        .into_future()));
+        assert_eq!(ret, 0);
+
+        let detached = core::mem::take(&mut *QUEUE.0.lock().unwrap());
+        assert_eq!(detached.len(), 1);
+        detached
+            .into_iter()
+            .for_each(|future| block_on(pin!(future)));
+        assert_eq!(FLUSHED.load(Relaxed), 1);
+    }
+}
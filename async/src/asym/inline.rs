@@ -0,0 +1,182 @@
+//! Blocks of sync code run inline on the stack of the task, which escalate to
+//! a stackful coroutine only when they have to suspend.
+
+use alloc::boxed::Box;
+use core::{
+    cell::Cell,
+    future::{Future, IntoFuture},
+    pin::Pin,
+    ptr::NonNull,
+    task::{Context, Poll, Waker},
+};
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+
+use super::{sync, Asym};
+
+std::thread_local! {
+    /// The waker of the task polling the inline block running on this thread,
+    /// if any.
+    static INLINE: Cell<Option<NonNull<Waker>>> = const { Cell::new(None) };
+}
+
+/// The payload unwinding an inline block which has to suspend.
+struct Escalate;
+
+/// Polls `future` once for [`AsymWait::wait`](super::AsymWait::wait) in an
+/// inline block, if any is running, and unwinds the block to escalate it if
+/// the future is pending.
+pub(super) fn poll_inline<F: Future>(future: Pin<&mut F>) -> Option<F::Output> {
+    let waker = INLINE.get()?;
+    // SAFETY: The waker is borrowed by `SyncInline::poll` until the block
+    // returns or unwinds.
+    let cx = &mut Context::from_waker(unsafe { waker.as_ref() });
+    match future.poll(cx) {
+        Poll::Ready(output) => Some(output),
+        Poll::Pending => resume_unwind(Box::new(Escalate)),
+    }
+}
+
+/// Turns a block of sync code into a future, which runs the block inline on
+/// the stack of the task instead of a coroutine, until it has to suspend.
+///
+/// The block is called right in the first poll. Every [`AsymWait::wait`] in it
+/// polls the future once, and continues inline if it's ready, so the blocks
+/// which rarely block cost nearly nothing more than a function call. Once a
+/// future is pending, the block is unwound, since its frames cannot be moved
+/// off the current stack, and called again from the start in a coroutine like
+/// [`sync`].
+///
+/// So the block may run twice, and everything it does before the first
+/// pending future must be safe to redo, e.g. reading some state, but not
+/// consuming a channel. The pending future is dropped by the unwinding too,
+/// along with anything it has consumed, and created afresh by the second run.
+/// The unwinding must not be caught by the block either.
+///
+/// The destructors run by the unwinding see [`std::thread::panicking`] return
+/// `true`, so a [`Mutex`](std::sync::Mutex) guard held across the pending wait
+/// poisons its mutex, which the second run finds poisoned, and a wait in a
+/// destructor blocks the thread instead of suspending. While running inline,
+/// [`in_coroutine`](super::in_coroutine) returns `false`.
+///
+/// [`AsymWait::wait`]: super::AsymWait::wait
+///
+/// ```rust
+/// # #![feature(allocator_api)]
+/// # unico_stack::global_stack_allocator!(std::alloc::Global);
+/// # unico_context::global_resumer!(unico_context::boost::Boost);
+/// use unico_async::{
+///     asym::{in_coroutine, sync_inline, AsymWait},
+///     test,
+/// };
+///
+/// let inline = test::block_on(sync_inline(|| {
+///     async { 42 }.wait();
+///     in_coroutine()
+/// }));
+/// assert!(!inline);
+/// ```
+pub fn sync_inline<'a, T, F>(func: F) -> SyncInline<'a, T, F>
+where
+    T: 'a,
+    F: Fn() -> T + Send + 'a,
+{
+    SyncInline(State::Inline(Some(func)))
+}
+
+/// The future returned by [`sync_inline`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct SyncInline<'a, T, F>(State<'a, T, F>);
+
+enum State<'a, T, F> {
+    Inline(Option<F>),
+    Coroutine(Asym<'a, T>),
+}
+
+// The block is never pinned.
+impl<T, F> Unpin for SyncInline<'_, T, F> {}
+
+impl<'a, T, F> Future for SyncInline<'a, T, F>
+where
+    T: 'a,
+    F: Fn() -> T + Send + 'a,
+{
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        if let State::Inline(func) = &mut self.0 {
+            let func = func.take().expect("`SyncInline` polled after completion");
+            let outer = INLINE.replace(Some(NonNull::from(cx.waker())));
+            let result = catch_unwind(AssertUnwindSafe(&func));
+            INLINE.set(outer);
+            match result {
+                Ok(output) => return Poll::Ready(output),
+                Err(payload) if payload.is::<Escalate>() => {}
+                Err(payload) => resume_unwind(payload),
+            }
+            self.0 = State::Coroutine(sync(func).into_future());
+        }
+        let State::Coroutine(asym) = &mut self.0 else {
+            unreachable!("the inline block is escalated above");
+        };
+        Pin::new(asym).poll(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        pin::pin,
+        sync::atomic::{AtomicUsize, Ordering::Relaxed},
+    };
+    use std::sync::{Mutex, PoisonError};
+
+    use super::sync_inline;
+    use crate::{
+        asym::{block_on::block_on, in_coroutine, AsymWait},
+        tests::yield_now,
+    };
+
+    #[test]
+    fn inline() {
+        let runs = AtomicUsize::new(0);
+        let inside = block_on(pin!(sync_inline(|| {
+            runs.fetch_add(1, Relaxed);
+            async { 42 }.wait();
+            in_coroutine()
+        })));
+        assert!(!inside);
+        assert_eq!(runs.load(Relaxed), 1);
+    }
+
+    #[test]
+    fn escalated() {
+        let runs = AtomicUsize::new(0);
+        let inside = block_on(pin!(sync_inline(|| {
+            runs.fetch_add(1, Relaxed);
+            yield_now().wait();
+            in_coroutine()
+        })));
+        assert!(inside);
+        assert_eq!(runs.load(Relaxed), 2);
+    }
+
+    #[test]
+    fn poisoned() {
+        let lock = Mutex::new(0);
+        let ret = block_on(pin!(sync_inline(|| {
+            let mut guard = lock.lock().unwrap_or_else(PoisonError::into_inner);
+            *guard += 1;
+            yield_now().wait();
+            *guard
+        })));
+        // The guard is dropped by the escalation as if the block panicked.
+        assert!(lock.is_poisoned());
+        assert_eq!(ret, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "boom")]
+    fn panicked() {
+        block_on(pin!(sync_inline(|| panic!("boom"))));
+    }
+}
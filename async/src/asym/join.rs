@@ -0,0 +1,219 @@
+use alloc::{collections::VecDeque, vec::Vec};
+use core::{
+    future::{Future, IntoFuture},
+    pin::Pin,
+    task::{Context, Poll},
+};
+use std::{
+    panic::{catch_unwind, AssertUnwindSafe},
+    thread::Result as ThreadResult,
+};
+
+use futures_core::Stream;
+
+use super::{sync_with, Asym, AsymBuilder, AsymContext};
+
+/// Runs the blocks of sync code concurrently, and yields their outputs as
+/// each of them completes, like a `FuturesUnordered` of them.
+///
+/// Every block is executed in its own stackful coroutine, all of which are
+/// polled on the current task, so no runtime is needed to spawn them. A panic
+/// in a block is caught and yielded as the `Err` of its output, leaving the
+/// other blocks running.
+#[track_caller]
+pub fn select_completed<'a, T, F, I>(blocks: I) -> Completed<'a, T>
+where
+    I: IntoIterator<Item = AsymBuilder<'a, T, F>>,
+    F: FnOnce(AsymContext<'_>) -> T + Send + 'a,
+    T: 'a,
+{
+    let jobs = (blocks.into_iter().enumerate())
+        .map(|(index, block)| {
+            let func = block.func;
+            let caught = move |cx: AsymContext<'_>| {
+                catch_unwind(AssertUnwindSafe(move || func(cx)))
+            };
+            let block = AsymBuilder {
+                name: block.name,
+                ..sync_with(caught)
+            };
+            (index, block.into_future())
+        })
+        .collect();
+    Completed {
+        jobs,
+        ready: VecDeque::new(),
+    }
+}
+
+/// Runs the blocks of sync code concurrently, and resolves to all of their
+/// outputs in the order of `blocks`.
+///
+/// See [`select_completed`] for how the blocks are executed and how their
+/// panics are caught.
+#[track_caller]
+pub fn join_all<'a, T, F, I>(blocks: I) -> JoinAll<'a, T>
+where
+    I: IntoIterator<Item = AsymBuilder<'a, T, F>>,
+    F: FnOnce(AsymContext<'_>) -> T + Send + 'a,
+    T: 'a,
+{
+    let completed = select_completed(blocks);
+    let outputs = completed.jobs.iter().map(|_| None).collect();
+    JoinAll { completed, outputs }
+}
+
+/// The stream returned by [`select_completed`].
+#[must_use = "streams do nothing unless polled"]
+pub struct Completed<'a, T> {
+    jobs: Vec<(usize, Asym<'a, ThreadResult<T>>)>,
+    ready: VecDeque<(usize, ThreadResult<T>)>,
+}
+
+impl<T> Unpin for Completed<'_, T> {}
+
+impl<T> Completed<'_, T> {
+    /// The number of blocks not yielded yet.
+    pub fn len(&self) -> usize {
+        self.jobs.len() + self.ready.len()
+    }
+
+    /// Whether all the blocks have been yielded.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Polls the running blocks, and yields the next completed one along with
+    /// its index in the blocks passed in.
+    fn poll_indexed(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<(usize, ThreadResult<T>)>> {
+        if self.ready.is_empty() {
+            let ready = &mut self.ready;
+            self.jobs
+                .retain_mut(|(index, job)| match Pin::new(job).poll(cx) {
+                    Poll::Ready(output) => {
+                        ready.push_back((*index, output));
+                        false
+                    }
+                    Poll::Pending => true,
+                });
+        }
+        match self.ready.pop_front() {
+            Some(output) => Poll::Ready(Some(output)),
+            None if self.jobs.is_empty() => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Stream for Completed<'_, T> {
+    type Item = ThreadResult<T>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.poll_indexed(cx)
+            .map(|next| next.map(|(_, output)| output))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len(), Some(self.len()))
+    }
+}
+
+/// The future returned by [`join_all`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct JoinAll<'a, T> {
+    completed: Completed<'a, T>,
+    outputs: Vec<Option<ThreadResult<T>>>,
+}
+
+impl<T> Unpin for JoinAll<'_, T> {}
+
+impl<T> Future for JoinAll<'_, T> {
+    type Output = Vec<ThreadResult<T>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        while let Some((index, output)) =
+            core::task::ready!(this.completed.poll_indexed(cx))
+        {
+            this.outputs[index] = Some(output);
+        }
+        let outputs = this.outputs.drain(..);
+        Poll::Ready(
+            outputs
+                .map(|output| output.expect("`JoinAll` polled after completion"))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use core::{
+        future::poll_fn,
+        pin::{pin, Pin},
+    };
+
+    use futures_core::Stream;
+
+    use super::{join_all, select_completed};
+    use crate::{
+        asym::{block_on::block_on, sync, AsymWait},
+        tests::yield_now,
+    };
+
+    /// Yields to the executor `times` times.
+    fn yield_times(times: usize) {
+        for _ in 0..times {
+            yield_now().wait();
+        }
+    }
+
+    #[test]
+    fn completion_order() {
+        let blocks = [3, 1, 4, 2].map(|times| {
+            sync(move || {
+                yield_times(times);
+                assert_ne!(times, 4, "the slowest block panics");
+                times
+            })
+        });
+        let mut completed = select_completed(blocks);
+        assert_eq!(completed.len(), 4);
+
+        let outputs = block_on(pin!(async {
+            let mut outputs = Vec::new();
+            while let Some(output) =
+                poll_fn(|cx| Pin::new(&mut completed).poll_next(cx)).await
+            {
+                outputs.push(output.ok());
+            }
+            outputs
+        }));
+        assert_eq!(outputs, [Some(1), Some(2), Some(3), None]);
+        assert!(completed.is_empty());
+    }
+
+    #[test]
+    fn joined_in_order() {
+        let blocks = (0..8).map(|i| {
+            sync(move || {
+                yield_times(8 - i);
+                assert_ne!(i, 5);
+                i * i
+            })
+        });
+        let outputs = block_on(pin!(join_all(blocks)));
+        let outputs: Vec<_> = outputs.into_iter().map(Result::ok).collect();
+        assert_eq!(
+            outputs,
+            [0, 1, 4, 9, 16, 25, 36, 49].map(|i| (i != 25).then_some(i))
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    cell::Cell,
+    future::Future,
+    marker::PhantomPinned,
+    mem,
+    pin::Pin,
+    ptr::NonNull,
+    task::{Context, Poll, Waker},
+};
+
+use unico_ful::{
+    asym::{Gn, YieldHandle},
+    BuildUnchecked, Builder,
+};
+
+use super::{Asym, AsymContext, SetCxGuard, CX};
+
+/// The job handed to an idle coroutine: a pointer to the pinned job and the
+/// function running it.
+type Slot = Cell<Option<(NonNull<()>, unsafe fn(NonNull<()>))>>;
+
+/// A finished coroutine waiting for the next job, together with its stack.
+struct Worker {
+    asym: Asym<'static, ()>,
+    slot: Box<Slot>,
+}
+
+// SAFETY: The slot is only accessed by the worker and the `PoolRun` owning it.
+unsafe impl Send for Worker {}
+
+impl Worker {
+    #[track_caller]
+    fn new() -> Self {
+        let slot = Box::new(Slot::new(None));
+        let ptr = NonNull::from(&*slot);
+        let func = move |y: &mut YieldHandle<(), NonNull<Waker>>, mut waker| loop {
+            // SAFETY: The slot is boxed in the worker, which outlives its coroutine.
+            let (job, run) = unsafe { ptr.as_ref() }
+                .take()
+                .expect("a pooled coroutine is resumed without a job");
+            {
+                let cx = AsymContext { y: &mut *y, waker };
+                // SAFETY: `cx` will be unset when the guard goes out of scope.
+                let cx = unsafe {
+                    mem::transmute::<AsymContext<'_>, AsymContext<'static>>(cx)
+                };
+                let _old_guard = SetCxGuard(CX.replace(Some(cx)));
+                // SAFETY: `job` is pinned in the `PoolRun` polling this coroutine.
+                unsafe { run(job) };
+            }
+            waker = y.yield_(());
+        };
+
+        // SAFETY: `func` is `'static`, and it's `Send` since the slot is sent along
+        // with the coroutine.
+        let gn = match unsafe { Gn::build_unchecked(Builder::new(), func) } {
+            Ok(gn) => gn,
+            Err(err) => panic!("failed to build a stackful future: {err}"),
+        };
+        Worker {
+            asym: Asym(
+                gn,
+                None,
+                #[cfg(feature = "watchdog")]
+                super::watchdog::next_id(),
+            ),
+            slot,
+        }
+    }
+}
+
+struct Job<F, T> {
+    func: Option<F>,
+    output: Option<T>,
+}
+
+/// # Safety
+///
+/// `job` must point to a valid `Job<F, T>`.
+unsafe fn run_job<F: FnOnce() -> T, T>(job: NonNull<()>) {
+    let job = job.cast::<Job<F, T>>().as_ptr();
+    // SAFETY: `job` is valid by contract. No reference is held across the call,
+    // since the `PoolRun` may access it meanwhile.
+    unsafe {
+        let func = (*job).func.take().expect("a pooled job is run twice");
+        let output = func();
+        (*job).output = Some(output);
+    }
+}
+
+/// A pool of finished coroutines, which are reused for running blocks of sync
+/// code without creating new stacks and contexts.
+///
+/// Every coroutine in the pool keeps running a loop that takes a block from
+/// [`CoPool::run`], so reusing it costs neither an allocation nor the creation
+/// of a context. A coroutine whose block panics or is cancelled is not reused.
+///
+/// Like [`Asym`], the coroutines may be resumed on another thread after they
+/// are suspended, which the thread-affine backends don't support.
+pub struct CoPool {
+    idle: spin::Mutex<Vec<Worker>>,
+    capacity: usize,
+}
+
+impl CoPool {
+    /// Creates an empty pool keeping at most `capacity` idle coroutines.
+    pub const fn new(capacity: usize) -> Self {
+        CoPool {
+            idle: spin::Mutex::new(Vec::new()),
+            capacity,
+        }
+    }
+
+    /// The number of idle coroutines in the pool.
+    pub fn idle(&self) -> usize {
+        self.idle.lock().len()
+    }
+
+    /// Turns a block of sync code into a future running on some coroutine of
+    /// the pool, or a new one if the pool is empty.
+    ///
+    /// The coroutine is taken on the first poll, and returned to the pool when
+    /// the block completes.
+    pub fn run<'a, T, F>(&'a self, func: F) -> PoolRun<'a, T, F>
+    where
+        F: FnOnce() -> T + Send + 'a,
+    {
+        PoolRun {
+            pool: self,
+            worker: None,
+            job: Job {
+                func: Some(func),
+                output: None,
+            },
+            _pinned: PhantomPinned,
+        }
+    }
+}
+
+/// The future returned by [`CoPool::run`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct PoolRun<'a, T, F> {
+    pool: &'a CoPool,
+    // The worker must be dropped before the job, since it may still be running.
+    worker: Option<Worker>,
+    job: Job<F, T>,
+    _pinned: PhantomPinned,
+}
+
+impl<T, F: FnOnce() -> T> Future for PoolRun<'_, T, F> {
+    type Output = T;
+
+    #[track_caller]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        // SAFETY: The job is never moved out, and the worker is never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let worker = match &mut this.worker {
+            Some(worker) => worker,
+            None => {
+                let mut worker = this.pool.idle.lock().pop().unwrap_or_else(Worker::new);
+                // The block inherits the context of the task, like a new coroutine.
+                worker.asym.1 = None;
+                let job = NonNull::from(&mut this.job).cast();
+                worker.slot.set(Some((job, run_job::<F, T>)));
+                this.worker.insert(worker)
+            }
+        };
+
+        if Pin::new(&mut worker.asym).poll(cx).is_ready() {
+            unreachable!("a pooled coroutine returned")
+        }
+        match this.job.output.take() {
+            Some(output) => {
+                let worker = this.worker.take().unwrap();
+                let mut idle = this.pool.idle.lock();
+                if idle.len() < this.pool.capacity {
+                    idle.push(worker);
+                }
+                Poll::Ready(output)
+            }
+            None => Poll::Pending,
+        }
+    }
+}
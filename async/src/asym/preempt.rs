@@ -0,0 +1,173 @@
+//! Cooperative preemption of the blocks of sync code, which are asked to yield
+//! by a timer signal once they have run for a while.
+
+use core::{
+    ffi::c_int,
+    mem::MaybeUninit,
+    ptr,
+    sync::atomic::{AtomicBool, Ordering::Relaxed},
+};
+use std::{
+    io::{Error as IoError, ErrorKind, Result as IoResult},
+    sync::OnceLock,
+    time::Duration,
+};
+
+use super::{SetCxGuard, CX};
+
+/// The signal sent by the timers, which is ignored by default, so a stray one
+/// does no harm.
+const SIGNAL: c_int = libc::SIGURG;
+
+std::thread_local! {
+    // Written by the signal handler, so it must be initialized constantly and
+    // never be dropped.
+    static YIELD: AtomicBool = const { AtomicBool::new(false) };
+}
+
+extern "C" fn handler(_: c_int) {
+    YIELD.with(|flag| flag.store(true, Relaxed));
+}
+
+/// A timer asking the blocks of sync code on the thread creating it to yield
+/// periodically, which is stopped when dropped.
+///
+/// This structure is created by [`start_yield_timer`].
+#[derive(Debug)]
+pub struct YieldTimer(libc::timer_t);
+
+/// Starts a timer on the current thread, which fires every `interval` of CPU
+/// time consumed by this thread.
+///
+/// Every time it fires, [`should_yield`] returns `true` on this thread until
+/// the next successful [`maybe_yield`]. Since the coroutines cannot be
+/// suspended at arbitrary points, a CPU-bound block of code must still call
+/// [`maybe_yield`] now and then for the executor to regain control.
+///
+/// The first call installs a handler of `SIGURG` for the whole process, which
+/// replaces any existing one without chaining to it. The failure to install
+/// it is returned by every call. A zero `interval` is rejected with
+/// [`ErrorKind::InvalidInput`], since it would disarm the timer.
+pub fn start_yield_timer(interval: Duration) -> IoResult<YieldTimer> {
+    /// The OS error of installing the handler, if any.
+    static INSTALL: OnceLock<Result<(), i32>> = OnceLock::new();
+    if interval.is_zero() {
+        return Err(IoError::new(ErrorKind::InvalidInput, "zero interval"));
+    }
+    let install = INSTALL.get_or_init(|| {
+        // SAFETY: The handler only touches a constantly initialized thread local,
+        // which is async-signal-safe.
+        let status = unsafe {
+            let handler: extern "C" fn(c_int) = handler;
+            let mut action = MaybeUninit::<libc::sigaction>::zeroed().assume_init();
+            action.sa_sigaction = handler as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART | libc::SA_ONSTACK;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(SIGNAL, &action, ptr::null_mut())
+        };
+        match status {
+            0 => Ok(()),
+            _ => Err(IoError::last_os_error().raw_os_error().unwrap_or(0)),
+        }
+    });
+    if let Err(code) = *install {
+        return Err(IoError::from_raw_os_error(code));
+    }
+
+    // SAFETY: Zeros are valid for the plain C structures.
+    let (mut event, mut spec) = unsafe {
+        (
+            MaybeUninit::<libc::sigevent>::zeroed().assume_init(),
+            MaybeUninit::<libc::itimerspec>::zeroed().assume_init(),
+        )
+    };
+    event.sigev_notify = libc::SIGEV_THREAD_ID;
+    event.sigev_signo = SIGNAL;
+    // SAFETY: `gettid` has no contract.
+    event.sigev_notify_thread_id = unsafe { libc::gettid() };
+    spec.it_interval.tv_sec = interval.as_secs() as libc::time_t;
+    spec.it_interval.tv_nsec = interval.subsec_nanos().into();
+    spec.it_value = spec.it_interval;
+
+    let (clock, mut timer) = (libc::CLOCK_THREAD_CPUTIME_ID, MaybeUninit::uninit());
+    // SAFETY: `event` targets the current thread, and `timer` is only used if
+    // it's created.
+    unsafe {
+        if libc::timer_create(clock, &mut event, timer.as_mut_ptr()) != 0 {
+            return Err(IoError::last_os_error());
+        }
+        let timer = YieldTimer(timer.assume_init());
+        match libc::timer_settime(timer.0, 0, &spec, ptr::null_mut()) {
+            0 => Ok(timer),
+            _ => Err(IoError::last_os_error()),
+        }
+    }
+}
+
+impl Drop for YieldTimer {
+    fn drop(&mut self) {
+        // SAFETY: The timer is created by `start_yield_timer`.
+        unsafe { libc::timer_delete(self.0) };
+    }
+}
+
+/// Whether a [`YieldTimer`] has fired on the current thread since the last
+/// successful [`maybe_yield`].
+pub fn should_yield() -> bool {
+    YIELD.with(|flag| flag.load(Relaxed))
+}
+
+/// Suspends the current block of sync code back to the executor if
+/// [`should_yield`], which is polled again right away. Returns whether it has
+/// yielded.
+///
+/// Nothing is done outside of any coroutine created by [`sync`](super::sync),
+/// or while unwinding.
+pub fn maybe_yield() -> bool {
+    if !should_yield() || std::thread::panicking() {
+        return false;
+    }
+    let Some(cx) = CX.take() else {
+        return false;
+    };
+    YIELD.with(|flag| flag.store(false, Relaxed));
+
+    let mut guard = SetCxGuard(None);
+    let cx = guard.0.insert(cx);
+    // SAFETY: `cx.waker` remains valid until `cx.y.yield_()`.
+    unsafe { cx.waker.as_ref() }.wake_by_ref();
+    cx.waker = cx.y.yield_(());
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{future::IntoFuture, hint::black_box, time::Duration};
+    use std::io::ErrorKind;
+
+    use super::{maybe_yield, should_yield, start_yield_timer};
+    use crate::asym::{block_on::block_on, sync};
+
+    #[test]
+    fn preempted() {
+        assert!(!maybe_yield());
+        let _timer = start_yield_timer(Duration::from_millis(1)).unwrap();
+
+        let yields = block_on(core::pin::pin!(sync(|| {
+            let (mut yields, mut sum) = (0, 0u64);
+            while yields < 3 {
+                sum = black_box(sum.wrapping_add(1));
+                if maybe_yield() {
+                    assert!(!should_yield());
+                    yields += 1;
+                }
+            }
+            yields
+        })
+        .into_future()));
+        assert_eq!(yields, 3);
+
+        let err = start_yield_timer(Duration::ZERO).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,69 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    any::Any,
+    mem,
+    sync::atomic::{AtomicBool, Ordering::*},
+};
+
+use spin::RwLock;
+
+/// Saves and restores some context stored in thread-local storage, e.g. the
+/// current `tracing` dispatcher or span.
+///
+/// The whole thread shares such context, while an [`Asym`](super::Asym) may be
+/// suspended in the middle of its execution with other tasks polled on the
+/// same thread meanwhile. So every registered saver swaps the context upon
+/// each switch: the context of the task polling the future is saved and the
+/// one of the coroutine is restored before the coroutine is resumed, and vice
+/// versa after it's suspended.
+///
+/// Upon the first resumption, the coroutine inherits the context of the task
+/// polling it.
+pub trait ContextSaver: Sync {
+    /// Captures the context of the current thread, leaving it untouched.
+    fn save(&self) -> Box<dyn Any + Send>;
+
+    /// Replaces the context of the current thread with the one previously
+    /// returned by [`ContextSaver::save`].
+    fn restore(&self, saved: Box<dyn Any + Send>);
+}
+
+static SAVERS: RwLock<Vec<&'static dyn ContextSaver>> = RwLock::new(Vec::new());
+/// Whether any saver is registered, so that the lock is left alone otherwise.
+static REGISTERED: AtomicBool = AtomicBool::new(false);
+
+/// Registers a [`ContextSaver`] for all the [`Asym`](super::Asym)s.
+///
+/// Savers should be registered at startup, before any coroutine is created,
+/// since the ones registered later are not applied to the coroutines already
+/// suspended until they're resumed once.
+pub fn register_context_saver(saver: &'static dyn ContextSaver) {
+    SAVERS.write().push(saver);
+    REGISTERED.store(true, Release);
+}
+
+pub(super) type Saved = Vec<Box<dyn Any + Send>>;
+
+pub(super) fn save() -> Saved {
+    if !REGISTERED.load(Acquire) {
+        return Vec::new();
+    }
+    SAVERS.read().iter().map(|saver| saver.save()).collect()
+}
+
+pub(super) fn restore(saved: Saved) {
+    if saved.is_empty() {
+        return;
+    }
+    let savers = SAVERS.read();
+    (savers.iter().zip(saved)).for_each(|(saver, saved)| saver.restore(saved));
+}
+
+/// Restores the saved context when dropped, even if the coroutine panics.
+pub(super) struct Restore(pub Saved);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        restore(mem::take(&mut self.0));
+    }
+}
@@ -0,0 +1,275 @@
+use alloc::vec::Vec;
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::future::FusedFuture;
+
+use super::{AsymContext, AsymWait};
+
+type Branch<'f, T> = Pin<&'f mut (dyn FusedFuture<Output = T> + Send + 'f)>;
+
+/// A set of named futures waited on together, like the branches of
+/// `futures::select!` in a loop, created by [`wait_select`].
+///
+/// Each wait resolves to the first branch ready, along with its key. The
+/// other branches are only polled, never dropped, so they're retained with
+/// their progress for the next wait. The futures are borrowed pinned, e.g.
+/// with [`core::pin::pin!`] on the stack of the coroutine, which keeps the
+/// [`!Unpin`](Unpin) ones in place across the iterations, and gives them back
+/// to the caller once this set is dropped.
+///
+/// Like `select!`, the futures must be [`FusedFuture`]s, and the branches
+/// whose futures are terminated are disabled, so a branch fires at most once
+/// per future. The branches are polled in turn starting from the one after
+/// the last fired, so a branch always ready cannot starve the others.
+pub struct WaitSelect<'f, K, T> {
+    branches: Vec<(K, Branch<'f, T>)>,
+    start: usize,
+}
+
+/// Creates an empty set of branches to wait on, see [`WaitSelect`].
+///
+/// ```rust
+/// # #![feature(allocator_api)]
+/// # unico_stack::global_stack_allocator!(std::alloc::Global);
+/// # unico_context::global_resumer!(unico_context::boost::Boost);
+/// use core::{future::IntoFuture, pin::pin};
+///
+/// use unico_async::{
+///     asym::{sync, wait_select},
+///     test,
+/// };
+///
+/// let fired = test::block_on(sync(|| {
+///     let mut fast = pin!(sync(|| 1).into_future());
+///     let mut slow = pin!(sync(|| 2).into_future());
+///     let mut select = wait_select().with("fast", fast.as_mut());
+///     let mut fired = Vec::new();
+///     fired.extend(select.wait());
+///     let mut select = select.with("slow", slow.as_mut());
+///     while let Some(branch) = select.wait() {
+///         fired.push(branch);
+///     }
+///     fired
+/// }));
+/// assert_eq!(fired, [("fast", 1), ("slow", 2)]);
+/// ```
+pub fn wait_select<'f, K, T>() -> WaitSelect<'f, K, T> {
+    WaitSelect {
+        branches: Vec::new(),
+        start: 0,
+    }
+}
+
+impl<'f, K, T> WaitSelect<'f, K, T> {
+    /// Adds `future` as a branch named `key`.
+    pub fn with<F>(mut self, key: K, future: Pin<&'f mut F>) -> Self
+    where
+        F: FusedFuture<Output = T> + Send + 'f,
+    {
+        self.add(key, future);
+        self
+    }
+
+    /// Adds `future` as a branch named `key` in place.
+    pub fn add<F>(&mut self, key: K, future: Pin<&'f mut F>)
+    where
+        F: FusedFuture<Output = T> + Send + 'f,
+    {
+        self.branches.push((key, future));
+    }
+
+    /// The number of the branches, including the disabled ones.
+    pub fn len(&self) -> usize {
+        self.branches.len()
+    }
+
+    /// Whether there's no branch at all.
+    pub fn is_empty(&self) -> bool {
+        self.branches.is_empty()
+    }
+
+    /// Whether every branch is disabled, where waiting resolves to [`None`]
+    /// right away.
+    pub fn is_terminated(&self) -> bool {
+        (self.branches.iter()).all(|(_, future)| future.is_terminated())
+    }
+
+    /// The future resolving to the first branch ready, or [`None`] if every
+    /// branch is disabled.
+    ///
+    /// Dropping it before it resolves drops none of the branches.
+    pub fn next_ready(&mut self) -> SelectNext<'_, 'f, K, T> {
+        SelectNext(self)
+    }
+
+    /// Waits with `cx` for the first branch ready, see
+    /// [`AsymWait::wait_with`].
+    pub fn wait_with(&mut self, cx: &mut AsymContext<'_>) -> Option<(K, T)>
+    where
+        K: Clone + Send,
+    {
+        self.next_ready().wait_with(cx)
+    }
+
+    /// Waits for the first branch ready, see [`AsymWait::wait`].
+    #[cfg(feature = "std")]
+    pub fn wait(&mut self) -> Option<(K, T)>
+    where
+        K: Clone + Send,
+    {
+        self.next_ready().wait()
+    }
+
+    fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<(K, T)>>
+    where
+        K: Clone,
+    {
+        let len = self.branches.len();
+        let mut enabled = false;
+        for index in (self.start..len).chain(0..self.start) {
+            let (key, future) = &mut self.branches[index];
+            if future.is_terminated() {
+                continue;
+            }
+            enabled = true;
+            if let Poll::Ready(output) = future.as_mut().poll(cx) {
+                self.start = (index + 1) % len;
+                return Poll::Ready(Some((key.clone(), output)));
+            }
+        }
+        if enabled {
+            Poll::Pending
+        } else {
+            Poll::Ready(None)
+        }
+    }
+}
+
+impl<K: fmt::Debug, T> fmt::Debug for WaitSelect<'_, K, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let branches = self.branches.iter().map(|(key, future)| {
+            let state = if future.is_terminated() {
+                "disabled"
+            } else {
+                "enabled"
+            };
+            (key, state)
+        });
+        f.debug_map().entries(branches).finish()
+    }
+}
+
+/// The future returned by [`WaitSelect::next_ready`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct SelectNext<'s, 'f, K, T>(&'s mut WaitSelect<'f, K, T>);
+
+impl<K: Clone, T> Future for SelectNext<'_, '_, K, T> {
+    type Output = Option<(K, T)>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.0.poll_next(cx)
+    }
+}
+
+impl<K: Clone, T> FusedFuture for SelectNext<'_, '_, K, T> {
+    fn is_terminated(&self) -> bool {
+        self.0.is_terminated()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use core::{
+        future::{Future, IntoFuture},
+        marker::PhantomPinned,
+        pin::{pin, Pin},
+        task::{Context, Poll},
+    };
+
+    use futures_core::future::FusedFuture;
+
+    use super::wait_select;
+    use crate::asym::{block_on::block_on, sync};
+
+    /// Ready after being polled some times, and never moved meanwhile.
+    struct Countdown {
+        left: u32,
+        done: bool,
+        _pinned: PhantomPinned,
+    }
+
+    impl Countdown {
+        fn new(left: u32) -> Self {
+            Countdown {
+                left,
+                done: false,
+                _pinned: PhantomPinned,
+            }
+        }
+    }
+
+    impl Future for Countdown {
+        type Output = u32;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u32> {
+            // SAFETY: No field is structurally pinned.
+            let this = unsafe { self.get_unchecked_mut() };
+            assert!(!this.done, "`Countdown` polled after completion");
+            if this.left == 0 {
+                this.done = true;
+                return Poll::Ready(0);
+            }
+            this.left -= 1;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    impl FusedFuture for Countdown {
+        fn is_terminated(&self) -> bool {
+            self.done
+        }
+    }
+
+    #[test]
+    fn retained() {
+        let fired = block_on(pin!(sync(|| {
+            let mut short = pin!(Countdown::new(1));
+            let mut long = pin!(Countdown::new(4));
+            let mut select = wait_select()
+                .with("long", long.as_mut())
+                .with("short", short.as_mut());
+            let mut fired = Vec::new();
+            while let Some((key, _)) = select.wait() {
+                fired.push(key);
+            }
+            assert!(select.is_terminated());
+            drop(select);
+            // The losers are polled in place, never dropped nor restarted.
+            assert!(long.is_terminated());
+            fired
+        })
+        .into_future()));
+        assert_eq!(fired, ["short", "long"]);
+    }
+
+    #[test]
+    fn fair() {
+        let fired = block_on(pin!(sync(|| {
+            let mut first = pin!(sync(|| 1).into_future());
+            let mut second = pin!(sync(|| 2).into_future());
+            let mut select = wait_select()
+                .with(0, first.as_mut())
+                .with(1, second.as_mut());
+            [select.wait(), select.wait(), select.wait()]
+        })
+        .into_future()));
+        assert_eq!(fired, [Some((0, 1)), Some((1, 2)), None]);
+    }
+}
@@ -0,0 +1,86 @@
+use alloc::boxed::Box;
+use core::{
+    cell::Cell,
+    future::{Future, IntoFuture},
+    pin::Pin,
+    ptr::NonNull,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+
+use super::{sync, Asym, SetCxGuard, CX};
+
+/// The handle passed to the block of code in [`stream_from_blocking`], used for
+/// emitting the items of the stream.
+pub struct Emitter<T> {
+    slot: NonNull<Cell<Option<T>>>,
+}
+
+// SAFETY: The slot is only accessed while the coroutine is running, when the
+// stream never touches it.
+unsafe impl<T: Send> Send for Emitter<T> {}
+
+impl<T> Emitter<T> {
+    /// Emits `item` as the next item of the stream, and suspends until the
+    /// stream is polled for the item after it.
+    #[track_caller]
+    pub fn emit(&mut self, item: T) {
+        // SAFETY: The slot is boxed in the stream, which outlives its coroutine.
+        unsafe { self.slot.as_ref() }.set(Some(item));
+
+        let mut guard = SetCxGuard(None);
+        let cx =
+            (guard.0).insert(CX.take().expect("an item is emitted out of its stream"));
+        cx.waker = cx.y.yield_(());
+    }
+}
+
+/// Turns a block of sync code into a [`Stream`] of the items it emits.
+///
+/// The block is executed in a stackful coroutine like [`sync`], so it can
+/// [`wait`](super::AsymWait::wait) on futures between the items. Every call to
+/// [`Emitter::emit`] suspends the coroutine and yields the item out, and the
+/// stream ends when the block returns.
+#[track_caller]
+pub fn stream_from_blocking<'a, T: Send + 'a>(
+    func: impl FnOnce(&mut Emitter<T>) + Send + 'a,
+) -> BlockingStream<'a, T> {
+    let slot = Box::new(Cell::new(None));
+    let mut emitter = Emitter {
+        slot: NonNull::from(&*slot),
+    };
+    BlockingStream {
+        asym: Some(sync(move || func(&mut emitter)).into_future()),
+        slot,
+    }
+}
+
+/// The stream returned by [`stream_from_blocking`].
+#[must_use = "streams do nothing unless polled"]
+pub struct BlockingStream<'a, T> {
+    // The coroutine must be dropped before the slot it refers to.
+    asym: Option<Asym<'a, ()>>,
+    slot: Box<Cell<Option<T>>>,
+}
+
+impl<T> Stream for BlockingStream<'_, T> {
+    type Item = T;
+
+    #[track_caller]
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let Some(asym) = &mut self.asym else {
+            return Poll::Ready(None);
+        };
+        match Pin::new(asym).poll(cx) {
+            Poll::Ready(()) => {
+                self.asym = None;
+                Poll::Ready(None)
+            }
+            Poll::Pending => match self.slot.take() {
+                Some(item) => Poll::Ready(Some(item)),
+                None => Poll::Pending,
+            },
+        }
+    }
+}
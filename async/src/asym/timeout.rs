@@ -0,0 +1,259 @@
+use core::{
+    fmt,
+    future::{Future, IntoFuture},
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+/// A monotonic clock counting in ticks, whose unit is up to the implementor.
+///
+/// This trait lets timeouts work without the standard library, e.g. on top of
+/// a hardware timer register.
+pub trait TimeSource {
+    /// The current time in ticks.
+    fn now(&self) -> u64;
+
+    /// Arranges for `waker` to be woken once the time reaches `deadline`, and
+    /// returns whether it will be.
+    ///
+    /// The default implementation returns `false`, since a bare clock has no
+    /// way to notify anyone. [`Timeout`] then asks to be polled again right
+    /// after every pending poll instead.
+    fn wake_at(&self, deadline: u64, waker: &Waker) -> bool {
+        let _ = (deadline, waker);
+        false
+    }
+
+    /// Cancels the wakeup arranged by [`wake_at`](Self::wake_at) with the
+    /// same `deadline` and `waker`, if it's still pending, so that the waker
+    /// is released right away.
+    ///
+    /// The default implementation does nothing.
+    fn cancel_wake(&self, deadline: u64, waker: &Waker) {
+        let _ = (deadline, waker);
+    }
+}
+
+impl<T: TimeSource + ?Sized> TimeSource for &T {
+    fn now(&self) -> u64 {
+        (**self).now()
+    }
+
+    fn wake_at(&self, deadline: u64, waker: &Waker) -> bool {
+        (**self).wake_at(deadline, waker)
+    }
+
+    fn cancel_wake(&self, deadline: u64, waker: &Waker) {
+        (**self).cancel_wake(deadline, waker)
+    }
+}
+
+/// Counts the nanoseconds elapsed since the instant, and wakes the tasks at
+/// their deadlines from a timer thread spawned on the first use.
+#[cfg(feature = "std")]
+impl TimeSource for Instant {
+    fn now(&self) -> u64 {
+        self.elapsed().as_nanos().try_into().unwrap_or(u64::MAX)
+    }
+
+    fn wake_at(&self, deadline: u64, waker: &Waker) -> bool {
+        match self.checked_add(Duration::from_nanos(deadline)) {
+            Some(at) => timer::wake_at(at, waker.clone()),
+            None => false,
+        }
+    }
+
+    fn cancel_wake(&self, deadline: u64, waker: &Waker) {
+        if let Some(at) = self.checked_add(Duration::from_nanos(deadline)) {
+            timer::cancel(at, waker);
+        }
+    }
+}
+
+/// A single thread waking the tasks at their deadlines, in the order of the
+/// deadlines.
+#[cfg(feature = "std")]
+mod timer {
+    use alloc::collections::BTreeMap;
+    use core::task::Waker;
+    use std::{
+        sync::{Condvar, Mutex, MutexGuard, OnceLock},
+        thread,
+        time::Instant,
+    };
+
+    /// The pending timers, keyed by their deadlines and then the order of
+    /// registration.
+    struct Timers {
+        next: u64,
+        wakers: BTreeMap<(Instant, u64), Waker>,
+    }
+
+    static TIMERS: Mutex<Timers> = Mutex::new(Timers {
+        next: 0,
+        wakers: BTreeMap::new(),
+    });
+    static CHANGED: Condvar = Condvar::new();
+    /// Whether the timer thread is spawned successfully.
+    static RUNNING: OnceLock<bool> = OnceLock::new();
+
+    fn lock() -> MutexGuard<'static, Timers> {
+        TIMERS.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Wakes `waker` at `at`, and returns whether the timer thread is running.
+    pub(super) fn wake_at(at: Instant, waker: Waker) -> bool {
+        let running = RUNNING.get_or_init(|| {
+            let builder = thread::Builder::new().name("unico-timer".into());
+            builder.spawn(run).is_ok()
+        });
+        if !running {
+            return false;
+        }
+        let mut timers = lock();
+        timers.next += 1;
+        let key = (at, timers.next);
+        timers.wakers.insert(key, waker);
+        CHANGED.notify_one();
+        true
+    }
+
+    /// Removes a timer set by [`wake_at`] with `at` and a waker waking the same
+    /// task as `waker`, if it's not fired yet.
+    ///
+    /// The timer thread isn't notified, since it only ever waits for the
+    /// earliest timer a bit longer than needed.
+    pub(super) fn cancel(at: Instant, waker: &Waker) {
+        let mut timers = lock();
+        let found = (timers.wakers.range((at, 0)..=(at, u64::MAX)))
+            .find(|(_, timer)| timer.will_wake(waker))
+            .map(|(key, _)| *key);
+        if let Some(key) = found {
+            timers.wakers.remove(&key);
+        }
+    }
+
+    fn run() {
+        let mut timers = lock();
+        loop {
+            let Some((&(at, _), _)) = timers.wakers.first_key_value() else {
+                timers = CHANGED.wait(timers).unwrap_or_else(|err| err.into_inner());
+                continue;
+            };
+            let now = Instant::now();
+            if at > now {
+                timers = (CHANGED.wait_timeout(timers, at - now))
+                    .map_or_else(|err| err.into_inner().0, |(timers, _)| timers);
+                continue;
+            }
+            let Some((_, waker)) = timers.wakers.pop_first() else {
+                unreachable!("the first timer is peeked above");
+            };
+            // The waker may take some other locks, or register another timer.
+            drop(timers);
+            waker.wake();
+            timers = lock();
+        }
+    }
+}
+
+/// The error returned when a future doesn't complete before its deadline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimedOut;
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the future timed out")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for TimedOut {}
+
+/// A future that fails with [`TimedOut`] if the inner one doesn't complete
+/// within some ticks of a [`TimeSource`].
+///
+/// This structure is created by [`timeout`].
+#[derive(Debug)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Timeout<F, S: TimeSource> {
+    future: F,
+    source: S,
+    deadline: u64,
+    /// The waker the source is to wake at the deadline, if any.
+    timer: Option<Waker>,
+}
+
+/// Limits `future` to complete within `ticks` of `source`.
+///
+/// The task is woken at the deadline by [`TimeSource::wake_at`] if the source
+/// supports it, like `Instant` does. Otherwise, the
+/// returned future asks to be polled again right after every pending poll
+/// until the inner future completes or the deadline passes. In other words,
+/// the waiting is a busy loop yielding to the executor in between.
+pub fn timeout<F: IntoFuture, S: TimeSource>(
+    future: F,
+    source: S,
+    ticks: u64,
+) -> Timeout<F::IntoFuture, S> {
+    let deadline = source.now().saturating_add(ticks);
+    Timeout {
+        future: future.into_future(),
+        source,
+        deadline,
+        timer: None,
+    }
+}
+
+impl<F: Future, S: TimeSource> Future for Timeout<F, S> {
+    type Output = Result<F::Output, TimedOut>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: `future` is structurally pinned, and never moved out.
+        let this = unsafe { self.get_unchecked_mut() };
+        // SAFETY: See above.
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        if let Poll::Ready(output) = future.poll(cx) {
+            this.cancel_timer();
+            return Poll::Ready(Ok(output));
+        }
+        if this.source.now() >= this.deadline {
+            this.cancel_timer();
+            return Poll::Ready(Err(TimedOut));
+        }
+        // The timer is set again only if the task is woken by another waker.
+        let waker = cx.waker();
+        if !this
+            .timer
+            .as_ref()
+            .is_some_and(|timer| timer.will_wake(waker))
+        {
+            this.cancel_timer();
+            if this.source.wake_at(this.deadline, waker) {
+                this.timer = Some(waker.clone());
+            } else {
+                waker.wake_by_ref();
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl<F, S: TimeSource> Timeout<F, S> {
+    /// Cancels the timer set for the deadline, if any.
+    fn cancel_timer(&mut self) {
+        if let Some(timer) = self.timer.take() {
+            self.source.cancel_wake(self.deadline, &timer);
+        }
+    }
+}
+
+/// Cancels the pending timer, so that the task isn't kept alive until the
+/// deadline, nor woken spuriously then.
+impl<F, S: TimeSource> Drop for Timeout<F, S> {
+    fn drop(&mut self) {
+        self.cancel_timer();
+    }
+}
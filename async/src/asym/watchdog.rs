@@ -0,0 +1,259 @@
+//! A watchdog catching the coroutines which run for too long without
+//! suspending, e.g. when stuck in an infinite loop, which blocks the thread of
+//! the executor silently.
+
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    sync::atomic::{
+        AtomicBool, AtomicU64, AtomicUsize,
+        Ordering::{Acquire, Relaxed, Release},
+    },
+    time::Duration,
+};
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+    thread::{self, JoinHandle, Thread},
+    time::Instant,
+};
+
+/// The number of the running watchdogs. The coroutines are only timed while
+/// it's nonzero.
+static WATCHDOGS: AtomicUsize = AtomicUsize::new(0);
+
+/// The next id of a coroutine, starting from 1, since 0 stands for none.
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// The slots of all the threads which have ever resumed a coroutine while
+/// some watchdog is running.
+static SLOTS: Mutex<Vec<Weak<Slot>>> = Mutex::new(Vec::new());
+
+/// The origin of the timestamps in the slots.
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+/// The coroutine running on some thread.
+struct Slot {
+    thread: Thread,
+    /// The id of the coroutine, or 0 if none is running.
+    id: AtomicU64,
+    /// The name of the coroutine, if any.
+    name: Mutex<Option<&'static str>>,
+    /// The time of the resumption of the coroutine, in nanoseconds since
+    /// `EPOCH`.
+    since: AtomicU64,
+}
+
+std::thread_local! {
+    static SLOT: Arc<Slot> = {
+        let slot = Arc::new(Slot {
+            thread: thread::current(),
+            id: AtomicU64::new(0),
+            name: Mutex::new(None),
+            since: AtomicU64::new(0),
+        });
+        let mut slots = SLOTS.lock().unwrap_or_else(|err| err.into_inner());
+        slots.push(Arc::downgrade(&slot));
+        slot
+    };
+}
+
+fn now() -> u64 {
+    let elapsed = EPOCH.get_or_init(Instant::now).elapsed();
+    elapsed.as_nanos().try_into().unwrap_or(u64::MAX)
+}
+
+/// Allocates the id of a new coroutine.
+pub(super) fn next_id() -> u64 {
+    NEXT_ID.fetch_add(1, Relaxed)
+}
+
+/// Marks the coroutine `id` named `name` as running on the current thread
+/// until the returned guard is dropped, which restores the coroutine resuming
+/// it, if any.
+pub(super) fn running(id: u64, name: Option<&'static str>) -> Running {
+    if WATCHDOGS.load(Relaxed) == 0 {
+        return Running(None);
+    }
+    let slot = SLOT.try_with(|slot| {
+        let outer_name = slot.set_name(name);
+        let outer = (slot.id.load(Relaxed), outer_name, slot.since.load(Relaxed));
+        slot.since.store(now(), Relaxed);
+        slot.id.store(id, Release);
+        (slot.clone(), outer)
+    });
+    Running(slot.ok())
+}
+
+impl Slot {
+    fn set_name(&self, name: Option<&'static str>) -> Option<&'static str> {
+        let mut slot = self.name.lock().unwrap_or_else(|err| err.into_inner());
+        core::mem::replace(&mut *slot, name)
+    }
+}
+
+type Outer = (u64, Option<&'static str>, u64);
+
+pub(super) struct Running(Option<(Arc<Slot>, Outer)>);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        if let Some((slot, (id, name, since))) = self.0.take() {
+            slot.set_name(name);
+            slot.since.store(since, Relaxed);
+            slot.id.store(id, Release);
+        }
+    }
+}
+
+/// A coroutine found running for too long without suspending, reported by the
+/// watchdog started with [`start_watchdog`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Hang {
+    /// The id of the coroutine, which is unique in the process.
+    pub id: u64,
+    /// The name of the coroutine set by
+    /// [`AsymBuilder::named`](super::AsymBuilder::named), if any.
+    pub name: Option<&'static str>,
+    /// The thread running the coroutine.
+    pub thread: Thread,
+    /// How long the coroutine has run since it was resumed last time.
+    pub elapsed: Duration,
+}
+
+/// A watchdog thread started by [`start_watchdog`], which is stopped when
+/// dropped.
+#[derive(Debug)]
+pub struct Watchdog {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Starts a watchdog thread, which calls `on_hang` once for every run of a
+/// coroutine longer than `threshold` without suspending.
+///
+/// The coroutines are timed on every resumption, but only while some watchdog
+/// is running, so this has no cost otherwise. The running ones are checked
+/// every quarter of `threshold`, so a hang is reported with a delay of up to
+/// that long.
+///
+/// `on_hang` is called on the watchdog thread, since the hung one is busy. It
+/// usually logs a warning with the [`Hang`].
+pub fn start_watchdog(
+    threshold: Duration,
+    mut on_hang: impl FnMut(&Hang) + Send + 'static,
+) -> Watchdog {
+    let stop = Arc::new(AtomicBool::new(false));
+    let interval = (threshold / 4).max(Duration::from_millis(1));
+    let threshold = threshold.as_nanos().try_into().unwrap_or(u64::MAX);
+    WATCHDOGS.fetch_add(1, Relaxed);
+
+    let stopped = stop.clone();
+    let thread = thread::Builder::new().name("unico-watchdog".into());
+    let thread = thread.spawn(move || {
+        // The runs already reported, by the id of their threads.
+        let mut reported = HashMap::new();
+        while !stopped.load(Acquire) {
+            let slots = SLOTS.lock().unwrap_or_else(|err| err.into_inner()).clone();
+            let now = now();
+            for slot in slots.iter().filter_map(Weak::upgrade) {
+                let id = slot.id.load(Acquire);
+                let since = slot.since.load(Relaxed);
+                let name = *slot.name.lock().unwrap_or_else(|err| err.into_inner());
+                // Skip a slot switched to another coroutine in between.
+                if id == 0 || slot.id.load(Acquire) != id {
+                    continue;
+                }
+                let elapsed = now.saturating_sub(since);
+                let key = slot.thread.id();
+                if elapsed > threshold && reported.get(&key) != Some(&(id, since)) {
+                    reported.insert(key, (id, since));
+                    on_hang(&Hang {
+                        id,
+                        name,
+                        thread: slot.thread.clone(),
+                        elapsed: Duration::from_nanos(elapsed),
+                    });
+                }
+            }
+            let mut slots = SLOTS.lock().unwrap_or_else(|err| err.into_inner());
+            slots.retain(|slot| slot.strong_count() > 0);
+            drop(slots);
+            thread::park_timeout(interval);
+        }
+    });
+    let thread = match thread {
+        Ok(thread) => thread,
+        Err(err) => panic!("failed to start the watchdog: {err}"),
+    };
+    Watchdog {
+        stop,
+        thread: Some(thread),
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.stop.store(true, Release);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+        WATCHDOGS.fetch_sub(1, Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{sync::Arc, vec::Vec};
+    use core::{future::IntoFuture, hint::black_box, pin::pin, time::Duration};
+    use std::{sync::Mutex, thread, time::Instant};
+
+    use super::start_watchdog;
+    use crate::{
+        asym::{block_on::block_on, sync, sync_named, AsymWait},
+        tests::yield_now,
+    };
+
+    /// Spins the current thread for `duration`.
+    fn spin(duration: Duration) {
+        let start = Instant::now();
+        while start.elapsed() < duration {
+            black_box(());
+        }
+    }
+
+    #[test]
+    fn hang_reported() {
+        let hangs = Arc::new(Mutex::new(Vec::new()));
+        let current = thread::current().id();
+        let recorded = hangs.clone();
+        let watchdog = start_watchdog(Duration::from_millis(20), move |hang| {
+            // Other tests may be running coroutines meanwhile.
+            if hang.thread.id() == current {
+                recorded.lock().unwrap().push(hang.clone());
+            }
+        });
+
+        // The yielding block never runs for long at once.
+        block_on(pin!(sync(|| {
+            for _ in 0..50 {
+                spin(Duration::from_millis(2));
+                yield_now().wait();
+            }
+        })
+        .into_future()));
+        assert!(hangs.lock().unwrap().is_empty());
+
+        let spinning = sync_named("spinning", || spin(Duration::from_millis(100)));
+        block_on(pin!(spinning.into_future()));
+        drop(watchdog);
+        let hangs = hangs.lock().unwrap();
+        assert_eq!(hangs.len(), 1);
+        assert_eq!(hangs[0].name, Some("spinning"));
+        assert!(hangs[0].elapsed > Duration::from_millis(20));
+    }
+}
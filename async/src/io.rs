@@ -0,0 +1,231 @@
+//! Bridges from asynchronous I/O traits of [`tokio`] to the blocking ones in
+//! [`std::io`], based on [`AsymWait::wait`].
+
+use core::{future::poll_fn, pin::Pin, ptr, task::Poll};
+use std::io::{self, IoSlice, SeekFrom};
+
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+
+use crate::asym::AsymWait;
+
+/// A wrapper turning some [`AsyncRead`] + [`AsyncWrite`] + [`AsyncSeek`] object
+/// into its blocking counterpart.
+///
+/// All the operations wait on the underlying futures with [`AsymWait::wait`],
+/// so they suspend the current coroutine if called inside
+/// [`sync`](crate::asym::sync), or block the current thread otherwise.
+#[derive(Debug, Default)]
+pub struct BlockingSeeker<S> {
+    inner: S,
+}
+
+impl<S> BlockingSeeker<S> {
+    /// Wraps an asynchronous I/O object.
+    pub fn new(inner: S) -> Self {
+        BlockingSeeker { inner }
+    }
+
+    /// Gets a reference to the underlying I/O object.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying I/O object.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Unwraps this structure, returning the underlying I/O object.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: AsyncWrite + Unpin + Send> BlockingSeeker<S> {
+    /// Shuts down the underlying writer with [`AsyncWrite::poll_shutdown`],
+    /// e.g. flushing and closing a file, or half-closing a TCP stream.
+    ///
+    /// Dropping the writer leaves the asynchronous side unfinalized, so a sink
+    /// needing a graceful close should be shut down explicitly. No more data
+    /// should be written afterwards.
+    pub fn shutdown(&mut self) -> io::Result<()> {
+        let inner = &mut self.inner;
+        poll_fn(|cx| Pin::new(&mut *inner).poll_shutdown(cx)).wait()
+    }
+}
+
+impl<S: AsyncRead + Unpin + Send> io::Read for BlockingSeeker<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let inner = &mut self.inner;
+        poll_fn(|cx| {
+            let mut buf = ReadBuf::new(buf);
+            match Pin::new(&mut *inner).poll_read(cx, &mut buf) {
+                Poll::Ready(Ok(())) => Poll::Ready(Ok(buf.filled().len())),
+                Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+                Poll::Pending => Poll::Pending,
+            }
+        })
+        .wait()
+    }
+}
+
+impl<S: AsyncWrite + Unpin + Send> io::Write for BlockingSeeker<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let inner = &mut self.inner;
+        poll_fn(|cx| Pin::new(&mut *inner).poll_write(cx, buf)).wait()
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let inner = &mut self.inner;
+        poll_fn(|cx| Pin::new(&mut *inner).poll_write_vectored(cx, bufs)).wait()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let inner = &mut self.inner;
+        poll_fn(|cx| Pin::new(&mut *inner).poll_flush(cx)).wait()
+    }
+}
+
+impl<S: AsyncSeek + Unpin + Send> io::Seek for BlockingSeeker<S> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let inner = &mut self.inner;
+        // Complete any seek left by a previous cancelled operation before starting
+        // ours, as required by `AsyncSeek::start_seek`.
+        poll_fn(|cx| Pin::new(&mut *inner).poll_complete(cx)).wait()?;
+        Pin::new(&mut *inner).start_seek(pos)?;
+        poll_fn(|cx| Pin::new(&mut *inner).poll_complete(cx)).wait()
+    }
+}
+
+/// A wrapper turning some [`AsyncBufRead`] object into a blocking [`BufRead`],
+/// which exposes the internal buffer of the reader without another copy.
+///
+/// Like [`BlockingSeeker`], the buffer is filled by waiting with
+/// [`AsymWait::wait`].
+///
+/// [`BufRead`]: io::BufRead
+#[derive(Debug, Default)]
+pub struct BlockingBufReader<R> {
+    inner: R,
+}
+
+impl<R> BlockingBufReader<R> {
+    /// Wraps an asynchronous buffered reader.
+    pub fn new(inner: R) -> Self {
+        BlockingBufReader { inner }
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwraps this structure, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: AsyncBufRead + Unpin + Send> io::Read for BlockingBufReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = io::BufRead::fill_buf(self)?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        io::BufRead::consume(self, len);
+        Ok(len)
+    }
+}
+
+impl<R: AsyncBufRead + Unpin + Send> io::BufRead for BlockingBufReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let inner = &mut self.inner;
+        let buf = poll_fn(|cx| match Pin::new(&mut *inner).poll_fill_buf(cx) {
+            Poll::Ready(result) => Poll::Ready(result.map(ptr::from_ref)),
+            Poll::Pending => Poll::Pending,
+        })
+        .wait()?;
+        // SAFETY: The buffer lies in the reader, which stays borrowed by the
+        // returned slice.
+        Ok(unsafe { &*buf })
+    }
+
+    fn consume(&mut self, amt: usize) {
+        Pin::new(&mut self.inner).consume(amt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{future::IntoFuture, pin::pin};
+    use std::{
+        io::{BufRead, Cursor, Read, Seek, SeekFrom, Write},
+        string::String,
+        vec::Vec,
+    };
+
+    use super::{BlockingBufReader, BlockingSeeker};
+    use crate::asym::{block_on::block_on, sync};
+
+    #[test]
+    fn cursor() {
+        let mut file = BlockingSeeker::new(Cursor::new(Vec::new()));
+        block_on(pin!(sync(|| {
+            file.write_all(b"hello, unico").unwrap();
+            assert_eq!(file.seek(SeekFrom::End(-5)).unwrap(), 7);
+
+            let mut buf = [0; 5];
+            file.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, b"unico");
+            assert_eq!(file.stream_position().unwrap(), 12);
+        })
+        .into_future()));
+        assert_eq!(file.into_inner().into_inner(), b"hello, unico");
+    }
+
+    #[test]
+    fn shutdown() {
+        let (writer, reader) = tokio::io::duplex(64);
+        let (mut writer, mut reader) =
+            (BlockingSeeker::new(writer), BlockingSeeker::new(reader));
+        block_on(pin!(sync(|| {
+            writer.write_all(b"hello, unico").unwrap();
+            writer.shutdown().unwrap();
+            assert!(writer.write(b"closed").is_err());
+
+            // The reader sees the end of the stream.
+            let mut buf = Vec::new();
+            reader.read_to_end(&mut buf).unwrap();
+            assert_eq!(buf, b"hello, unico");
+        })
+        .into_future()));
+    }
+
+    #[test]
+    fn buf_reader() {
+        let source: &[u8] = b"first line\nsecond\nthird";
+        // A tiny buffer makes the lines span several fills.
+        let mut reader =
+            BlockingBufReader::new(tokio::io::BufReader::with_capacity(4, source));
+        block_on(pin!(sync(|| {
+            assert_eq!(reader.fill_buf().unwrap(), b"firs");
+            reader.consume(2);
+            assert_eq!(reader.fill_buf().unwrap(), b"rs");
+
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            assert_eq!(line, "rst line\n");
+            let lines: Vec<String> = (&mut reader).lines().map(Result::unwrap).collect();
+            assert_eq!(lines, ["second", "third"]);
+
+            // The end of the source is reached.
+            assert!(reader.fill_buf().unwrap().is_empty());
+            assert_eq!(reader.read(&mut [0; 4]).unwrap(), 0);
+        })
+        .into_future()));
+    }
+}
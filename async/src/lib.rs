@@ -9,11 +9,139 @@
 
 #[cfg(feature = "asym")]
 pub mod asym;
+#[cfg(feature = "tokio")]
+pub mod io;
+#[cfg(feature = "std")]
+pub mod runtime;
 #[cfg(feature = "sym")]
 pub mod sym;
+pub mod sync;
+#[cfg(all(feature = "asym", feature = "std"))]
+pub mod test;
 
-#[cfg(feature = "sym")]
 extern crate alloc;
 
 #[cfg(any(test, feature = "std"))]
 extern crate std;
+
+#[cfg(test)]
+mod tests {
+    use alloc::{alloc::Global, sync::Arc, task::Wake};
+    use core::{
+        cell::Cell,
+        future::Future,
+        pin::Pin,
+        ptr::NonNull,
+        sync::atomic::{AtomicUsize, Ordering::Relaxed},
+        task::{Context, Poll, Waker},
+    };
+
+    use unico_context::{
+        boost::{Boost, Fcx, NewError},
+        global_resumer, BackendInfo, Entry, Map, Resume, Transfer,
+    };
+    use unico_stack::global_stack_allocator;
+
+    global_resumer!(Counted);
+    global_stack_allocator!(Global);
+
+    std::thread_local! {
+        static CONTEXTS: Cell<isize> = const { Cell::new(0) };
+    }
+
+    /// [`Boost`], counting the contexts created on the current thread and not
+    /// deallocated yet.
+    #[derive(Clone, Copy)]
+    struct Counted;
+
+    // SAFETY: Everything is forwarded to `Boost`.
+    unsafe impl Resume for Counted {
+        type Context = Fcx;
+
+        type NewError = NewError;
+
+        fn backend_info(&self) -> BackendInfo {
+            Boost.backend_info()
+        }
+
+        unsafe fn new_on(
+            &self,
+            stack: NonNull<[u8]>,
+            entry: Entry<Fcx>,
+        ) -> Result<NonNull<Fcx>, NewError> {
+            // SAFETY: The contract is the same.
+            let cx = unsafe { Boost.new_on(stack, entry) }?;
+            CONTEXTS.set(CONTEXTS.get() + 1);
+            Ok(cx)
+        }
+
+        unsafe fn resume(&self, cx: NonNull<Fcx>, data: *mut ()) -> Transfer<Fcx> {
+            // SAFETY: The contract is the same.
+            unsafe { Boost.resume(cx, data) }
+        }
+
+        unsafe fn resume_with(
+            &self,
+            cx: NonNull<Fcx>,
+            data: *mut (),
+            map: Map<Fcx>,
+        ) -> Transfer<Fcx> {
+            // SAFETY: The contract is the same.
+            unsafe { Boost.resume_with(cx, data, map) }
+        }
+
+        unsafe fn dealloc_context(&self, cx: NonNull<Fcx>) {
+            CONTEXTS.set(CONTEXTS.get() - 1);
+            // SAFETY: The contract is the same.
+            unsafe { Boost.dealloc_context(cx) }
+        }
+    }
+
+    /// The number of the contexts created on the current thread and not
+    /// deallocated yet, which may be negative if some of them are deallocated
+    /// on another thread.
+    pub(crate) fn live_contexts() -> isize {
+        CONTEXTS.get()
+    }
+
+    struct Noop;
+
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    /// A waker doing nothing, for the futures polled by hand.
+    pub(crate) fn noop_waker() -> Waker {
+        Waker::from(Arc::new(Noop))
+    }
+
+    /// Counts its own drops, which should happen exactly once on every exit
+    /// path of the coroutine or the future holding it.
+    pub(crate) struct DropCounter<'a>(pub(crate) &'a AtomicUsize);
+
+    impl Drop for DropCounter<'_> {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Relaxed);
+        }
+    }
+
+    /// Yields once to the executor: pending once, waking itself right away.
+    pub(crate) fn yield_now() -> YieldNow {
+        YieldNow(false)
+    }
+
+    pub(crate) struct YieldNow(bool);
+
+    impl Future for YieldNow {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                return Poll::Ready(());
+            }
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
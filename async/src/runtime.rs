@@ -0,0 +1,278 @@
+//! A lightweight runtime driving futures on the current thread.
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+};
+use core::{
+    cell::{Cell, RefCell},
+    fmt,
+    future::{Future, IntoFuture},
+    pin::{pin, Pin},
+    sync::atomic::{AtomicBool, Ordering::*},
+    task::{Context, Poll, Waker},
+};
+use std::{
+    sync::{Condvar, Mutex},
+    task::Wake,
+};
+
+type Task = Pin<Box<dyn Future<Output = ()>>>;
+
+/// The id of the future driven by [`LocalRuntime::block_on`], apart from the
+/// ids of the spawned tasks.
+const MAIN: usize = usize::MAX;
+
+/// The queue of the woken futures, shared by the runtime and the wakers.
+#[derive(Default)]
+struct Shared {
+    ready: Mutex<VecDeque<usize>>,
+    condvar: Condvar,
+}
+
+/// The waker of a future in [`LocalRuntime`], which queues its id for the
+/// next poll.
+struct TaskWaker {
+    id: usize,
+    /// Whether the id is in the queue, so that waking a future many times
+    /// before it's polled queues it only once.
+    queued: AtomicBool,
+    shared: Arc<Shared>,
+}
+
+impl TaskWaker {
+    fn new(id: usize, shared: &Arc<Shared>) -> Arc<Self> {
+        Arc::new(TaskWaker {
+            id,
+            queued: AtomicBool::new(false),
+            shared: shared.clone(),
+        })
+    }
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.queued.swap(true, AcqRel) {
+            self.shared.ready.lock().unwrap().push_back(self.id);
+            self.shared.condvar.notify_one();
+        }
+    }
+}
+
+/// A single-threaded runtime, which polls the futures only when they're woken,
+/// and parks the thread otherwise.
+///
+/// Unlike a busy-wait executor like `spin_on`, the thread sleeps on a condvar
+/// while every future is pending, and is notified by the wakers, possibly
+/// from other threads, e.g. a timer or another runtime. This suits the blocks
+/// of [`sync`](crate::asym::sync) code, which are simply polled by the thread
+/// calling [`LocalRuntime::block_on`], without pulling in `tokio`.
+///
+/// Besides the future it blocks on, the runtime drives the tasks spawned with
+/// [`LocalRuntime::spawn`] meanwhile, which need not be [`Send`]. The tasks
+/// unfinished when the runtime is dropped are dropped along with it.
+///
+/// ```rust
+/// # #![feature(allocator_api)]
+/// # unico_stack::global_stack_allocator!(std::alloc::Global);
+/// # unico_context::global_resumer!(unico_context::boost::Boost);
+/// use unico_async::{
+///     asym::{sync, AsymWait},
+///     runtime::LocalRuntime,
+/// };
+///
+/// let runtime = LocalRuntime::new();
+/// let (tx, rx) = smol::channel::bounded(1);
+/// runtime.spawn(async move { tx.send(42).await.unwrap() });
+///
+/// let ret = runtime.block_on(sync(|| rx.recv().wait().unwrap() + 1));
+/// assert_eq!(ret, 43);
+/// ```
+pub struct LocalRuntime {
+    shared: Arc<Shared>,
+    tasks: RefCell<BTreeMap<usize, (Task, Arc<TaskWaker>)>>,
+    next_id: Cell<usize>,
+}
+
+impl LocalRuntime {
+    /// Creates a runtime with no task.
+    pub fn new() -> Self {
+        LocalRuntime {
+            shared: Arc::default(),
+            tasks: RefCell::new(BTreeMap::new()),
+            next_id: Cell::new(0),
+        }
+    }
+
+    /// The number of the spawned tasks not finished yet.
+    pub fn len(&self) -> usize {
+        self.tasks.borrow().len()
+    }
+
+    /// Whether every spawned task is finished.
+    pub fn is_empty(&self) -> bool {
+        self.tasks.borrow().is_empty()
+    }
+
+    /// Spawns `future` onto the runtime, to be run along with the future
+    /// blocked on by [`LocalRuntime::block_on`] without anyone awaiting it.
+    ///
+    /// The task makes progress only while some thread blocks on the runtime.
+    pub fn spawn(&self, future: impl IntoFuture<Output = ()> + 'static) {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        let waker = TaskWaker::new(id, &self.shared);
+        let task: Task = Box::pin(future.into_future());
+        self.tasks.borrow_mut().insert(id, (task, waker.clone()));
+        waker.wake();
+    }
+
+    /// Runs `future` to completion on the current thread, along with the
+    /// spawned tasks, and returns its output.
+    ///
+    /// The thread is parked whenever no future is woken, and the spawned tasks
+    /// unfinished when `future` completes are retained for the next call.
+    pub fn block_on<F: IntoFuture>(&self, future: F) -> F::Output {
+        let mut future = pin!(future.into_future());
+        let main = TaskWaker::new(MAIN, &self.shared);
+        let waker = Waker::from(main.clone());
+        let cx = &mut Context::from_waker(&waker);
+        // Poll the future right away, without a detour through the queue.
+        let mut id = MAIN;
+        loop {
+            // The ids of the futures of the previous calls are also `MAIN`,
+            // which cost a spurious poll at most.
+            if id == MAIN {
+                main.queued.store(false, Release);
+                if let Poll::Ready(output) = future.as_mut().poll(cx) {
+                    return output;
+                }
+            } else {
+                self.poll_task(id);
+            }
+            id = self.next_ready();
+        }
+    }
+
+    /// Pops the id of the next future woken, and parks the thread until there
+    /// is one.
+    fn next_ready(&self) -> usize {
+        let mut ready = self.shared.ready.lock().unwrap();
+        loop {
+            if let Some(id) = ready.pop_front() {
+                return id;
+            }
+            ready = self.shared.condvar.wait(ready).unwrap();
+        }
+    }
+
+    fn poll_task(&self, id: usize) {
+        // The task is taken out while polled, so that it may spawn others. The
+        // id of a task woken after it's finished finds nothing.
+        let Some((mut task, waker)) = self.tasks.borrow_mut().remove(&id) else {
+            return;
+        };
+        waker.queued.store(false, Release);
+        let task_waker = Waker::from(waker.clone());
+        let cx = &mut Context::from_waker(&task_waker);
+        if task.as_mut().poll(cx).is_pending() {
+            self.tasks.borrow_mut().insert(id, (task, waker));
+        }
+    }
+}
+
+impl Default for LocalRuntime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for LocalRuntime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalRuntime")
+            .field("tasks", &self.len())
+            .finish()
+    }
+}
+
+/// Runs `future` to completion on a fresh [`LocalRuntime`], and returns its
+/// output.
+///
+/// ```rust
+/// # #![feature(allocator_api)]
+/// # unico_stack::global_stack_allocator!(std::alloc::Global);
+/// # unico_context::global_resumer!(unico_context::boost::Boost);
+/// use unico_async::{
+///     asym::{sync, AsymWait},
+///     runtime,
+/// };
+///
+/// let ret = runtime::block_on(sync(|| async { 42 }.wait()));
+/// assert_eq!(ret, 42);
+/// ```
+pub fn block_on<F: IntoFuture>(future: F) -> F::Output {
+    LocalRuntime::new().block_on(future)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{rc::Rc, vec::Vec};
+    use core::{cell::RefCell, future::poll_fn, task::Poll, time::Duration};
+    use std::{sync::mpsc, thread};
+
+    use super::LocalRuntime;
+    use crate::{
+        asym::{sync, AsymWait},
+        tests::yield_now,
+    };
+
+    #[test]
+    fn parked() {
+        let runtime = LocalRuntime::new();
+        let (tx, rx) = mpsc::channel();
+        let mut polls = 0;
+        let ret = runtime.block_on(poll_fn(|cx| {
+            polls += 1;
+            if let Ok(ret) = rx.try_recv() {
+                return Poll::Ready(ret);
+            }
+            let (waker, tx) = (cx.waker().clone(), tx.clone());
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                tx.send(42).unwrap();
+                waker.wake();
+            });
+            Poll::Pending
+        }));
+        assert_eq!(ret, 42);
+        // Polled only when woken, instead of spinning meanwhile.
+        assert_eq!(polls, 2);
+    }
+
+    #[test]
+    fn spawned() {
+        let runtime = LocalRuntime::new();
+        let order = Rc::new(RefCell::new(Vec::new()));
+        for index in 0..3 {
+            let order = order.clone();
+            runtime.spawn(async move {
+                yield_now().await;
+                order.borrow_mut().push(index);
+            });
+        }
+        assert_eq!(runtime.len(), 3);
+
+        let ret = runtime.block_on(sync(|| {
+            (0..10).for_each(|_| yield_now().wait());
+            7
+        }));
+        assert_eq!(ret, 7);
+        assert!(runtime.is_empty());
+        assert_eq!(*order.borrow(), [0, 1, 2]);
+    }
+}
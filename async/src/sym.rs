@@ -6,6 +6,7 @@
 //! sync/async channel that sends the result to somewhere.
 
 mod cx;
+mod deterministic;
 
 use core::{
     future::{Future, IntoFuture},
@@ -20,9 +21,12 @@ use unico_ful::{
     sym::{Co, PanicHook},
     Builder, NewError,
 };
-use unico_stack::Stack;
+use unico_stack::IntoStack;
 
-pub use self::cx::{SchedContext, WakerRef};
+pub use self::{
+    cx::{SchedContext, WakerRef},
+    deterministic::{Choice, DeterministicScheduler},
+};
 
 /// A task that can be spawned in a scheduler.
 #[derive(Debug)]
@@ -176,7 +180,7 @@ pub trait SchedulerExt: Scheduler {
     ) -> Result<Task<Self::Metadata>, NewError>
     where
         F: FnOnce(&mut SchedContext<Self>) + Send + 'static,
-        S: Into<Stack>,
+        S: IntoStack,
         P: PanicHook,
         Self: Send + 'static,
     {
@@ -221,19 +225,14 @@ impl<F: Future + Send + Sized> SymWait for F {}
 
 #[cfg(test)]
 mod tests {
-    use alloc::{alloc::Global, collections::VecDeque, sync::Arc};
+    use alloc::{collections::VecDeque, sync::Arc};
     use std::println;
 
     use spin::Mutex;
-    use unico_context::{boost::Boost, global_resumer};
     use unico_ful::Builder;
-    use unico_stack::global_stack_allocator;
 
     use super::{Scheduler, SchedulerExt, Task};
 
-    global_resumer!(Boost);
-    global_stack_allocator!(Global);
-
     struct Fifo(Mutex<VecDeque<Task>>);
 
     impl Scheduler for Fifo {
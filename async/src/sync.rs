@@ -0,0 +1,538 @@
+//! Synchronization primitives aware of stackful coroutines.
+//!
+//! Blocking primitives like `std::sync::Mutex` hold the whole execution unit
+//! when contended, while the actual owner may be a suspended coroutine on the
+//! very same thread, resulting in a deadlock. The primitives in this module
+//! are futures instead, so waiting on them with `.wait()` suspends only the
+//! current coroutine.
+
+use alloc::collections::BTreeMap;
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    future::Future,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use spin::Mutex;
+
+#[derive(Default)]
+struct State {
+    locked: bool,
+    /// The next key for some waiting locker, which increases monotonically so
+    /// that the waiters are woken in FIFO order.
+    next: u64,
+    waiters: BTreeMap<u64, Waker>,
+}
+
+impl State {
+    fn wake_first(&self) {
+        if let Some(waker) = self.waiters.values().next() {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+/// An asynchronous mutex that can be held across `.wait()`.
+///
+/// This is the coroutine analog of `tokio::sync::Mutex`. [`CoMutex::lock`]
+/// returns a future, so inside a `sync` block it should be locked with
+/// `mutex.lock().wait()`. If the mutex is held by another coroutine, the
+/// current coroutine is suspended until the guard is released, allowing the
+/// owner to make progress on the same thread.
+///
+/// The mutex is not reentrant: locking it again in the owning coroutine will
+/// wait forever.
+///
+/// Unlike `std::sync::Mutex`, the mutex is never poisoned. If the owning
+/// coroutine panics, its guard is dropped while the coroutine unwinds, before
+/// the panic reaches the task polling it, and the next waiter is woken as
+/// usual. The data may be left half-updated though.
+pub struct CoMutex<T: ?Sized> {
+    state: Mutex<State>,
+    data: UnsafeCell<T>,
+}
+
+// SAFETY: The data is only accessed through a unique guard.
+unsafe impl<T: ?Sized + Send> Send for CoMutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for CoMutex<T> {}
+
+impl<T> CoMutex<T> {
+    /// Creates a new mutex in an unlocked state.
+    pub fn new(data: T) -> Self {
+        CoMutex {
+            state: Mutex::new(State::default()),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Consumes the mutex, returning the underlying data.
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> CoMutex<T> {
+    /// Acquires the mutex asynchronously.
+    ///
+    /// The returned future resolves to a guard once the lock is acquired. If
+    /// the future is dropped before that, it leaves the queue of waiters
+    /// without acquiring the lock.
+    pub fn lock(&self) -> Lock<'_, T> {
+        Lock {
+            mutex: self,
+            key: None,
+        }
+    }
+
+    /// Attempts to acquire the mutex without waiting.
+    pub fn try_lock(&self) -> Option<CoMutexGuard<'_, T>> {
+        let mut state = self.state.lock();
+        if state.locked {
+            return None;
+        }
+        state.locked = true;
+        Some(CoMutexGuard { mutex: self })
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    ///
+    /// No locking is needed since the mutex is borrowed mutably.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+}
+
+impl<T: Default> Default for CoMutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for CoMutex<T> {
+    fn from(data: T) -> Self {
+        Self::new(data)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for CoMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("CoMutex");
+        match self.try_lock() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+        d.finish_non_exhaustive()
+    }
+}
+
+/// The future returned by [`CoMutex::lock`].
+#[must_use = "futures do nothing unless you `.await` or `.wait()` them"]
+pub struct Lock<'a, T: ?Sized> {
+    mutex: &'a CoMutex<T>,
+    key: Option<u64>,
+}
+
+impl<'a, T: ?Sized> Future for Lock<'a, T> {
+    type Output = CoMutexGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mutex = self.mutex;
+        let mut state = mutex.state.lock();
+        if !state.locked {
+            state.locked = true;
+            if let Some(key) = self.key.take() {
+                state.waiters.remove(&key);
+            }
+            return Poll::Ready(CoMutexGuard { mutex });
+        }
+
+        match self.key {
+            Some(key) => match state.waiters.get_mut(&key) {
+                Some(waker) => waker.clone_from(cx.waker()),
+                None => unreachable!("a waiting locker lost its waker"),
+            },
+            None => {
+                let key = state.next;
+                state.next += 1;
+                state.waiters.insert(key, cx.waker().clone());
+                self.key = Some(key);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl<T: ?Sized> Drop for Lock<'_, T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            let mut state = self.mutex.state.lock();
+            state.waiters.remove(&key);
+            // We may have been woken for an unlocked mutex, so pass the chance on.
+            if !state.locked {
+                state.wake_first();
+            }
+        }
+    }
+}
+
+/// The guard of a locked [`CoMutex`], which releases the lock on drop.
+pub struct CoMutexGuard<'a, T: ?Sized> {
+    mutex: &'a CoMutex<T>,
+}
+
+// SAFETY: The guard only gives out references of the data.
+unsafe impl<T: ?Sized + Sync> Sync for CoMutexGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for CoMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: The lock is held by this guard.
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for CoMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: The lock is held by this guard.
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for CoMutexGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized> Drop for CoMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        let mut state = self.mutex.state.lock();
+        state.locked = false;
+        state.wake_first();
+    }
+}
+
+/// A waiter of some [`CoCondvar`].
+struct Waiter {
+    waker: Waker,
+    notified: bool,
+}
+
+#[derive(Default)]
+struct Waiters {
+    /// The next key for some waiter, which increases monotonically so that the
+    /// waiters are notified in FIFO order.
+    next: u64,
+    waiters: BTreeMap<u64, Waiter>,
+}
+
+impl Waiters {
+    fn notify_first(&mut self) {
+        let first = self.waiters.values_mut().find(|waiter| !waiter.notified);
+        if let Some(waiter) = first {
+            waiter.notified = true;
+            waiter.waker.wake_by_ref();
+        }
+    }
+}
+
+/// An asynchronous condition variable paired with a [`CoMutex`].
+///
+/// This is the coroutine analog of `std::sync::Condvar`. [`CoCondvar::wait`]
+/// returns a future, so inside a `sync` block it should be waited with
+/// `condvar.wait(guard).wait()`, which suspends only the current coroutine
+/// until it's notified, letting the others on the same thread run.
+///
+/// There are no spurious wakeups, but the condition may be changed by another
+/// coroutine between the notification and the relocking, so it should still be
+/// checked in a loop.
+#[derive(Default)]
+pub struct CoCondvar {
+    state: Mutex<Waiters>,
+}
+
+impl CoCondvar {
+    /// Creates a new condition variable without waiters.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Releases the lock of `guard` and waits for a notification
+    /// asynchronously.
+    ///
+    /// The returned future resolves to the guard of the mutex relocked after
+    /// the notification. The lock is released on the first poll of the future,
+    /// once it's registered as a waiter, so no notification in between is
+    /// missed. If the future is dropped after it's notified, the notification
+    /// is passed on to the next waiter.
+    pub fn wait<'a, T: ?Sized>(&'a self, guard: CoMutexGuard<'a, T>) -> Wait<'a, T> {
+        Wait {
+            condvar: self,
+            mutex: guard.mutex,
+            guard: Some(guard),
+            key: None,
+            lock: None,
+        }
+    }
+
+    /// Wakes up the first waiter not notified yet, if any.
+    pub fn notify_one(&self) {
+        self.state.lock().notify_first();
+    }
+
+    /// Wakes up all the current waiters.
+    pub fn notify_all(&self) {
+        let mut state = self.state.lock();
+        for waiter in state.waiters.values_mut() {
+            if !waiter.notified {
+                waiter.notified = true;
+                waiter.waker.wake_by_ref();
+            }
+        }
+    }
+}
+
+impl fmt::Debug for CoCondvar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CoCondvar").finish_non_exhaustive()
+    }
+}
+
+/// The future returned by [`CoCondvar::wait`].
+#[must_use = "futures do nothing unless you `.await` or `.wait()` them"]
+pub struct Wait<'a, T: ?Sized> {
+    condvar: &'a CoCondvar,
+    mutex: &'a CoMutex<T>,
+    guard: Option<CoMutexGuard<'a, T>>,
+    key: Option<u64>,
+    lock: Option<Lock<'a, T>>,
+}
+
+impl<'a, T: ?Sized> Future for Wait<'a, T> {
+    type Output = CoMutexGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if let Some(guard) = self.guard.take() {
+            let mut state = self.condvar.state.lock();
+            let key = state.next;
+            state.next += 1;
+            let waker = cx.waker().clone();
+            let waiter = Waiter {
+                waker,
+                notified: false,
+            };
+            state.waiters.insert(key, waiter);
+            drop(state);
+            self.key = Some(key);
+            drop(guard);
+            return Poll::Pending;
+        }
+
+        if let Some(key) = self.key {
+            let mut state = self.condvar.state.lock();
+            match state.waiters.get_mut(&key) {
+                Some(waiter) if waiter.notified => {
+                    state.waiters.remove(&key);
+                    drop(state);
+                    self.key = None;
+                    self.lock = Some(self.mutex.lock());
+                }
+                Some(waiter) => {
+                    waiter.waker.clone_from(cx.waker());
+                    return Poll::Pending;
+                }
+                None => unreachable!("a waiter of a condvar lost its waker"),
+            }
+        }
+
+        match &mut self.lock {
+            Some(lock) => Pin::new(lock).poll(cx),
+            None => panic!("`Wait` polled after completion"),
+        }
+    }
+}
+
+impl<T: ?Sized> Drop for Wait<'_, T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            let mut state = self.condvar.state.lock();
+            // A notification consumed by this waiter is passed on.
+            let waiter = state.waiters.remove(&key);
+            if waiter.is_some_and(|waiter| waiter.notified) {
+                state.notify_first();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{sync::Arc, task::Wake};
+    use core::{
+        future::Future,
+        pin::{pin, Pin},
+        sync::atomic::{AtomicUsize, Ordering::SeqCst},
+        task::{Context, Poll, Waker},
+    };
+
+    use super::{CoCondvar, CoMutex};
+    use crate::asym::{block_on::block_on, sync, AsymWait};
+
+    struct Counter(AtomicUsize);
+
+    impl Wake for Counter {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, SeqCst);
+        }
+    }
+
+    #[test]
+    fn contended() {
+        let counter = Arc::new(Counter(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let cx = &mut Context::from_waker(&waker);
+
+        let mutex = CoMutex::new(0);
+        let mut guard = mutex.try_lock().unwrap();
+        *guard += 1;
+
+        let mut l1 = pin!(mutex.lock());
+        let mut l2 = pin!(mutex.lock());
+        assert!(l1.as_mut().poll(cx).is_pending());
+        assert!(l2.as_mut().poll(cx).is_pending());
+        assert!(mutex.try_lock().is_none());
+
+        drop(guard);
+        assert_eq!(counter.0.load(SeqCst), 1);
+
+        let Poll::Ready(mut guard) = l1.as_mut().poll(cx) else {
+            panic!("the first locker should acquire the lock")
+        };
+        *guard += 1;
+        assert!(l2.as_mut().poll(cx).is_pending());
+
+        drop(guard);
+        assert_eq!(counter.0.load(SeqCst), 2);
+        let Poll::Ready(guard) = l2.as_mut().poll(cx) else {
+            panic!("the second locker should acquire the lock")
+        };
+        assert_eq!(*guard, 2);
+    }
+
+    #[test]
+    fn cancelled() {
+        let counter = Arc::new(Counter(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let cx = &mut Context::from_waker(&waker);
+
+        let mutex = CoMutex::new(());
+        let guard = mutex.try_lock().unwrap();
+        let mut l2 = pin!(mutex.lock());
+        {
+            let mut l1 = pin!(mutex.lock());
+            assert!(l1.as_mut().poll(cx).is_pending());
+            assert!(l2.as_mut().poll(cx).is_pending());
+            drop(guard);
+        }
+        // The woken but cancelled locker passes the chance on.
+        assert_eq!(counter.0.load(SeqCst), 2);
+        assert!(l2.as_mut().poll(cx).is_ready());
+    }
+
+    #[test]
+    fn notified() {
+        let counter = Arc::new(Counter(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let cx = &mut Context::from_waker(&waker);
+
+        let (mutex, condvar) = (CoMutex::new(false), CoCondvar::new());
+        let mut w1 = pin!(condvar.wait(mutex.try_lock().unwrap()));
+        assert!(w1.as_mut().poll(cx).is_pending());
+        let mut w2 = pin!(condvar.wait(mutex.try_lock().unwrap()));
+        assert!(w2.as_mut().poll(cx).is_pending());
+
+        // The lock is released while waiting.
+        *mutex.try_lock().unwrap() = true;
+        assert!(w1.as_mut().poll(cx).is_pending());
+        assert_eq!(counter.0.load(SeqCst), 0);
+
+        condvar.notify_one();
+        assert_eq!(counter.0.load(SeqCst), 1);
+        let guard = mutex.try_lock().unwrap();
+        // The notified waiter waits for the lock then, and the other one is
+        // still waiting for a notification.
+        assert!(w1.as_mut().poll(cx).is_pending());
+        assert!(w2.as_mut().poll(cx).is_pending());
+        drop(guard);
+
+        let Poll::Ready(guard) = w1.as_mut().poll(cx) else {
+            panic!("the notified waiter should relock the mutex")
+        };
+        assert!(*guard);
+        drop(guard);
+        assert!(w2.as_mut().poll(cx).is_pending());
+    }
+
+    #[test]
+    fn notify_all_and_cancel() {
+        let counter = Arc::new(Counter(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let cx = &mut Context::from_waker(&waker);
+
+        let (mutex, condvar) = (CoMutex::new(()), CoCondvar::new());
+        let waits: [_; 3] = core::array::from_fn(|_| {
+            let mut wait = condvar.wait(mutex.try_lock().unwrap());
+            assert!(Pin::new(&mut wait).poll(cx).is_pending());
+            wait
+        });
+        condvar.notify_one();
+        let [w1, mut w2, mut w3] = waits;
+        drop(w1);
+        // The notified but cancelled waiter passes the notification on.
+        assert_eq!(counter.0.load(SeqCst), 2);
+        assert!(Pin::new(&mut w2).poll(cx).is_ready());
+
+        condvar.notify_all();
+        assert_eq!(counter.0.load(SeqCst), 3);
+        assert!(Pin::new(&mut w3).poll(cx).is_ready());
+    }
+
+    #[test]
+    fn panicked_owner() {
+        use core::future::IntoFuture;
+        use std::panic::AssertUnwindSafe;
+
+        use smol::future::{zip, FutureExt};
+
+        use crate::tests::yield_now;
+
+        let mutex = CoMutex::new(0);
+        let owner = AssertUnwindSafe(
+            sync(|| {
+                let mut guard = mutex.lock().wait();
+                *guard += 1;
+                // Let the sibling wait for the lock.
+                yield_now().wait();
+                panic!("holding the lock")
+            })
+            .into_future(),
+        );
+        let sibling = sync(|| {
+            let mut guard = mutex.lock().wait();
+            *guard += 1;
+            *guard
+        })
+        .into_future();
+
+        let (owner, sibling) = block_on(pin!(zip(owner.catch_unwind(), sibling)));
+        let payload = owner.unwrap_err();
+        assert_eq!(payload.downcast_ref(), Some(&"holding the lock"));
+        // The guard is released while unwinding, and the sibling acquires it.
+        assert_eq!(sibling, 2);
+        assert_eq!(*mutex.try_lock().unwrap(), 2);
+    }
+}
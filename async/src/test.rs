@@ -0,0 +1,37 @@
+//! Utilities for testing the code built on stackful coroutines.
+//!
+//! A panic escaping a block of [`sync`](crate::asym::sync) code unwinds
+//! through the poll of the awaiting future with its original payload, so
+//! [`block_on`] is all a test needs: the test fails with the message of the
+//! panic, and works with `#[should_panic(expected = ..)]` as usual. Unlike the
+//! runtime of `#[tokio::test]`, no task is involved, so nothing catches the
+//! panic on the way.
+//!
+//! ```rust,should_panic
+//! # #![feature(allocator_api)]
+//! # unico_stack::global_stack_allocator!(std::alloc::Global);
+//! # unico_context::global_resumer!(unico_context::boost::Boost);
+//! use unico_async::{asym::sync, test};
+//!
+//! test::block_on(async { sync(|| panic!("boom")).await });
+//! ```
+
+pub use crate::runtime::block_on;
+
+#[cfg(test)]
+mod tests {
+    use super::block_on;
+    use crate::asym::{sync, AsymWait};
+
+    #[test]
+    fn output() {
+        let ret = block_on(async { sync(|| async { 42 }.wait()).await });
+        assert_eq!(ret, 42);
+    }
+
+    #[test]
+    #[should_panic(expected = "boom")]
+    fn panic_resumed() {
+        block_on(async { sync(|| panic!("boom")).await });
+    }
+}
@@ -1,6 +1,6 @@
-use core::{mem, ptr::NonNull};
+use core::{fmt, mem, ptr::NonNull};
 
-use crate::{stack_top, Entry, Map, Resume};
+use crate::{stack_top, BackendInfo, Entry, Map, Resume};
 
 const CONTEXT_SIZE: usize = include!(concat!(env!("OUT_DIR"), "/context_size.txt"));
 const CONTEXT_LEN: usize = CONTEXT_SIZE / mem::size_of::<usize>();
@@ -35,7 +35,20 @@ pub struct Boost;
 
 #[derive(Debug)]
 pub enum NewError {
-    StackTooSmall,
+    /// The stack cannot hold the context, which needs at least `minimum`
+    /// bytes.
+    StackTooSmall { provided: usize, minimum: usize },
+}
+
+impl fmt::Display for NewError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NewError::StackTooSmall { provided, minimum } => write!(
+                f,
+                "the stack is too small: got {provided} bytes, need at least {minimum}"
+            ),
+        }
+    }
 }
 
 // SAFETY: `Fcx` is created from `stack`. See Boost's assembly file for more
@@ -45,12 +58,24 @@ unsafe impl Resume for Boost {
 
     type NewError = NewError;
 
+    fn backend_info(&self) -> BackendInfo {
+        BackendInfo {
+            name: "boost",
+            thread_affine: false,
+            saves_fp: true,
+        }
+    }
+
     unsafe fn new_on(
         &self,
         stack: NonNull<[u8]>,
         entry: Entry<Fcx>,
     ) -> Result<NonNull<Fcx>, NewError> {
-        let top: NonNull<()> = stack_top(stack).ok_or(NewError::StackTooSmall)?;
+        let top: NonNull<()> =
+            stack_top(stack).map_err(|minimum| NewError::StackTooSmall {
+                provided: stack.len(),
+                minimum,
+            })?;
         // SAFETY: The stack is valid by contract.
         Ok(unsafe { self::new_on(top, stack.len(), entry) })
     }
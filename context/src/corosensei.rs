@@ -0,0 +1,233 @@
+use core::{
+    cell::Cell,
+    fmt,
+    mem::{self, ManuallyDrop, MaybeUninit},
+    num::NonZeroUsize,
+    ptr::NonNull,
+};
+
+use ::corosensei::{
+    stack::{Stack, StackPointer, MIN_STACK_SIZE},
+    CoroutineResult, ScopedCoroutine, Yielder,
+};
+
+use crate::{stack_top, BackendInfo, Entry, Map, Resume};
+
+type Transfer = crate::Transfer<Cx>;
+
+/// The minimum size of a stack, which holds the context and the smallest stack
+/// of a coroutine, both aligned.
+const MINIMUM: usize =
+    mem::size_of::<Cx>() + MIN_STACK_SIZE + 2 * crate::page::STACK_ALIGN;
+
+std::thread_local! {
+    /// The context running on the current thread, or `None` if it's the root
+    /// one, i.e. the native stack of the thread.
+    static CURRENT: Cell<Option<NonNull<Cx>>> = const { Cell::new(None) };
+}
+
+/// A switch between contexts, sent to the destination or yielded to the root
+/// context.
+///
+/// When sent, `cx` is the context switched from; when yielded, it's the one
+/// to be switched to.
+struct Switch {
+    cx: NonNull<Cx>,
+    data: *mut (),
+    map: Option<Map<Cx>>,
+}
+
+impl Switch {
+    /// Receives the switch on the destination stack.
+    ///
+    /// # Safety
+    ///
+    /// `self.map` must be valid to be called with `self.cx` and `self.data`.
+    unsafe fn receive(self) -> Transfer {
+        match self.map {
+            // SAFETY: `map` is valid by contract.
+            Some(map) => unsafe { map(self.cx, self.data) },
+            None => Transfer::new(Some(self.cx), self.data),
+        }
+    }
+}
+
+/// The part of the stack below the context, on which the coroutine runs.
+struct Region {
+    base: StackPointer,
+    limit: StackPointer,
+}
+
+// SAFETY: The region lies in the stack passed to `Resume::new_on`, and both
+// ends are aligned.
+unsafe impl Stack for Region {
+    fn base(&self) -> StackPointer {
+        self.base
+    }
+
+    fn limit(&self) -> StackPointer {
+        self.limit
+    }
+}
+
+type Fiber = ScopedCoroutine<'static, Switch, Switch, (), Region>;
+
+/// A context running on a [`corosensei`](::corosensei) coroutine.
+///
+/// Since the coroutines are asymmetric, every switch is relayed by the root
+/// context of the thread, which resumes the destination on behalf of the
+/// source. Thus a context must be resumed on the thread where it's created.
+pub struct Cx {
+    /// The coroutine, which is never dropped since it's abandoned in the middle
+    /// of its entry.
+    fiber: ManuallyDrop<Fiber>,
+    yielder: Cell<Option<NonNull<Yielder<Switch, Switch>>>>,
+}
+
+impl fmt::Debug for Cx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cx").finish_non_exhaustive()
+    }
+}
+
+/// The [`Resume`] implementation with the [`corosensei`](https://github.com/Amanieu/corosensei)
+/// coroutines, mostly used to validate the other backends against.
+///
+/// The contexts are thread-affine. See [`Cx`] for more information.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Corosensei;
+
+#[derive(Debug)]
+pub enum NewError {
+    /// The stack cannot hold the context, which needs at least `minimum`
+    /// bytes.
+    StackTooSmall { provided: usize, minimum: usize },
+}
+
+impl fmt::Display for NewError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NewError::StackTooSmall { provided, minimum } => write!(
+                f,
+                "the stack is too small: got {provided} bytes, need at least {minimum}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NewError {}
+
+/// # Safety
+///
+/// See [`Resume::resume_with`] for more information.
+unsafe fn switch(target: NonNull<Cx>, data: *mut (), map: Option<Map<Cx>>) -> Transfer {
+    let mut switch = Switch {
+        cx: target,
+        data,
+        map,
+    };
+    if let Some(current) = CURRENT.get() {
+        // SAFETY: The running context is valid, and so is its yielder, which lies
+        // on its stack.
+        let yielder = unsafe { current.as_ref().yielder.get() };
+        let yielder = yielder.expect("a context is running without its yielder");
+        // SAFETY: The root context relays the switch, and the received one is
+        // valid by the contract of the sender.
+        return unsafe { yielder.as_ref().suspend(switch).receive() };
+    }
+
+    // The root context has no coroutine, so it's identified by a slot on its
+    // stack, which is resumed only through the relay below.
+    let mut root = MaybeUninit::<Cx>::uninit();
+    let root = NonNull::from(&mut root).cast::<Cx>();
+    switch.cx = root;
+    let mut to = target;
+    loop {
+        assert_ne!(to, root, "a root context is resumed by itself");
+        CURRENT.set(Some(to));
+        // SAFETY: `to` is valid by contract.
+        let state = unsafe { (*to.as_ptr()).fiber.resume(switch) };
+        CURRENT.set(None);
+        let CoroutineResult::Yield(next) = state else {
+            unreachable!("the entry of a context returned")
+        };
+        switch = Switch { cx: to, ..next };
+        if next.cx == root {
+            // SAFETY: The switch is valid by the contract of the sender.
+            return unsafe { switch.receive() };
+        }
+        to = next.cx;
+    }
+}
+
+// SAFETY: The coroutine runs on the given stack, below the context itself.
+unsafe impl Resume for Corosensei {
+    type Context = Cx;
+
+    type NewError = NewError;
+
+    fn backend_info(&self) -> BackendInfo {
+        BackendInfo {
+            name: "corosensei",
+            thread_affine: true,
+            saves_fp: false,
+        }
+    }
+
+    unsafe fn new_on(
+        &self,
+        stack: NonNull<[u8]>,
+        entry: Entry<Cx>,
+    ) -> Result<NonNull<Cx>, NewError> {
+        let too_small = || NewError::StackTooSmall {
+            provided: stack.len(),
+            minimum: MINIMUM,
+        };
+        let pointer: NonNull<Cx> = stack_top(stack).map_err(|_| too_small())?;
+        let base = pointer.addr();
+        let limit = (stack.as_non_null_ptr().addr().get())
+            .checked_next_multiple_of(crate::page::STACK_ALIGN)
+            .and_then(NonZeroUsize::new)
+            .filter(|limit| limit.get() + MIN_STACK_SIZE <= base.get())
+            .ok_or_else(too_small)?;
+
+        let fiber = Fiber::with_stack(Region { base, limit }, move |yielder, first| {
+            // SAFETY: The context outlives its coroutine.
+            unsafe {
+                (*pointer.as_ptr())
+                    .yielder
+                    .set(Some(NonNull::from(yielder)))
+            };
+            // SAFETY: The switch is valid by the contract of the sender.
+            let t = unsafe { first.receive() };
+            let cx = t
+                .context
+                .expect("a new context is started without its source");
+            // SAFETY: `entry` is valid by contract.
+            unsafe { entry(cx, t.data) }
+        });
+        // SAFETY: The context is placed on the top of the stack by `stack_top`.
+        unsafe {
+            pointer.write(Cx {
+                fiber: ManuallyDrop::new(fiber),
+                yielder: Cell::new(None),
+            })
+        };
+        Ok(pointer)
+    }
+
+    unsafe fn resume(&self, cx: NonNull<Cx>, data: *mut ()) -> Transfer {
+        // SAFETY: `cx` is valid by contract.
+        unsafe { switch(cx, data, None) }
+    }
+
+    unsafe fn resume_with(
+        &self,
+        cx: NonNull<Cx>,
+        data: *mut (),
+        map: Map<Cx>,
+    ) -> Transfer {
+        // SAFETY: `cx` and `map` is valid by contract.
+        unsafe { switch(cx, data, Some(map)) }
+    }
+}
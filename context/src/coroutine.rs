@@ -0,0 +1,139 @@
+use core::{
+    marker::PhantomData,
+    mem::{ManuallyDrop, MaybeUninit},
+    ptr::{self, NonNull},
+};
+
+use crate::{Entry, Resume};
+
+/// A typed entry of some context, which takes an argument of `T` and returns
+/// a value of `U`. See [`coroutine!`] for more information.
+pub struct Coroutine<R: Resume, T, U> {
+    resumer: R,
+    entry: Entry<R::Context>,
+    marker: PhantomData<fn(T) -> U>,
+}
+
+impl<R: Resume, T, U> Coroutine<R, T, U> {
+    /// Wraps the raw entry, which is usually done by [`coroutine!`].
+    ///
+    /// # Safety
+    ///
+    /// On its creation, `entry` must read a `T` from the data it receives, and
+    /// resume the source context with a pointer to a `U` once, which stays
+    /// valid until the stack is deallocated. After that, it must never be
+    /// resumed again.
+    pub const unsafe fn from_raw(resumer: R, entry: Entry<R::Context>) -> Self {
+        Coroutine {
+            resumer,
+            entry,
+            marker: PhantomData,
+        }
+    }
+
+    /// Runs the coroutine on `stack` with `arg` until it returns.
+    ///
+    /// The stack can be deallocated or reused once this function returns,
+    /// since the finished context is never resumed again.
+    ///
+    /// # Safety
+    ///
+    /// `stack` must be valid for the whole call. See [`Resume::new_on`] for
+    /// more information.
+    pub unsafe fn call_on(&self, stack: NonNull<[u8]>, arg: T) -> Result<U, R::NewError> {
+        // SAFETY: `stack` is valid by contract.
+        let cx = unsafe { self.resumer.new_on(stack, self.entry) }?;
+        // The argument is moved out by the entry.
+        let mut arg = ManuallyDrop::new(arg);
+        // SAFETY: `cx` is just created, and the entry reads the argument by the
+        // contract of `Coroutine::from_raw`.
+        let t = unsafe { self.resumer.resume(cx, ptr::addr_of_mut!(arg).cast()) };
+        // SAFETY: The entry returns a pointer to its return value on its stack.
+        Ok(unsafe { t.data.cast::<U>().read() })
+    }
+}
+
+impl<R: Resume, T, U> Clone for Coroutine<R, T, U> {
+    fn clone(&self) -> Self {
+        Coroutine {
+            resumer: self.resumer.clone(),
+            entry: self.entry,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// # Safety
+///
+/// `cx` and `data` must be received on the creation of a [`Coroutine`]. See
+/// [`Coroutine::from_raw`] for more information.
+#[doc(hidden)]
+pub unsafe fn __enter<R: Resume, T, U>(
+    resumer: &R,
+    cx: NonNull<R::Context>,
+    data: *mut (),
+    body: impl FnOnce(T) -> U,
+) -> ! {
+    // SAFETY: The data is the argument sent by `Coroutine::call_on`.
+    let arg = unsafe { data.cast::<T>().read() };
+    let mut ret = MaybeUninit::new(body(arg));
+    // SAFETY: `cx` is the context calling this coroutine, which moves the return
+    // value out and never resumes this context again.
+    unsafe { resumer.resume(cx, ret.as_mut_ptr().cast()) };
+    unreachable!("a finished coroutine is resumed")
+}
+
+/// Defines a [`Coroutine`] of the resumer `$r`, which runs a closure-like
+/// body taking one argument on a new context.
+///
+/// The unpacking of the argument and the return value from the transfer
+/// structure is generated, so no [`Entry`] needs to be written by hand:
+///
+/// ```
+/// use core::ptr::NonNull;
+///
+/// use unico_context::{boost::Boost, coroutine};
+///
+/// let square = coroutine!(Boost, |x: u64| -> u64 { x * x });
+/// let mut stack = [0u8; 16384];
+/// let ret = unsafe { square.call_on(NonNull::from(&mut stack[..]), 12) };
+/// assert_eq!(ret.unwrap(), 144);
+/// ```
+///
+/// A resumer that is not a unit struct, like a constant or a static, is given
+/// with its type as `$r as $R`:
+///
+/// ```
+/// # use core::ptr::NonNull;
+/// use unico_context::{boost::Boost, coroutine};
+///
+/// static RESUMER: Boost = Boost;
+///
+/// let square = coroutine!(RESUMER as Boost, |x: u64| -> u64 { x * x });
+/// # let mut stack = [0u8; 16384];
+/// # let ret = unsafe { square.call_on(NonNull::from(&mut stack[..]), 12) };
+/// # assert_eq!(ret.unwrap(), 144);
+/// ```
+///
+/// Like a function item, the body cannot capture its environment. A panic in
+/// the body aborts the process, since it cannot unwind across contexts.
+#[macro_export]
+macro_rules! coroutine {
+    ($r:path, | $arg:ident : $t:ty | -> $u:ty $body:block) => {
+        $crate::coroutine!($r as $r, |$arg: $t| -> $u $body)
+    };
+    ($r:path as $R:ty, | $arg:ident : $t:ty | -> $u:ty $body:block) => {{
+        unsafe extern "C" fn entry(
+            cx: core::ptr::NonNull<<$R as $crate::Resume>::Context>,
+            data: *mut (),
+        ) -> ! {
+            // The body is bound outside the `unsafe` block, so that the unsafe
+            // operations in it still need their own blocks.
+            let body = |$arg: $t| -> $u { $body };
+            // SAFETY: `entry` is only called by `Coroutine::call_on`.
+            unsafe { $crate::__enter(&$r, cx, data, body) }
+        }
+
+        unsafe { $crate::Coroutine::<$R, $t, $u>::from_raw($r, entry) }
+    }};
+}
@@ -5,6 +5,10 @@
 #![deny(trivial_casts)]
 #![deny(trivial_numeric_casts)]
 #![cfg_attr(feature = "ucx", feature(new_uninit))]
+#![cfg_attr(
+    any(feature = "sanitizer", feature = "default-boost"),
+    feature(linkage)
+)]
 #![allow(internal_features)]
 #![feature(allocator_api)]
 #![feature(allow_internal_unstable)]
@@ -16,20 +20,37 @@ cfg_if::cfg_if! {
         pub mod boost;
     }
 }
+cfg_if::cfg_if! {
+    if #[cfg(feature = "corosensei")] {
+        pub mod corosensei;
+    }
+}
 cfg_if::cfg_if! {
     if #[cfg(feature = "ucx")] {
-        extern crate std;
         pub mod ucx;
     }
 }
+extern crate alloc;
+#[cfg(any(feature = "corosensei", feature = "ucx"))]
+extern crate std;
+
+mod coroutine;
 mod page;
+pub mod record;
 
+use alloc::{boxed::Box, vec};
 use core::{
     alloc::{AllocError, Layout},
     fmt::Debug,
-    ptr::NonNull,
+    mem::MaybeUninit,
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicBool, Ordering::Relaxed},
 };
 
+#[doc(hidden)]
+pub use self::coroutine::__enter;
+pub use self::coroutine::Coroutine;
+
 /// The transfer structure between contexts.
 #[derive(Debug)]
 #[repr(C)]
@@ -46,7 +67,78 @@ pub struct Transfer<C> {
     pub data: *mut (),
 }
 
+impl<C> Transfer<C> {
+    /// Creates a transfer structure, typically returned from a [`Map`].
+    pub const fn new(context: Option<NonNull<C>>, data: *mut ()) -> Self {
+        Transfer { context, data }
+    }
+
+    /// The context to be resumed or just switched from.
+    pub const fn context(&self) -> Option<NonNull<C>> {
+        self.context
+    }
+
+    /// The data passed between contexts.
+    pub const fn data(&self) -> *mut () {
+        self.data
+    }
+
+    /// Replaces the data passed between contexts, keeping the context.
+    pub const fn with_data(self, data: *mut ()) -> Self {
+        Transfer { data, ..self }
+    }
+}
+
+/// The static information of some [`Resume`] implementation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct BackendInfo {
+    /// The name of the backend.
+    pub name: &'static str,
+    /// Whether a context must be resumed on the thread where it's created.
+    pub thread_affine: bool,
+    /// Whether the floating-point environment is saved across switches.
+    pub saves_fp: bool,
+}
+
+impl BackendInfo {
+    /// Creates the information of the backend `name`, which is assumed to be
+    /// thread-affine and not to save the floating-point environment, until
+    /// told otherwise.
+    ///
+    /// ```
+    /// use unico_context::BackendInfo;
+    ///
+    /// let info = BackendInfo::new("mine").with_thread_affine(false);
+    /// assert_eq!(info.name, "mine");
+    /// assert!(!info.thread_affine && !info.saves_fp);
+    /// ```
+    pub const fn new(name: &'static str) -> Self {
+        BackendInfo {
+            name,
+            thread_affine: true,
+            saves_fp: false,
+        }
+    }
+
+    /// Sets whether a context must be resumed on the thread where it's created.
+    pub const fn with_thread_affine(self, thread_affine: bool) -> Self {
+        BackendInfo {
+            thread_affine,
+            ..self
+        }
+    }
+
+    /// Sets whether the floating-point environment is saved across switches.
+    pub const fn with_saves_fp(self, saves_fp: bool) -> Self {
+        BackendInfo { saves_fp, ..self }
+    }
+}
+
 pub type Entry<C> = unsafe extern "C" fn(cx: NonNull<C>, data: *mut ()) -> !;
+/// The function executed on top of the destination stack by
+/// [`Resume::resume_with`], which receives the source context and the data, and
+/// returns the transfer structure that the destination finally receives.
 #[allow(improper_ctypes_definitions)]
 pub type Map<C> =
     unsafe extern "C-unwind" fn(cx: NonNull<C>, data: *mut ()) -> Transfer<C>;
@@ -64,6 +156,17 @@ pub unsafe trait Resume: Sized + Clone + 'static {
     /// The error type returned during creation of some context.
     type NewError: Debug;
 
+    /// The information of this backend, useful for logging or refusing to
+    /// migrate contexts across threads.
+    ///
+    /// By default, the backend is named after the type of the resumer, and
+    /// assumed to be thread-affine and not to save the floating-point
+    /// environment, as described in [`BackendInfo::new`]. The built-in
+    /// backends override it with their exact information.
+    fn backend_info(&self) -> BackendInfo {
+        BackendInfo::new(core::any::type_name::<Self>())
+    }
+
     /// Creates a new context on top of some stack.
     ///
     /// # Safety
@@ -106,6 +209,35 @@ pub unsafe trait Resume: Sized + Clone + 'static {
         data: *mut (),
         map: Map<Self::Context>,
     ) -> Transfer<Self::Context>;
+
+    /// Releases what the backend holds for the finished context `cx`, right
+    /// before its stack is deallocated.
+    ///
+    /// The stack itself is never owned by the backend, but by the creator of
+    /// the context, who deallocates it afterwards. The coroutines of
+    /// `unico-ful`, for example, return their stacks to the allocators they
+    /// come from once finished or dropped, after calling this method. The
+    /// default implementation does nothing, since the contexts of most backends
+    /// live entirely on their stacks.
+    ///
+    /// # Safety
+    ///
+    /// `cx` must be created from the current [`Resume::new_on`], and must not
+    /// be running nor resumed ever again.
+    unsafe fn dealloc_context(&self, cx: NonNull<Self::Context>) {
+        let _ = cx;
+    }
+
+    /// Checks the backend by [`selftest`].
+    ///
+    /// The wrappers of some other resumer, like
+    /// [`Recording`](record::Recording), forward this method to the resumer
+    /// they wrap, so that the switches of the probe are not observed by
+    /// them.
+    #[track_caller]
+    fn selftest(&self) {
+        selftest(self)
+    }
 }
 
 fn layout_union(l1: Layout, l2: Layout) -> Layout {
@@ -114,23 +246,36 @@ fn layout_union(l1: Layout, l2: Layout) -> Layout {
     Layout::from_size_align(size, align).unwrap()
 }
 
-fn stack_top<T>(stack: NonNull<[u8]>) -> Option<NonNull<T>> {
+/// Places a `T` on the top of the stack, or returns the minimum size of the
+/// stack if it's too small.
+///
+/// The returned pointer is aligned to [`page::STACK_ALIGN`] at least, so that
+/// the stack growing below it is properly aligned regardless of the base of
+/// the stack.
+fn stack_top<T>(stack: NonNull<[u8]>) -> Result<NonNull<T>, usize> {
     let layout = Layout::new::<T>();
-    if stack.len() < layout_union(layout, page::STACK_LAYOUT).size() {
-        return None;
+    let union = layout_union(layout, page::STACK_LAYOUT);
+    let minimum = union.size();
+    if stack.len() < minimum {
+        return Err(minimum);
     }
 
     let ptr = stack.as_non_null_ptr();
     let addr = ptr.addr().get();
-    let ret = (addr + stack.len() - layout.size()) & !(layout.align() - 1);
+    let ret = (addr + stack.len() - layout.size()) & !(union.align() - 1);
     if ret < addr {
-        return None;
+        return Err(minimum);
     }
-    ret.try_into().map(|addr| ptr.with_addr(addr).cast()).ok()
+    debug_assert_eq!(ret % page::STACK_ALIGN, 0, "the stack top is misaligned");
+    ret.try_into()
+        .map(|addr| ptr.with_addr(addr).cast())
+        .map_err(|_| minimum)
 }
 
 // SAFETY: These functions are implemented by `global_resumer!`.
 unsafe extern "Rust" {
+    fn __rust_unico_context_backend_info() -> BackendInfo;
+
     fn __rust_unico_context_new(
         stack: NonNull<u8>,
         stack_size: usize,
@@ -144,6 +289,14 @@ unsafe extern "Rust" {
         data: *mut (),
         map: Map<()>,
     ) -> Transfer<()>;
+
+    fn __rust_unico_context_dealloc(cx: NonNull<()>);
+}
+
+/// The information of the global resumer.
+pub fn backend_info() -> BackendInfo {
+    // SAFETY: The function is defined by `global_resumer!` with no contract.
+    unsafe { __rust_unico_context_backend_info() }
 }
 
 /// Creates a new context on top of some stack.
@@ -183,23 +336,143 @@ pub unsafe fn resume_with(cx: NonNull<()>, data: *mut (), map: Map<()>) -> Trans
     unsafe { __rust_unico_context_resume_with(cx, data, map) }
 }
 
+/// Releases what the global resumer holds for the finished context `cx`, right
+/// before its stack is deallocated.
+///
+/// # Safety
+///
+/// `cx` must be created from [`new_on`], and must not be running nor resumed
+/// ever again. See [`Resume::dealloc_context`] for more information.
+pub unsafe fn dealloc_context(cx: NonNull<()>) {
+    unsafe { __rust_unico_context_dealloc(cx) }
+}
+
+/// The size of the heap-allocated stack used by [`selftest`].
+const SELFTEST_STACK_SIZE: usize = 16384;
+
+/// The value sent back and forth by [`selftest`].
+const SELFTEST_SENTINEL: usize = 0x5e1f_7e57;
+
+struct Probe<'a, R> {
+    resumer: &'a R,
+    value: usize,
+}
+
+unsafe extern "C" fn probe_entry<R: Resume>(
+    mut cx: NonNull<R::Context>,
+    mut data: *mut (),
+) -> ! {
+    loop {
+        let probe = data.cast::<Probe<'_, R>>();
+        // SAFETY: `data` is always the probe sent by `selftest`, which outlives
+        // this context.
+        let t = unsafe {
+            (*probe).value = !(*probe).value;
+            (*probe).resumer.resume(cx, data)
+        };
+        cx = t
+            .context
+            .expect("the backend resumed the selftest without a context");
+        data = t.data;
+    }
+}
+
+/// Switches to a tiny context and back a few times, and panics if the values
+/// transferred are not intact.
+///
+/// A mis-linked or unsupported backend usually corrupts the memory silently,
+/// so this function turns that into an immediate failure. The context is
+/// created on a stack allocated from the heap, and is released along with it
+/// afterwards.
+#[track_caller]
+pub fn selftest<R: Resume>(resumer: &R) {
+    let name = resumer.backend_info().name;
+    let mut memory: Box<[MaybeUninit<u8>]> =
+        vec![MaybeUninit::uninit(); SELFTEST_STACK_SIZE].into_boxed_slice();
+    let stack = NonNull::slice_from_raw_parts(
+        NonNull::from(&mut *memory).cast::<u8>(),
+        SELFTEST_STACK_SIZE,
+    );
+    // SAFETY: `stack` is fresh and unused by anything else.
+    let mut cx = match unsafe { resumer.new_on(stack, probe_entry::<R>) } {
+        Ok(cx) => cx,
+        Err(err) => panic!("the `{name}` backend failed the selftest: {err:?}"),
+    };
+
+    let mut probe = Probe {
+        resumer,
+        value: SELFTEST_SENTINEL,
+    };
+    let data = ptr::from_mut(&mut probe).cast::<()>();
+    for round in 0..2 {
+        let expected = !probe.value;
+        // SAFETY: `cx` is created or returned above, and `probe_entry` accepts the
+        // probe.
+        let t = unsafe { resumer.resume(cx, data) };
+        match t.context {
+            Some(next) if t.data == data && probe.value == expected => cx = next,
+            _ => panic!(
+                "the `{name}` backend failed the selftest in round {round}: got \
+                 {:?} and {:#x}, expected {data:?} and {expected:#x}; the backend may \
+                 be mis-linked or unsupported on this target",
+                t.data, probe.value,
+            ),
+        }
+    }
+    // SAFETY: The probe is never resumed again, and holds nothing to be dropped
+    // on its stack.
+    unsafe { resumer.dealloc_context(cx) };
+}
+
+/// Runs [`selftest`] on `resumer` unless it has already passed.
+#[doc(hidden)]
+#[track_caller]
+pub fn __selftest_once<R: Resume>(resumer: &R) {
+    static PASSED: AtomicBool = AtomicBool::new(false);
+    if !PASSED.load(Relaxed) {
+        resumer.selftest();
+        PASSED.store(true, Relaxed);
+    }
+}
+
 /// Define a global resumer so that those global functions (like [`resume`]) can
 /// be used in general.
 ///
 /// This macro works just like `#[global_allocator]` attribute, except it only
-/// receives the path of the target static variable, while the actual definition
-/// can lie elsewhere.
+/// receives the path of the target static variable or constant, like
+/// `Ucontext::DEFAULT`, while the actual definition can lie elsewhere. A
+/// resumer built by some builder should thus be defined as a `static` first,
+/// which is evaluated once at compile time.
+///
+/// In debug builds, the resumer is checked by [`selftest`] upon the first
+/// creation of some context.
+///
+/// With the `default-boost` feature, [`Boost`](boost::Boost) is installed as a
+/// weak default, which is overridden by any resumer defined with this macro.
 #[macro_export]
 #[allow_internal_unstable(allocator_api)]
 macro_rules! global_resumer {
     ($t:path) => {
-        #[no_mangle]
+        $crate::global_resumer!(@attrs [] $t);
+    };
+    (@attrs [$(#[$attr:meta])*] $t:path) => {
+        $(#[$attr])*
+        #[unsafe(no_mangle)]
+        #[doc(hidden)]
+        fn __rust_unico_context_backend_info() -> $crate::BackendInfo {
+            $crate::Resume::backend_info(&$t)
+        }
+
+        $(#[$attr])*
+        #[unsafe(no_mangle)]
         #[doc(hidden)]
         unsafe fn __rust_unico_context_new(
             stack: core::ptr::NonNull<u8>,
             stack_size: usize,
             entry: $crate::Entry<()>,
         ) -> Result<core::ptr::NonNull<()>, core::alloc::AllocError> {
+            #[cfg(debug_assertions)]
+            $crate::__selftest_once(&$t);
             unsafe {
                 $crate::Resume::new_on(
                     &$t,
@@ -211,7 +484,8 @@ macro_rules! global_resumer {
             .map_err(|_| core::alloc::AllocError)
         }
 
-        #[no_mangle]
+        $(#[$attr])*
+        #[unsafe(no_mangle)]
         #[doc(hidden)]
         unsafe fn __rust_unico_context_resume(
             cx: core::ptr::NonNull<()>,
@@ -223,7 +497,8 @@ macro_rules! global_resumer {
             }
         }
 
-        #[no_mangle]
+        $(#[$attr])*
+        #[unsafe(no_mangle)]
         #[doc(hidden)]
         unsafe fn __rust_unico_context_resume_with(
             cx: core::ptr::NonNull<()>,
@@ -239,5 +514,476 @@ macro_rules! global_resumer {
                 ))
             }
         }
+
+        $(#[$attr])*
+        #[unsafe(no_mangle)]
+        #[doc(hidden)]
+        unsafe fn __rust_unico_context_dealloc(cx: core::ptr::NonNull<()>) {
+            unsafe { $crate::Resume::dealloc_context(&$t, core::ptr::NonNull::cast(cx)) }
+        }
     };
 }
+
+/// The weak default of the global resumer, in a module apart from the
+/// declarations of the symbols.
+#[cfg(feature = "default-boost")]
+#[allow(clippy::missing_transmute_annotations)]
+mod fallback {
+    crate::global_resumer!(@attrs [#[linkage = "weak"]] crate::boost::Boost);
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::ptr::{self, NonNull};
+    use std::{panic::AssertUnwindSafe, vec, vec::Vec};
+
+    use crate::{Resume, Transfer};
+
+    const MAX_DEPTH: u32 = 4;
+    const MAX_NODES: u64 = 64;
+
+    /// The `SplitMix64` generator.
+    fn next(state: &mut u64) -> u64 {
+        *state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    struct Fuzz<R> {
+        resumer: R,
+        seed: u64,
+        runs: Vec<u32>,
+        errors: u32,
+    }
+
+    impl<R> Fuzz<R> {
+        /// The number of payloads that the coroutine `id` receives.
+        fn rounds(&self, id: u64) -> u32 {
+            let mut state = self.seed ^ id;
+            (next(&mut state) % 4) as u32 + 1
+        }
+    }
+
+    fn reply(payload: u64, id: u64) -> u64 {
+        payload.rotate_left(7) ^ id
+    }
+
+    struct Args<R> {
+        fuzz: *mut Fuzz<R>,
+        id: u64,
+        depth: u32,
+    }
+
+    struct Child<C> {
+        cx: NonNull<C>,
+        id: u64,
+        left: u32,
+        _stack: Vec<u8>,
+    }
+
+    unsafe fn spawn<R: Resume>(fuzz: *mut Fuzz<R>, depth: u32) -> Child<R::Context> {
+        let (resumer, id) = unsafe {
+            let id = (*fuzz).runs.len() as u64;
+            (*fuzz).runs.push(0);
+            ((*fuzz).resumer.clone(), id)
+        };
+        let mut stack = vec![0; 65536];
+        let cx = unsafe { resumer.new_on(NonNull::from(&mut stack[..]), entry::<R>) };
+        let args = Args { fuzz, id, depth };
+        let data = ptr::from_ref(&args).cast_mut().cast();
+        let t = unsafe { resumer.resume(cx.unwrap(), data) };
+        unsafe {
+            if t.data.addr() as u64 != id {
+                (*fuzz).errors += 1;
+            }
+            Child {
+                cx: t.context.unwrap(),
+                id,
+                left: (*fuzz).rounds(id),
+                _stack: stack,
+            }
+        }
+    }
+
+    /// Interacts with a random child once, dropping it if it completes.
+    unsafe fn drive<R: Resume>(
+        fuzz: *mut Fuzz<R>,
+        children: &mut Vec<Child<R::Context>>,
+        state: &mut u64,
+    ) {
+        let index = (next(state) % children.len() as u64) as usize;
+        let payload = next(state) & 0xffff_ffff;
+        let child = &mut children[index];
+        unsafe {
+            let resumer = (*fuzz).resumer.clone();
+            let data = ptr::without_provenance_mut(payload as usize);
+            let t = resumer.resume(child.cx, data);
+            if t.data.addr() as u64 != reply(payload, child.id) {
+                (*fuzz).errors += 1;
+            }
+            child.left -= 1;
+            match child.left {
+                0 => drop(children.swap_remove(index)),
+                _ => child.cx = t.context.unwrap(),
+            }
+        }
+    }
+
+    unsafe extern "C" fn entry<R: Resume>(from: NonNull<R::Context>, data: *mut ()) -> ! {
+        let Args { fuzz, id, depth } = unsafe { data.cast::<Args<R>>().read() };
+        let (resumer, rounds) = unsafe {
+            (&mut (*fuzz).runs)[id as usize] += 1;
+            ((*fuzz).resumer.clone(), (*fuzz).rounds(id))
+        };
+        let mut state = unsafe { (*fuzz).seed } ^ id.rotate_left(32);
+
+        let mut children = Vec::new();
+        if depth < MAX_DEPTH {
+            for _ in 0..next(&mut state) % 3 {
+                if unsafe { (*fuzz).runs.len() } as u64 >= MAX_NODES {
+                    break;
+                }
+                children.push(unsafe { spawn(fuzz, depth + 1) });
+            }
+        }
+
+        let data = ptr::without_provenance_mut(id as usize);
+        let mut t = unsafe { resumer.resume(from, data) };
+        for round in 0..rounds {
+            let last = round + 1 == rounds;
+            let times = if last {
+                usize::MAX
+            } else {
+                next(&mut state) as usize % 3
+            };
+            for _ in 0..times {
+                if children.is_empty() {
+                    break;
+                }
+                unsafe { drive(fuzz, &mut children, &mut state) };
+            }
+
+            let data =
+                ptr::without_provenance_mut(reply(t.data.addr() as u64, id) as usize);
+            t = unsafe { resumer.resume(t.context.unwrap(), data) };
+        }
+        unreachable!("a completed coroutine is resumed")
+    }
+
+    /// Builds a random tree of nested coroutines, which are resumed in random
+    /// order with random data.
+    fn fuzz<R: Resume>(resumer: R, seed: u64) {
+        let mut fuzz = Fuzz {
+            resumer,
+            seed,
+            runs: Vec::new(),
+            errors: 0,
+        };
+        let fuzz = ptr::addr_of_mut!(fuzz);
+        let mut state = seed;
+        let mut children: Vec<_> = (0..4).map(|_| unsafe { spawn(fuzz, 0) }).collect();
+        while !children.is_empty() {
+            unsafe { drive(fuzz, &mut children, &mut state) };
+        }
+
+        let fuzz = unsafe { &*fuzz };
+        assert_eq!(fuzz.errors, 0, "corrupted data with seed {seed}");
+        assert!(fuzz.runs.iter().all(|&runs| runs == 1));
+    }
+
+    /// The context finishing a parked one, carried by the unwinding.
+    struct Finisher<C>(NonNull<C>);
+
+    // SAFETY: The unwinding never leaves the thread.
+    unsafe impl<C> Send for Finisher<C> {}
+
+    /// Parks at its first switch, like the entry of a coroutine in `unico-ful`
+    /// which is never resumed again, and catches the unwinding finishing it.
+    unsafe extern "C" fn parked<R: Resume>(cx: NonNull<R::Context>, data: *mut ()) -> ! {
+        // SAFETY: `data` is the resumer sent by `unwind_parked`.
+        let resumer = unsafe { &*data.cast::<R>() };
+        let park = AssertUnwindSafe(|| unsafe { resumer.resume(cx, data) });
+        let Err(payload) = std::panic::catch_unwind(park) else {
+            panic!("the parked context is resumed normally");
+        };
+        let Ok(finisher) = payload.downcast::<Finisher<R::Context>>() else {
+            panic!("the parked context is unwound by something else");
+        };
+        unsafe { resumer.resume(finisher.0, ptr::null_mut()) };
+        unreachable!("a finished context is resumed")
+    }
+
+    #[allow(improper_ctypes_definitions)]
+    unsafe extern "C-unwind" fn unwind<C: 'static>(
+        cx: NonNull<C>,
+        _: *mut (),
+    ) -> Transfer<C> {
+        std::panic::resume_unwind(std::boxed::Box::new(Finisher(cx)))
+    }
+
+    /// Finishes a context parked at its first switch by unwinding it from a
+    /// [`Map`](crate::Map), which is how an unstarted coroutine is dropped, and
+    /// releases it afterwards.
+    fn unwind_parked<R: Resume>(resumer: R) {
+        let mut stack = vec![0; 65536];
+        let cx = unsafe { resumer.new_on(NonNull::from(&mut stack[..]), parked::<R>) };
+        let data = ptr::from_ref(&resumer).cast_mut().cast();
+        let t = unsafe { resumer.resume(cx.unwrap(), data) };
+        assert_eq!(t.data, data);
+        let t = unsafe {
+            resumer.resume_with(t.context.unwrap(), ptr::null_mut(), unwind::<R::Context>)
+        };
+        assert!(t.data.is_null());
+        // SAFETY: The context is finished.
+        unsafe { resumer.dealloc_context(t.context.unwrap()) };
+    }
+
+    #[cfg(feature = "boost")]
+    #[test]
+    fn unwind_parked_boost() {
+        unwind_parked(crate::boost::Boost);
+    }
+
+    #[cfg(feature = "ucx")]
+    #[test]
+    fn unwind_parked_ucx() {
+        unwind_parked(crate::ucx::Ucontext::DEFAULT);
+        unwind_parked(crate::ucx::Ucontext::fast_create());
+        unwind_parked(crate::ucx::Ucontext::builder().save_sigmask(false).build());
+    }
+
+    #[cfg(feature = "corosensei")]
+    #[test]
+    fn unwind_parked_corosensei() {
+        unwind_parked(crate::corosensei::Corosensei);
+    }
+
+    #[cfg(feature = "boost")]
+    #[test]
+    fn fuzz_boost() {
+        for seed in 0..32 {
+            fuzz(crate::boost::Boost, seed);
+        }
+    }
+
+    #[cfg(feature = "boost")]
+    #[test]
+    fn selftest_boost() {
+        crate::selftest(&crate::boost::Boost);
+    }
+
+    #[cfg(feature = "ucx")]
+    #[test]
+    fn selftest_ucx() {
+        crate::selftest(&crate::ucx::Ucontext::DEFAULT);
+    }
+
+    #[cfg(feature = "ucx")]
+    #[test]
+    fn selftest_ucx_fast() {
+        crate::selftest(&crate::ucx::Ucontext::fast_create());
+    }
+
+    #[cfg(feature = "ucx")]
+    #[test]
+    fn selftest_ucx_unmasked() {
+        crate::selftest(&crate::ucx::Ucontext::builder().save_sigmask(false).build());
+    }
+
+    #[cfg(feature = "boost")]
+    #[test]
+    fn coroutine_boost() {
+        use std::string::String;
+
+        let join = crate::coroutine!(crate::boost::Boost, |words: Vec<&str>| -> String {
+            words.join(" ")
+        });
+        let mut stack = vec![0u8; 16384];
+        let stack = NonNull::from(&mut stack[..]);
+        for _ in 0..2 {
+            let ret = unsafe { join.call_on(stack, vec!["hello", "unico"]) };
+            assert_eq!(ret.unwrap(), "hello unico");
+        }
+    }
+
+    #[cfg(feature = "ucx")]
+    #[test]
+    fn coroutine_ucx() {
+        let sum = crate::coroutine!(
+            crate::ucx::Ucontext::DEFAULT as crate::ucx::Ucontext,
+            |n: u64| -> u64 { (1..=n).sum() }
+        );
+        let mut stack = vec![0u8; 16384];
+        let ret = unsafe { sum.call_on(NonNull::from(&mut stack[..]), 100) };
+        assert_eq!(ret.unwrap(), 5050);
+    }
+
+    #[cfg(feature = "boost")]
+    #[test]
+    fn stack_too_small() {
+        use std::string::ToString;
+
+        use crate::boost::{Boost, NewError};
+
+        let mut stack = [0u8; 16];
+        let err = unsafe { Boost.new_on(NonNull::from(&mut stack[..]), entry::<Boost>) };
+        let Err(err @ NewError::StackTooSmall { provided, minimum }) = err else {
+            panic!("a context is created on a 16-byte stack");
+        };
+        assert_eq!(provided, 16);
+        assert!(minimum > provided);
+        assert!(err.to_string().contains(&minimum.to_string()));
+    }
+
+    #[cfg(feature = "corosensei")]
+    #[test]
+    fn selftest_corosensei() {
+        crate::selftest(&crate::corosensei::Corosensei);
+    }
+
+    #[cfg(feature = "corosensei")]
+    #[test]
+    fn fuzz_corosensei() {
+        for seed in 0..32 {
+            fuzz(crate::corosensei::Corosensei, seed);
+        }
+    }
+
+    #[cfg(feature = "ucx")]
+    #[test]
+    fn fuzz_ucx() {
+        for seed in 0..32 {
+            fuzz(crate::ucx::Ucontext::DEFAULT, seed);
+        }
+    }
+
+    #[cfg(feature = "ucx")]
+    #[test]
+    fn fuzz_ucx_fast() {
+        for seed in 0..32 {
+            fuzz(crate::ucx::Ucontext::fast_create(), seed);
+        }
+    }
+
+    #[cfg(feature = "ucx")]
+    #[test]
+    fn fuzz_ucx_unmasked() {
+        let resumer = crate::ucx::Ucontext::builder().save_sigmask(false).build();
+        for seed in 0..32 {
+            fuzz(resumer, seed);
+        }
+    }
+
+    /// The byte the stacks are painted with to find the deepest one used.
+    const PAINT: u8 = 0xa5;
+
+    /// The stack budget of a frame of `recurse`.
+    const FRAME: usize = 256;
+
+    #[inline(never)]
+    fn recurse(depth: u32) -> u64 {
+        let frame = core::hint::black_box([depth as u8; 64]);
+        match depth {
+            0 => 0,
+            _ => recurse(depth - 1) + u64::from(frame[0]),
+        }
+    }
+
+    unsafe extern "C" fn recurse_entry<R: Resume>(
+        from: NonNull<R::Context>,
+        data: *mut (),
+    ) -> ! {
+        let (resumer, depth) = unsafe { data.cast::<(R, u32)>().read() };
+        let sum = recurse(depth);
+        unsafe { resumer.resume(from, ptr::without_provenance_mut(sum as usize)) };
+        unreachable!("a completed coroutine is resumed")
+    }
+
+    /// Runs `recurse(depth)` in a coroutine on a painted stack of `size` bytes,
+    /// which lies above a painted guard, and returns the number of bytes used.
+    ///
+    /// Panics if the guard is touched.
+    fn stack_usage<R: Resume>(resumer: R, depth: u32, size: usize) -> usize {
+        const GUARD: usize = 4096;
+        let mut memory = vec![PAINT; GUARD + size];
+        let stack = NonNull::from(&mut memory[GUARD..]);
+        let cx = unsafe { resumer.new_on(stack, recurse_entry::<R>) };
+        let mut args = (resumer.clone(), depth);
+        let data = ptr::addr_of_mut!(args).cast();
+        let t = unsafe { resumer.resume(cx.unwrap(), data) };
+        assert_eq!(
+            t.data.addr() as u64,
+            (1..=depth).map(|d| d as u8 as u64).sum()
+        );
+
+        let name = resumer.backend_info().name;
+        let deepest = memory.iter().position(|b| *b != PAINT).unwrap();
+        assert!(deepest >= GUARD, "the `{name}` backend overflows its stack");
+        GUARD + size - deepest
+    }
+
+    /// Checks that a coroutine of `resumer` uses no more than `overhead` bytes
+    /// of its stack by itself, and that a deep computation fits in a stack
+    /// sized by the budgets without overflowing.
+    fn stack_overhead<R: Resume>(resumer: R, overhead: usize) {
+        let name = resumer.backend_info().name;
+        let used = stack_usage(resumer.clone(), 0, 65536);
+        assert!(
+            used <= overhead,
+            "the `{name}` backend uses {used} bytes of stack, more than {overhead}"
+        );
+        let size = (overhead + 64 * FRAME).next_multiple_of(16);
+        let used = stack_usage(resumer, 64, size);
+        assert!(used > size / 4, "only {used} bytes used out of {size}");
+    }
+
+    #[cfg(feature = "boost")]
+    #[test]
+    fn stack_overhead_boost() {
+        stack_overhead(crate::boost::Boost, 1024);
+    }
+
+    #[cfg(feature = "ucx")]
+    #[test]
+    fn stack_overhead_ucx() {
+        // The context holds the whole `ucontext_t` with the floating-point state.
+        stack_overhead(crate::ucx::Ucontext::DEFAULT, 4096);
+        stack_overhead(crate::ucx::Ucontext::fast_create(), 4096);
+    }
+
+    #[cfg(feature = "corosensei")]
+    #[test]
+    fn stack_overhead_corosensei() {
+        // The coroutine is set up by the initial frames of `corosensei`.
+        stack_overhead(crate::corosensei::Corosensei, 4096);
+    }
+
+    #[cfg(feature = "boost")]
+    #[test]
+    fn fuzz_replay() {
+        use crate::{
+            boost::Boost,
+            record::{Recording, Replay, SwitchLog},
+        };
+
+        static LOG: SwitchLog<4096> = SwitchLog::new();
+        let recording = Recording::new(Boost, &LOG);
+        recording.selftest();
+        assert!(LOG.is_empty());
+        fuzz(recording, 7);
+        assert!(!LOG.is_empty() && !LOG.is_truncated());
+        let first = LOG.get(0).unwrap();
+        assert_eq!((first.from, first.to), (0, 1));
+
+        LOG.rewind();
+        let replay = Replay::new(Boost, &LOG);
+        assert_eq!(replay.next(), Some(first));
+        replay.selftest();
+        fuzz(replay, 7);
+        assert_eq!(replay.next(), None);
+    }
+}
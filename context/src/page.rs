@@ -1,4 +1,4 @@
-use core::{alloc::Layout, mem};
+use core::alloc::Layout;
 
 pub const SHIFT: u32 = 12;
 pub const SIZE: usize = 1 << SHIFT;
@@ -6,6 +6,10 @@ pub const SIZE: usize = 1 << SHIFT;
 // pub const LAYOUT: Layout = unsafe { Layout::from_size_align_unchecked(SIZE,
 // SIZE) };
 
-// SAFETY: The align is a valid align of `usize`.
+/// The alignment of the stack pointer, which SIMD code and the placement of
+/// `ucontext_t` rely on.
+pub const STACK_ALIGN: usize = 16;
+
+// SAFETY: The align is a power of 2.
 pub const STACK_LAYOUT: Layout =
-    unsafe { Layout::from_size_align_unchecked(SIZE / 2, mem::align_of::<usize>()) };
+    unsafe { Layout::from_size_align_unchecked(SIZE / 2, STACK_ALIGN) };
@@ -0,0 +1,353 @@
+//! [`Resume`] wrappers that record the sequence of context switches and check
+//! it against a recorded one, which helps reproduce a failing interleaving of
+//! coroutines.
+//!
+//! Coroutines are identified by the order of their creation, starting from 1,
+//! while 0 stands for any context not created through the wrapper, e.g. the
+//! root context of a thread. Since the log keeps track of the current context
+//! globally, only the switches on a single thread should be recorded.
+
+use core::{
+    fmt,
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering::*},
+};
+
+use crate::{BackendInfo, Entry, Map, Resume, Transfer};
+
+/// A context switch recorded in [`SwitchLog`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Switch {
+    /// The id of the context switched from.
+    pub from: u32,
+    /// The id of the context switched to.
+    pub to: u32,
+}
+
+impl Switch {
+    fn pack(self) -> u64 {
+        (u64::from(self.from) << 32) | u64::from(self.to)
+    }
+
+    fn unpack(packed: u64) -> Self {
+        Switch {
+            from: (packed >> 32) as u32,
+            to: packed as u32,
+        }
+    }
+}
+
+/// A fixed-capacity log of at most `N` contexts and `N` switches, which can be
+/// placed in a `static` item.
+pub struct SwitchLog<const N: usize> {
+    /// The stacks of the created contexts, indexed by their ids minus 1.
+    stacks: [(AtomicUsize, AtomicUsize); N],
+    created: AtomicUsize,
+    switches: [AtomicU64; N],
+    len: AtomicUsize,
+    truncated: AtomicBool,
+    current: AtomicU32,
+    replayed: AtomicUsize,
+}
+
+impl<const N: usize> SwitchLog<N> {
+    /// Creates an empty log.
+    pub const fn new() -> Self {
+        SwitchLog {
+            stacks: [const { (AtomicUsize::new(0), AtomicUsize::new(0)) }; N],
+            created: AtomicUsize::new(0),
+            switches: [const { AtomicU64::new(0) }; N],
+            len: AtomicUsize::new(0),
+            truncated: AtomicBool::new(false),
+            current: AtomicU32::new(0),
+            replayed: AtomicUsize::new(0),
+        }
+    }
+
+    /// The number of the recorded switches.
+    pub fn len(&self) -> usize {
+        self.len.load(Acquire)
+    }
+
+    /// Whether no switch is recorded.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether some contexts or switches are dropped because the log is full.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated.load(Relaxed)
+    }
+
+    /// The `index`-th recorded switch.
+    pub fn get(&self, index: usize) -> Option<Switch> {
+        (index < self.len()).then(|| Switch::unpack(self.switches[index].load(Relaxed)))
+    }
+
+    /// Iterates over the recorded switches.
+    pub fn iter(&self) -> impl Iterator<Item = Switch> + '_ {
+        (0..self.len()).map_while(|index| self.get(index))
+    }
+
+    /// Forgets the created contexts so that their ids are assigned from 1
+    /// again, while keeping the recorded switches for [`Replay`].
+    pub fn rewind(&self) {
+        self.created.store(0, Relaxed);
+        self.current.store(0, Relaxed);
+        self.replayed.store(0, Relaxed);
+    }
+
+    /// Forgets everything in the log.
+    pub fn clear(&self) {
+        self.rewind();
+        self.len.store(0, Release);
+        self.truncated.store(false, Relaxed);
+    }
+
+    fn register(&self, stack: NonNull<[u8]>) {
+        let id = self.created.load(Relaxed);
+        match self.stacks.get(id) {
+            Some((base, len)) => {
+                base.store(stack.addr().get(), Relaxed);
+                len.store(stack.len(), Relaxed);
+                self.created.store(id + 1, Relaxed);
+            }
+            None => self.truncated.store(true, Relaxed),
+        }
+    }
+
+    /// Finds the context whose stack contains `cx`, preferring the newest one
+    /// since the stacks of the finished contexts may be reused.
+    fn id_of<C>(&self, cx: NonNull<C>) -> u32 {
+        let addr = cx.addr().get();
+        let created = self.created.load(Relaxed);
+        (self.stacks[..created].iter().enumerate().rev())
+            .find(|(_, (base, len))| {
+                let base = base.load(Relaxed);
+                (base..base + len.load(Relaxed)).contains(&addr)
+            })
+            .map_or(0, |(index, _)| index as u32 + 1)
+    }
+
+    /// Moves the current context to `cx`, and returns the switch.
+    fn switch<C>(&self, cx: NonNull<C>) -> Switch {
+        let to = self.id_of(cx);
+        let from = self.current.swap(to, Relaxed);
+        Switch { from, to }
+    }
+
+    fn record(&self, switch: Switch) {
+        let len = self.len.load(Relaxed);
+        match self.switches.get(len) {
+            Some(slot) => {
+                slot.store(switch.pack(), Relaxed);
+                self.len.store(len + 1, Release);
+            }
+            None => self.truncated.store(true, Relaxed),
+        }
+    }
+
+    #[track_caller]
+    fn check(&self, switch: Switch) {
+        let index = self.replayed.fetch_add(1, Relaxed);
+        match self.get(index) {
+            Some(expected) => assert_eq!(
+                expected, switch,
+                "the switch #{index} diverges from the recording"
+            ),
+            None => assert!(
+                self.is_truncated(),
+                "the switch #{index} ({switch:?}) is beyond the recording"
+            ),
+        }
+    }
+}
+
+impl<const N: usize> Default for SwitchLog<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> fmt::Debug for SwitchLog<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SwitchLog")
+            .field("capacity", &N)
+            .field("created", &self.created.load(Relaxed))
+            .field("len", &self.len())
+            .field("truncated", &self.is_truncated())
+            .finish()
+    }
+}
+
+/// A [`Resume`] wrapper that records every context switch in a log.
+///
+/// It can be used as a global resumer through a `static` item:
+///
+/// ```rust
+/// use unico_context::{
+///     boost::Boost,
+///     global_resumer,
+///     record::{Recording, SwitchLog},
+/// };
+///
+/// static LOG: SwitchLog<1024> = SwitchLog::new();
+/// static RESUMER: Recording<Boost, 1024> = Recording::new(Boost, &LOG);
+/// global_resumer!(RESUMER);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Recording<R, const N: usize> {
+    inner: R,
+    log: &'static SwitchLog<N>,
+}
+
+impl<R, const N: usize> Recording<R, N> {
+    /// Wraps `inner`, recording the switches in `log`.
+    pub const fn new(inner: R, log: &'static SwitchLog<N>) -> Self {
+        Recording { inner, log }
+    }
+
+    /// The log of the switches.
+    pub fn log(&self) -> &'static SwitchLog<N> {
+        self.log
+    }
+}
+
+// SAFETY: The contexts are created and resumed by the inner resumer.
+unsafe impl<R: Resume, const N: usize> Resume for Recording<R, N> {
+    type Context = R::Context;
+
+    type NewError = R::NewError;
+
+    fn backend_info(&self) -> BackendInfo {
+        self.inner.backend_info()
+    }
+
+    unsafe fn new_on(
+        &self,
+        stack: NonNull<[u8]>,
+        entry: Entry<R::Context>,
+    ) -> Result<NonNull<R::Context>, R::NewError> {
+        // SAFETY: The contract is the same.
+        let cx = unsafe { self.inner.new_on(stack, entry) }?;
+        self.log.register(stack);
+        Ok(cx)
+    }
+
+    unsafe fn resume(
+        &self,
+        cx: NonNull<R::Context>,
+        data: *mut (),
+    ) -> Transfer<R::Context> {
+        self.log.record(self.log.switch(cx));
+        // SAFETY: The contract is the same.
+        unsafe { self.inner.resume(cx, data) }
+    }
+
+    unsafe fn resume_with(
+        &self,
+        cx: NonNull<R::Context>,
+        data: *mut (),
+        map: Map<R::Context>,
+    ) -> Transfer<R::Context> {
+        self.log.record(self.log.switch(cx));
+        // SAFETY: The contract is the same.
+        unsafe { self.inner.resume_with(cx, data, map) }
+    }
+
+    unsafe fn dealloc_context(&self, cx: NonNull<R::Context>) {
+        // SAFETY: The contract is the same.
+        unsafe { self.inner.dealloc_context(cx) }
+    }
+    #[track_caller]
+    fn selftest(&self) {
+        self.inner.selftest()
+    }
+}
+
+/// A [`Resume`] wrapper that checks every context switch against a log
+/// recorded by [`Recording`], and panics on the first divergence.
+///
+/// A resumer cannot choose the destination of a switch by itself, so the
+/// program is expected to follow the recording on its own, e.g. with a
+/// deterministic scheduler. [`Replay::next`] tells which coroutine the
+/// recording resumes next for such schedulers.
+///
+/// Call [`SwitchLog::rewind`] before replaying so that the ids are assigned
+/// from the beginning.
+#[derive(Debug, Clone, Copy)]
+pub struct Replay<R, const N: usize> {
+    inner: R,
+    log: &'static SwitchLog<N>,
+}
+
+impl<R, const N: usize> Replay<R, N> {
+    /// Wraps `inner`, checking the switches against `log`.
+    pub const fn new(inner: R, log: &'static SwitchLog<N>) -> Self {
+        Replay { inner, log }
+    }
+
+    /// The log of the switches.
+    pub fn log(&self) -> &'static SwitchLog<N> {
+        self.log
+    }
+
+    /// The next switch in the recording, if any.
+    pub fn next(&self) -> Option<Switch> {
+        self.log.get(self.log.replayed.load(Relaxed))
+    }
+}
+
+// SAFETY: The contexts are created and resumed by the inner resumer.
+unsafe impl<R: Resume, const N: usize> Resume for Replay<R, N> {
+    type Context = R::Context;
+
+    type NewError = R::NewError;
+
+    fn backend_info(&self) -> BackendInfo {
+        self.inner.backend_info()
+    }
+
+    unsafe fn new_on(
+        &self,
+        stack: NonNull<[u8]>,
+        entry: Entry<R::Context>,
+    ) -> Result<NonNull<R::Context>, R::NewError> {
+        // SAFETY: The contract is the same.
+        let cx = unsafe { self.inner.new_on(stack, entry) }?;
+        self.log.register(stack);
+        Ok(cx)
+    }
+
+    #[track_caller]
+    unsafe fn resume(
+        &self,
+        cx: NonNull<R::Context>,
+        data: *mut (),
+    ) -> Transfer<R::Context> {
+        self.log.check(self.log.switch(cx));
+        // SAFETY: The contract is the same.
+        unsafe { self.inner.resume(cx, data) }
+    }
+
+    #[track_caller]
+    unsafe fn resume_with(
+        &self,
+        cx: NonNull<R::Context>,
+        data: *mut (),
+        map: Map<R::Context>,
+    ) -> Transfer<R::Context> {
+        self.log.check(self.log.switch(cx));
+        // SAFETY: The contract is the same.
+        unsafe { self.inner.resume_with(cx, data, map) }
+    }
+
+    unsafe fn dealloc_context(&self, cx: NonNull<R::Context>) {
+        // SAFETY: The contract is the same.
+        unsafe { self.inner.dealloc_context(cx) }
+    }
+    #[track_caller]
+    fn selftest(&self) {
+        self.inner.selftest()
+    }
+}
@@ -1,21 +1,28 @@
 use core::{
     cell::Cell,
-    mem,
+    ffi::c_int,
+    fmt,
+    mem::{self, MaybeUninit},
     ptr::{self, NonNull},
 };
-use std::{boxed::Box, io::Error as IoError};
+#[cfg(debug_assertions)]
+use std::thread::{self, ThreadId};
+use std::{
+    boxed::Box,
+    io::{Error as IoError, ErrorKind},
+};
 
 use libc::ucontext_t;
 
-use crate::{stack_top, Entry, Map, Resume};
+use crate::{stack_top, BackendInfo, Entry, Map, Resume};
 
-type Transfer = crate::Transfer<ucontext_t>;
+type Transfer = crate::Transfer<Ucx>;
 
 std::thread_local! {
     static TRANSFER: Cell<LocalTransfer> = {
         Cell::new(LocalTransfer {
             from: None,
-            ucx: new_root(),
+            ucx: None,
             on_top: None,
             data: ptr::null_mut(),
         })
@@ -24,98 +31,404 @@ std::thread_local! {
 
 #[derive(Debug, Clone, Copy)]
 struct LocalTransfer {
-    from: Option<NonNull<ucontext_t>>,
-    ucx: NonNull<ucontext_t>,
-    on_top: Option<Map<ucontext_t>>,
+    from: Option<NonNull<Ucx>>,
+    /// The current context, which is lazily set to the root context on the
+    /// first switch.
+    ucx: Option<NonNull<Ucx>>,
+    on_top: Option<Map<Ucx>>,
     data: *mut (),
 }
 
-/// The wrapper type of a pointer to `ucontext_t` with some additional data.
+impl LocalTransfer {
+    /// The context switched from, which is always recorded before a switch.
+    #[track_caller]
+    fn source(&self) -> NonNull<Ucx> {
+        self.from.expect(
+            "no source context is recorded for the switch; a root context may be \
+             resumed as if it had a parent context",
+        )
+    }
+}
+
+/// The wrapper type of `ucontext_t` with some additional data.
+///
+/// The context switching relies on thread-local storage, so a context must be
+/// resumed on the thread where it's created. This is checked in debug builds.
+#[repr(C)]
+pub struct Ucx {
+    ucx: ucontext_t,
+    #[cfg(debug_assertions)]
+    thread: ThreadId,
+    /// The bounds of the stack, lazily recorded for the root context.
+    #[cfg(feature = "sanitizer")]
+    bounds: (*const core::ffi::c_void, usize),
+    /// The id of the stack registered to Valgrind, or
+    /// [`sanitizer::NO_STACK_ID`].
+    #[cfg(feature = "sanitizer")]
+    stack_id: usize,
+}
+
+impl Ucx {
+    /// # Safety
+    ///
+    /// `this` must point to a valid block of memory of `Ucx`.
+    unsafe fn init(this: *mut Ucx) -> Result<(), IoError> {
+        // SAFETY: `this` is valid by contract.
+        unsafe {
+            #[cfg(debug_assertions)]
+            ptr::addr_of_mut!((*this).thread).write(thread::current().id());
+            #[cfg(feature = "sanitizer")]
+            ptr::addr_of_mut!((*this).bounds).write((ptr::null(), 0));
+            #[cfg(feature = "sanitizer")]
+            ptr::addr_of_mut!((*this).stack_id).write(sanitizer::NO_STACK_ID);
+            if libc::getcontext(ptr::addr_of_mut!((*this).ucx)) != 0 {
+                return Err(IoError::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// Initializes the context like [`Ucx::init`], but without `getcontext`.
+    ///
+    /// The `ucontext_t` is zeroed except for the fields that `makecontext` and
+    /// `setcontext` rely on, i.e. the default floating-point environment. The
+    /// signal mask is left empty, so the context must only be resumed without
+    /// loading it, as [`FastUcontext`] does.
+    ///
+    /// # Safety
+    ///
+    /// `this` must point to a valid block of memory of `Ucx`.
+    #[cfg(all(target_os = "linux", target_env = "gnu", target_arch = "x86_64"))]
+    unsafe fn init_fast(this: *mut Ucx) -> Result<(), IoError> {
+        /// The default x87 control word.
+        const FCW: u32 = 0x037f;
+        /// The x87 tag word with all the registers empty.
+        const FTW: u32 = 0xffff;
+        /// The default MXCSR.
+        const MXCSR: u32 = 0x1f80;
+
+        // SAFETY: `this` is valid by contract.
+        unsafe {
+            #[cfg(debug_assertions)]
+            ptr::addr_of_mut!((*this).thread).write(thread::current().id());
+            #[cfg(feature = "sanitizer")]
+            ptr::addr_of_mut!((*this).bounds).write((ptr::null(), 0));
+            #[cfg(feature = "sanitizer")]
+            ptr::addr_of_mut!((*this).stack_id).write(sanitizer::NO_STACK_ID);
+
+            let ucx = ptr::addr_of_mut!((*this).ucx);
+            ucx.write_bytes(0, 1);
+            // glibc keeps the floating-point state right after the signal mask,
+            // where `getcontext` stores the environment with `fnstenv` and then
+            // MXCSR at its offset in `_libc_fpstate`.
+            let fpregs = ptr::addr_of_mut!((*ucx).uc_sigmask)
+                .add(1)
+                .cast::<libc::_libc_fpstate>();
+            fpregs
+                .cast::<[u32; 7]>()
+                .write([FCW, 0, FTW, 0, 0, 0, MXCSR]);
+            (*ucx).uc_mcontext.fpregs = fpregs;
+        }
+        Ok(())
+    }
 
-fn new_root() -> NonNull<ucontext_t> {
-    let mut ret = Box::new_uninit();
+    /// Falls back to [`Ucx::init`] on the targets whose layout of `ucontext_t`
+    /// is not known.
+    ///
+    /// # Safety
+    ///
+    /// `this` must point to a valid block of memory of `Ucx`.
+    #[cfg(not(all(target_os = "linux", target_env = "gnu", target_arch = "x86_64")))]
+    unsafe fn init_fast(this: *mut Ucx) -> Result<(), IoError> {
+        // SAFETY: `this` is valid by contract.
+        unsafe { Ucx::init(this) }
+    }
+
+    #[cfg(debug_assertions)]
+    #[track_caller]
+    fn check_thread(&self) {
+        let current = thread::current().id();
+        assert_eq!(
+            self.thread, current,
+            "a `ucontext_t` created on {:?} is resumed on {:?}, which is not supported by \
+             the thread-affine `Ucontext` resumer",
+            self.thread, current
+        );
+    }
+}
+
+impl fmt::Debug for Ucx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("Ucx");
+        d.field("stack", &self.ucx.uc_stack.ss_sp);
+        #[cfg(debug_assertions)]
+        d.field("thread", &self.thread);
+        d.finish_non_exhaustive()
+    }
+}
+
+#[track_caller]
+fn new_root() -> NonNull<Ucx> {
+    let mut ret = Box::<Ucx>::new_uninit();
     // SAFETY: `ret` contains a valid block of memory, waiting to be initialized.
-    let status = unsafe { libc::getcontext(ret.as_mut_ptr()) };
-    assert_eq!(
-        status,
-        0,
-        "Failed to construct top level context: {:?}",
-        IoError::last_os_error()
-    );
-    // SAFETY: `ret` is initialized by `libc::getcontext`.
+    let status = unsafe { Ucx::init(ret.as_mut_ptr()) };
+    if let Err(err) = status {
+        panic!("failed to construct the root context of the current thread: {err}");
+    }
+    // SAFETY: `ret` is initialized by `Ucx::init`.
     NonNull::from(Box::leak(unsafe { ret.assume_init() }))
 }
 
+/// # Safety
+///
+/// See [`Resume::dealloc_context`] for more information.
+unsafe fn dealloc_context(cx: NonNull<Ucx>) {
+    // SAFETY: The context is finished by contract, and its stack is still
+    // valid.
+    #[cfg(feature = "sanitizer")]
+    unsafe {
+        sanitizer::deregister_stack(cx)
+    };
+    #[cfg(not(feature = "sanitizer"))]
+    let _ = cx;
+}
+
+/// Sets the root context of the current thread in `root`, instead of the boxed
+/// one allocated on the first context switch.
+///
+/// This function must be called before any context switch on the current
+/// thread, or an error of [`ErrorKind::AlreadyExists`] is returned.
+///
+/// # Safety
+///
+/// `root` must outlive all the context switches on the current thread.
+pub unsafe fn init_root(root: &mut MaybeUninit<Ucx>) -> Result<(), IoError> {
+    let mut t = TRANSFER.get();
+    if t.ucx.is_some() {
+        return Err(ErrorKind::AlreadyExists.into());
+    }
+    // SAFETY: `root` is a valid block of memory, waiting to be initialized.
+    unsafe { Ucx::init(root.as_mut_ptr()) }?;
+    t.ucx = Some(NonNull::from(root).cast());
+    TRANSFER.set(t);
+    Ok(())
+}
+
+/// Splits the address of `entry` into two `int`s, the higher half first.
+fn split_entry(entry: Entry<Ucx>) -> [c_int; 2] {
+    let addr = entry as usize as u64;
+    [(addr >> 32) as u32 as c_int, addr as u32 as c_int]
+}
+
+/// Reassembles the entry split by [`split_entry`].
+///
+/// # Safety
+///
+/// `hi` and `lo` must be returned by [`split_entry`].
+unsafe fn join_entry(hi: c_int, lo: c_int) -> Entry<Ucx> {
+    let addr = (hi as u32 as u64) << 32 | lo as u32 as u64;
+    // SAFETY: The address is the one of an `Entry<Ucx>` by contract.
+    unsafe { mem::transmute::<usize, Entry<Ucx>>(addr as usize) }
+}
+
 /// # Safety
 ///
 /// See [`Resume::new_on`] for more information.
 unsafe fn new_on(
     stack: NonNull<[u8]>,
-    entry: Entry<ucontext_t>,
-) -> Result<NonNull<ucontext_t>, NewError> {
-    #[allow(improper_ctypes_definitions)]
-    unsafe extern "C" fn wrapper(entry: Entry<ucontext_t>) {
+    entry: Entry<Ucx>,
+    fast: bool,
+) -> Result<NonNull<Ucx>, NewError> {
+    unsafe extern "C" fn wrapper(hi: c_int, lo: c_int) {
+        // SAFETY: The halves are split from an `Entry<Ucx>` below.
+        let entry = unsafe { join_entry(hi, lo) };
         let t = TRANSFER.get();
-        entry(t.from.unwrap(), t.data);
+        // SAFETY: The context is just switched from `t.from`.
+        #[cfg(feature = "sanitizer")]
+        unsafe {
+            sanitizer::finish_switch(ptr::null_mut(), t.source())
+        };
+        entry(t.source(), t.data);
     }
 
-    let pointer: NonNull<ucontext_t> = stack_top(stack).ok_or(NewError::StackTooSmall)?;
-    let ucx = pointer.as_ptr();
+    let pointer: NonNull<Ucx> =
+        stack_top(stack).map_err(|minimum| NewError::StackTooSmall {
+            provided: stack.len(),
+            minimum,
+        })?;
 
-    // SAFETY: `ucx` is proper aligned and points to a valid block of uninitialized
-    // memory.
-    let status = unsafe { libc::getcontext(ucx) };
-    if status != 0 {
-        return Err(NewError::GetContext(IoError::last_os_error()));
-    }
+    let init = if fast { Ucx::init_fast } else { Ucx::init };
+    // SAFETY: `pointer` is proper aligned and points to a valid block of
+    // uninitialized memory.
+    unsafe { init(pointer.as_ptr()) }.map_err(NewError::GetContext)?;
+    // SAFETY: `pointer` is initialized above.
+    let ucx = unsafe { ptr::addr_of_mut!((*pointer.as_ptr()).ucx) };
 
-    // SAFETY: `ucx` is initialized by `libc::getcontext`; `pointer` is greater than
-    // `stack`'s base pointer by address and points to the same block of memory.
+    // SAFETY: `ucx` is initialized by `Ucx::init` or `Ucx::init_fast`; `pointer` is
+    // greater than `stack`'s base pointer by address and points to the same block
+    // of memory.
     unsafe {
         let ucx = &mut *ucx;
         ucx.uc_stack.ss_sp = stack.as_ptr().cast();
         ucx.uc_stack.ss_size = pointer.as_ptr().byte_offset_from(ucx.uc_stack.ss_sp) as _;
         ucx.uc_link = ptr::null_mut();
+
+        #[cfg(feature = "sanitizer")]
+        sanitizer::register_stack(pointer, ucx.uc_stack.ss_sp, ucx.uc_stack.ss_size);
     }
 
-    // SAFETY: `ucx` is initialized by `libc::getcontext`; `wrapper` has exactly 1
-    // parameter.
+    // The variadic arguments of `makecontext` are `int`s, which cannot hold a
+    // pointer on LP64 targets.
+    let [hi, lo] = split_entry(entry);
+    // SAFETY: `ucx` is initialized above; `wrapper` has exactly 2 `int` parameters.
     unsafe {
-        let wrapper = mem::transmute(wrapper as unsafe extern "C" fn(_));
-        libc::makecontext(ucx, wrapper, 1, entry)
+        let wrapper = mem::transmute::<unsafe extern "C" fn(c_int, c_int), extern "C" fn()>(
+            wrapper,
+        );
+        libc::makecontext(ucx, wrapper, 2, hi, lo)
     };
 
     Ok(pointer)
 }
 
+/// Switches from `src` to `target` like `swapcontext`, but without saving or
+/// restoring the signal mask, which saves the 2 system calls of
+/// `rt_sigprocmask` per switch.
+///
+/// The registers are saved to and restored from the same places as the ones of
+/// `swapcontext` and `setcontext`, including the arguments set up by
+/// `makecontext`, so the contexts stay interchangeable with them.
+///
+/// # Safety
+///
+/// Both pointers must point to valid `ucontext_t`s, whose `fpregs` point to
+/// their own floating-point state.
+#[cfg(all(target_os = "linux", target_env = "gnu", target_arch = "x86_64"))]
+unsafe fn swap_unmasked(src: *mut ucontext_t, target: *const ucontext_t) {
+    // SAFETY: The offsets are the ones of `REG_*` in `gregs` and `fpregs` in
+    // `mcontext_t`, and of MXCSR in `_libc_fpstate`. The registers preserved
+    // across calls are restored from `src` when switched back to the label.
+    unsafe {
+        core::arch::asm!(
+            "lea rax, [rip + 2f]",
+            "mov [rdi + 8 * 16], rax",
+            "mov [rdi + 8 * 15], rsp",
+            "mov [rdi + 8 * 11], rbx",
+            "mov [rdi + 8 * 10], rbp",
+            "mov [rdi + 8 * 4], r12",
+            "mov [rdi + 8 * 5], r13",
+            "mov [rdi + 8 * 6], r14",
+            "mov [rdi + 8 * 7], r15",
+            "mov rax, [rdi + 8 * 23]",
+            "fnstenv [rax]",
+            "stmxcsr [rax + 24]",
+            "mov rax, [rsi + 8 * 23]",
+            "fldenv [rax]",
+            "ldmxcsr [rax + 24]",
+            "mov rsp, [rsi + 8 * 15]",
+            "mov rbx, [rsi + 8 * 11]",
+            "mov rbp, [rsi + 8 * 10]",
+            "mov r12, [rsi + 8 * 4]",
+            "mov r13, [rsi + 8 * 5]",
+            "mov r14, [rsi + 8 * 6]",
+            "mov r15, [rsi + 8 * 7]",
+            "push qword ptr [rsi + 8 * 16]",
+            "mov rdi, [rsi + 8 * 8]",
+            "mov rdx, [rsi + 8 * 12]",
+            "mov rcx, [rsi + 8 * 14]",
+            "mov r8, [rsi + 8 * 0]",
+            "mov r9, [rsi + 8 * 1]",
+            "mov rsi, [rsi + 8 * 9]",
+            "ret",
+            "2:",
+            in("rdi") ptr::addr_of_mut!((*src).uc_mcontext),
+            in("rsi") ptr::addr_of!((*target).uc_mcontext),
+            clobber_abi("C"),
+        )
+    }
+}
+
+/// Falls back to `swapcontext` on the targets whose layout of `ucontext_t` is
+/// not known.
+///
+/// # Safety
+///
+/// Both pointers must point to valid `ucontext_t`s.
+#[cfg(not(all(target_os = "linux", target_env = "gnu", target_arch = "x86_64")))]
+unsafe fn swap_unmasked(src: *mut ucontext_t, target: *const ucontext_t) {
+    // SAFETY: Both pointers are valid by contract.
+    let status = unsafe { libc::swapcontext(src, target) };
+    assert_eq!(
+        status,
+        0,
+        "failed to swap context: {:?}",
+        IoError::last_os_error()
+    );
+}
+
 /// # Safety
 ///
 /// See [`Resume::resume`] for more information.
+#[track_caller]
 unsafe fn resume_with(
-    target: NonNull<ucontext_t>,
-    on_top: Option<Map<ucontext_t>>,
+    target: NonNull<Ucx>,
+    on_top: Option<Map<Ucx>>,
     data: *mut (),
+    save_sigmask: bool,
 ) -> Transfer {
-    let src = TRANSFER.get().ucx;
+    // SAFETY: `target` is valid by contract.
+    #[cfg(debug_assertions)]
+    unsafe { target.as_ref() }.check_thread();
+
+    let src = match TRANSFER.get().ucx {
+        Some(ucx) => ucx,
+        None => new_root(),
+    };
     TRANSFER.set(LocalTransfer {
         from: Some(src),
-        ucx: target,
+        ucx: Some(target),
         on_top,
         data,
     });
 
+    #[cfg(feature = "sanitizer")]
+    let mut fake_stack = ptr::null_mut();
+    // SAFETY: `target` is valid by contract.
+    #[cfg(feature = "sanitizer")]
+    unsafe {
+        sanitizer::start_switch(&mut fake_stack, target)
+    };
+
     // SAFETY: Both pointers have their reference to a valid `ucontext_t`
     // respectively.
-    let status = unsafe { libc::swapcontext(src.as_ptr(), target.as_ptr()) };
-    assert_eq!(
-        status,
-        0,
-        "failed to swap context: {:?}",
-        IoError::last_os_error()
-    );
+    let (src, target) = unsafe {
+        (
+            ptr::addr_of_mut!((*src.as_ptr()).ucx),
+            ptr::addr_of!((*target.as_ptr()).ucx),
+        )
+    };
+    if save_sigmask {
+        // SAFETY: See above.
+        let status = unsafe { libc::swapcontext(src, target) };
+        assert_eq!(
+            status,
+            0,
+            "failed to swap context: {:?}",
+            IoError::last_os_error()
+        );
+    } else {
+        // SAFETY: See above. The `fpregs` of every context is set up by
+        // `Ucx::init` or `Ucx::init_fast`.
+        unsafe { swap_unmasked(src, target) };
+    }
 
     let t = TRANSFER.get();
-    let ucx = t.from.unwrap();
+    let ucx = t.source();
+    // SAFETY: The context is just switched from `ucx`.
+    #[cfg(feature = "sanitizer")]
+    unsafe {
+        sanitizer::finish_switch(fake_stack, ucx)
+    };
     match t.on_top {
         Some(on_top) => on_top(ucx, t.data),
         None => Transfer {
@@ -126,40 +439,467 @@ unsafe fn resume_with(
 }
 
 /// The [`Resume`] implementation with the POSIX library's [`makecontext`](https://man7.org/linux/man-pages/man3/makecontext.3.html) functionalities.
-#[derive(Debug, Copy, Clone, Default)]
-pub struct Ucontext;
+///
+/// The contexts are thread-affine: resuming one on another thread panics in
+/// debug builds. See [`Ucx`] for more information.
+///
+/// By default, every switch saves and restores the signal mask with
+/// `swapcontext`, so each context keeps its own one. A resumer built with
+/// [`UcontextBuilder::save_sigmask`] disabled switches without it instead,
+/// which is much faster for the programs that never change the signal mask
+/// in a coroutine:
+///
+/// ```rust
+/// use unico_context::{global_resumer, ucx::Ucontext};
+///
+/// static RESUMER: Ucontext = Ucontext::builder().save_sigmask(false).build();
+/// global_resumer!(RESUMER);
+/// ```
+#[derive(Debug, Copy, Clone)]
+pub struct Ucontext {
+    save_sigmask: bool,
+}
+
+impl Default for Ucontext {
+    fn default() -> Self {
+        Ucontext::DEFAULT
+    }
+}
 
 #[derive(Debug)]
 pub enum NewError {
-    StackTooSmall,
+    /// The stack cannot hold the context, which needs at least `minimum`
+    /// bytes.
+    StackTooSmall {
+        provided: usize,
+        minimum: usize,
+    },
     GetContext(IoError),
 }
 
+impl fmt::Display for NewError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NewError::StackTooSmall { provided, minimum } => write!(
+                f,
+                "the stack is too small: got {provided} bytes, need at least {minimum}"
+            ),
+            NewError::GetContext(err) => write!(f, "failed to get the context: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for NewError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NewError::StackTooSmall { .. } => None,
+            NewError::GetContext(err) => Some(err),
+        }
+    }
+}
+
+impl From<NewError> for IoError {
+    fn from(err: NewError) -> Self {
+        match err {
+            NewError::StackTooSmall { .. } => IoError::new(ErrorKind::InvalidInput, err),
+            NewError::GetContext(err) => err,
+        }
+    }
+}
+
 // SAFETY: The `ucontext_t` is created on the given stack. See `self::new_on`
 // for more information.
 unsafe impl Resume for Ucontext {
-    type Context = ucontext_t;
+    type Context = Ucx;
+
+    type NewError = NewError;
+
+    fn backend_info(&self) -> BackendInfo {
+        BackendInfo {
+            name: "ucontext",
+            thread_affine: true,
+            saves_fp: true,
+        }
+    }
+
+    unsafe fn new_on(
+        &self,
+        stack: NonNull<[u8]>,
+        entry: Entry<Ucx>,
+    ) -> Result<NonNull<Ucx>, NewError> {
+        new_on(stack, entry, false)
+    }
+
+    #[track_caller]
+    unsafe fn resume(&self, cx: NonNull<Ucx>, data: *mut ()) -> Transfer {
+        resume_with(cx, None, data, self.save_sigmask)
+    }
+
+    #[track_caller]
+    unsafe fn resume_with(
+        &self,
+        cx: NonNull<Ucx>,
+        data: *mut (),
+        map: Map<Ucx>,
+    ) -> Transfer {
+        resume_with(cx, Some(map), data, self.save_sigmask)
+    }
+
+    unsafe fn dealloc_context(&self, cx: NonNull<Ucx>) {
+        // SAFETY: The contract is the same.
+        unsafe { dealloc_context(cx) }
+    }
+}
+
+impl Ucontext {
+    /// The default resumer, saving the signal mask on every switch:
+    ///
+    /// ```rust
+    /// use unico_context::{global_resumer, ucx::Ucontext};
+    ///
+    /// global_resumer!(Ucontext::DEFAULT);
+    /// ```
+    pub const DEFAULT: Ucontext = Ucontext::builder().build();
+
+    /// Starts building a resumer, which saves the signal mask unless
+    /// configured otherwise.
+    pub const fn builder() -> UcontextBuilder {
+        UcontextBuilder { save_sigmask: true }
+    }
+
+    /// Whether the signal mask is saved and restored on every switch.
+    pub const fn saves_sigmask(&self) -> bool {
+        self.save_sigmask
+    }
+
+    /// The resumer creating the contexts without calling `getcontext`, which
+    /// saves a syscall per creation.
+    ///
+    /// The `ucontext_t` is zeroed instead, with only the fields required by
+    /// `makecontext` set up. Consequently, a new context starts with the
+    /// default floating-point environment rather than the one of the creating
+    /// thread, and an empty signal mask in its `ucontext_t`.
+    ///
+    /// That mask is never loaded: the returned resumer switches without the
+    /// signal mask, like a [`Ucontext`] with [`UcontextBuilder::save_sigmask`]
+    /// disabled, so the signals blocked by the thread stay blocked inside the
+    /// contexts. A [`Ucontext`] saving the signal mask must not resume these
+    /// contexts for the first time, since it would unblock every signal.
+    ///
+    /// This relies on the layout of `ucontext_t` in glibc on x86_64 Linux. On
+    /// other targets, the returned resumer falls back to `getcontext`.
+    pub const fn fast_create() -> FastUcontext {
+        FastUcontext
+    }
+}
+
+/// The builder of [`Ucontext`], created by [`Ucontext::builder`].
+#[derive(Debug, Copy, Clone)]
+pub struct UcontextBuilder {
+    save_sigmask: bool,
+}
+
+impl UcontextBuilder {
+    /// Sets whether the signal mask is saved and restored on every switch,
+    /// which is `true` by default.
+    ///
+    /// Without it, a switch is done by a few instructions instead of the 2
+    /// system calls of `rt_sigprocmask`, and every context runs with the
+    /// signal mask of the thread at the time, rather than its own one. This is
+    /// only supported with glibc on x86_64 Linux. On other targets, the signal
+    /// mask is saved anyway.
+    pub const fn save_sigmask(mut self, save_sigmask: bool) -> Self {
+        self.save_sigmask = save_sigmask;
+        self
+    }
+
+    /// Builds the resumer.
+    pub const fn build(self) -> Ucontext {
+        Ucontext {
+            save_sigmask: self.save_sigmask,
+        }
+    }
+}
+
+/// The [`Resume`] implementation returned by [`Ucontext::fast_create`].
+///
+/// It resumes any context without switching the signal mask. The contexts it
+/// creates are the same as the ones of [`Ucontext`] once created, except for
+/// the empty signal mask, see [`Ucontext::fast_create`].
+#[derive(Debug, Copy, Clone, Default)]
+pub struct FastUcontext;
+
+// SAFETY: See the implementation for `Ucontext`.
+unsafe impl Resume for FastUcontext {
+    type Context = Ucx;
 
     type NewError = NewError;
 
+    fn backend_info(&self) -> BackendInfo {
+        Ucontext::DEFAULT.backend_info()
+    }
+
     unsafe fn new_on(
         &self,
         stack: NonNull<[u8]>,
-        entry: Entry<ucontext_t>,
-    ) -> Result<NonNull<ucontext_t>, NewError> {
-        new_on(stack, entry)
+        entry: Entry<Ucx>,
+    ) -> Result<NonNull<Ucx>, NewError> {
+        new_on(stack, entry, true)
     }
 
-    unsafe fn resume(&self, cx: NonNull<ucontext_t>, data: *mut ()) -> Transfer {
-        resume_with(cx, None, data)
+    #[track_caller]
+    unsafe fn resume(&self, cx: NonNull<Ucx>, data: *mut ()) -> Transfer {
+        resume_with(cx, None, data, false)
     }
 
+    #[track_caller]
     unsafe fn resume_with(
         &self,
-        cx: NonNull<ucontext_t>,
+        cx: NonNull<Ucx>,
         data: *mut (),
-        map: Map<ucontext_t>,
-    ) -> crate::Transfer<ucontext_t> {
-        resume_with(cx, Some(map), data)
+        map: Map<Ucx>,
+    ) -> Transfer {
+        resume_with(cx, Some(map), data, false)
+    }
+
+    unsafe fn dealloc_context(&self, cx: NonNull<Ucx>) {
+        // SAFETY: The contract is the same.
+        unsafe { dealloc_context(cx) }
+    }
+}
+
+/// The integration with the fiber APIs of sanitizers, so that they are aware of
+/// the switched stacks.
+///
+/// The stacks registered to Valgrind are deregistered when their contexts are
+/// deallocated by [`Resume::dealloc_context`], or leaked otherwise.
+#[cfg(feature = "sanitizer")]
+mod sanitizer {
+    use core::{ffi::c_void, ptr::NonNull};
+
+    use super::Ucx;
+
+    // SAFETY: These functions are provided by the ASan runtime, and are null if
+    // it's not linked.
+    unsafe extern "C" {
+        #[linkage = "extern_weak"]
+        static __sanitizer_start_switch_fiber:
+            Option<unsafe extern "C" fn(*mut *mut c_void, *const c_void, usize)>;
+        #[linkage = "extern_weak"]
+        static __sanitizer_finish_switch_fiber:
+            Option<unsafe extern "C" fn(*mut c_void, *mut *const c_void, *mut usize)>;
+    }
+
+    /// Issues a Valgrind client request, which is a no-op returning `default`
+    /// if the program is not running on Valgrind.
+    #[cfg(target_arch = "x86_64")]
+    unsafe fn client_request(default: usize, args: [usize; 6]) -> usize {
+        let ret;
+        // SAFETY: The magic sequence rotates `rdi` by 128 bits in total, which
+        // changes nothing unless it's recognized by Valgrind.
+        unsafe {
+            core::arch::asm!(
+                "rol rdi, 3",
+                "rol rdi, 13",
+                "rol rdi, 61",
+                "rol rdi, 51",
+                "xchg rbx, rbx",
+                inout("rdx") default => ret,
+                in("rax") args.as_ptr(),
+                inout("rdi") 0usize => _,
+                options(nostack),
+            )
+        };
+        ret
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    unsafe fn client_request(default: usize, _: [usize; 6]) -> usize {
+        default
+    }
+
+    const VG_USERREQ_STACK_REGISTER: usize = 0x1501;
+    const VG_USERREQ_STACK_DEREGISTER: usize = 0x1502;
+    /// The id of the stacks not registered, which Valgrind never hands out.
+    pub const NO_STACK_ID: usize = usize::MAX;
+
+    /// Records the bounds of the stack of a new context, and registers it to
+    /// Valgrind.
+    ///
+    /// # Safety
+    ///
+    /// `ucx` must be initialized with `Ucx::init`.
+    pub unsafe fn register_stack(
+        mut ucx: NonNull<Ucx>,
+        bottom: *mut c_void,
+        size: usize,
+    ) {
+        // SAFETY: `ucx` is valid by contract.
+        unsafe { ucx.as_mut().bounds = (bottom, size) };
+        let start = bottom as usize;
+        let args = [VG_USERREQ_STACK_REGISTER, start, start + size, 0, 0, 0];
+        // SAFETY: The request only informs Valgrind of the stack.
+        let id = unsafe { client_request(NO_STACK_ID, args) };
+        // SAFETY: `ucx` is valid by contract.
+        unsafe { ucx.as_mut().stack_id = id };
+    }
+
+    /// Deregisters the stack of a finished context from Valgrind, if it's
+    /// registered by [`register_stack`].
+    ///
+    /// # Safety
+    ///
+    /// `ucx` must be a valid context.
+    pub unsafe fn deregister_stack(ucx: NonNull<Ucx>) {
+        // SAFETY: `ucx` is valid by contract.
+        let id = unsafe { ucx.as_ref() }.stack_id;
+        if id != NO_STACK_ID {
+            let args = [VG_USERREQ_STACK_DEREGISTER, id, 0, 0, 0, 0];
+            // SAFETY: The request only informs Valgrind of the stack.
+            unsafe { client_request(0, args) };
+        }
+    }
+
+    /// Notifies ASan that the execution is about to switch to `target`.
+    ///
+    /// # Safety
+    ///
+    /// `target` must be a valid context.
+    pub unsafe fn start_switch(fake_stack: &mut *mut c_void, target: NonNull<Ucx>) {
+        // SAFETY: The function is either provided by ASan or null.
+        if let Some(start) = unsafe { __sanitizer_start_switch_fiber } {
+            // SAFETY: `target` is valid by contract.
+            let (bottom, size) = unsafe { target.as_ref() }.bounds;
+            // SAFETY: The bounds are recorded from the actual stack.
+            unsafe { start(fake_stack, bottom, size) };
+        }
+    }
+
+    /// Notifies ASan that the execution has switched from `from`, recording the
+    /// bounds of its stack if they're unknown.
+    ///
+    /// # Safety
+    ///
+    /// `from` must be the valid context that the execution is switched from.
+    pub unsafe fn finish_switch(fake_stack: *mut c_void, mut from: NonNull<Ucx>) {
+        // SAFETY: The function is either provided by ASan or null.
+        if let Some(finish) = unsafe { __sanitizer_finish_switch_fiber } {
+            let mut bounds = (core::ptr::null(), 0);
+            // SAFETY: The pointers are valid for writes.
+            unsafe { finish(fake_stack, &mut bounds.0, &mut bounds.1) };
+            // SAFETY: `from` is valid by contract.
+            let from = unsafe { from.as_mut() };
+            if from.bounds.0.is_null() {
+                from.bounds = bounds;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        ffi::c_int,
+        mem::{self, MaybeUninit},
+        ptr::NonNull,
+    };
+    use std::{io::ErrorKind, thread, vec};
+
+    use super::{init_root, join_entry, split_entry, FastUcontext, Ucontext, Ucx};
+    use crate::{Entry, Resume};
+
+    unsafe extern "C" fn entry(_: NonNull<Ucx>, _: *mut ()) -> ! {
+        unreachable!()
+    }
+
+    #[test]
+    fn entry_split() {
+        // The split matters where `int` is narrower than a pointer.
+        assert_eq!(mem::size_of::<c_int>(), 4);
+
+        let entry: Entry<Ucx> = entry;
+        let [hi, lo] = split_entry(entry);
+        let joined = unsafe { join_entry(hi, lo) };
+        assert_eq!(joined as usize, entry as usize);
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(hi as u32 as usize, entry as usize >> 32);
+    }
+
+    #[test]
+    fn root_in_place() {
+        /// Switches back to the root context, telling its address.
+        unsafe extern "C" fn back(cx: NonNull<Ucx>, _: *mut ()) -> ! {
+            // SAFETY: `cx` is the root context suspended in `resume` below.
+            unsafe { Ucontext::DEFAULT.resume(cx, cx.as_ptr().cast()) };
+            unreachable!("the context is never resumed again")
+        }
+
+        // A fresh thread, so that no switch happens before, and `root`
+        // outlives all the switches after.
+        thread::spawn(|| {
+            let mut root = MaybeUninit::uninit();
+            // SAFETY: `root` lives until the end of the thread.
+            unsafe { init_root(&mut root) }.unwrap();
+            let err = unsafe { init_root(&mut MaybeUninit::uninit()) }.unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+            let mut stack = vec![0u8; 64 * 1024];
+            // SAFETY: The stack is valid and not used by anything else.
+            let cx =
+                unsafe { Ucontext::DEFAULT.new_on(NonNull::from(&mut stack[..]), back) };
+            // SAFETY: `cx` is just created on the current thread.
+            let t =
+                unsafe { Ucontext::DEFAULT.resume(cx.unwrap(), core::ptr::null_mut()) };
+            assert_eq!(t.data, root.as_mut_ptr().cast());
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn fast_sigmask() {
+        /// Switches back, telling whether `SIGUSR1` is blocked.
+        unsafe extern "C" fn check(cx: NonNull<Ucx>, _: *mut ()) -> ! {
+            let mut mask = MaybeUninit::uninit();
+            // SAFETY: The new mask is null, so the current one is only read.
+            let status = unsafe {
+                libc::pthread_sigmask(
+                    libc::SIG_BLOCK,
+                    core::ptr::null(),
+                    mask.as_mut_ptr(),
+                )
+            };
+            assert_eq!(status, 0);
+            // SAFETY: `mask` is filled in above.
+            let blocked = unsafe { libc::sigismember(mask.as_ptr(), libc::SIGUSR1) };
+            // SAFETY: `cx` is the context suspended in `resume` below.
+            unsafe { FastUcontext.resume(cx, blocked as usize as *mut ()) };
+            unreachable!("the context is never resumed again")
+        }
+
+        // A fresh thread, so that the signal mask of the others is untouched.
+        thread::spawn(|| {
+            let mut mask = MaybeUninit::uninit();
+            // SAFETY: `mask` is initialized by `sigemptyset` before use.
+            unsafe {
+                libc::sigemptyset(mask.as_mut_ptr());
+                libc::sigaddset(mask.as_mut_ptr(), libc::SIGUSR1);
+                let status = libc::pthread_sigmask(
+                    libc::SIG_BLOCK,
+                    mask.as_ptr(),
+                    core::ptr::null_mut(),
+                );
+                assert_eq!(status, 0);
+            }
+
+            let mut stack = vec![0u8; 64 * 1024];
+            // SAFETY: The stack is valid and not used by anything else.
+            let cx = unsafe { FastUcontext.new_on(NonNull::from(&mut stack[..]), check) };
+            // SAFETY: `cx` is just created on the current thread.
+            let t = unsafe { FastUcontext.resume(cx.unwrap(), core::ptr::null_mut()) };
+            assert_eq!(t.data as usize, 1, "the blocked signal is unblocked");
+        })
+        .join()
+        .unwrap();
     }
 }
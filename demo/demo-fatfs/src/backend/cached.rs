@@ -1,10 +1,11 @@
 use std::{
-    collections::BTreeMap, io::{self, SeekFrom}, ops::Add, path::Path
+    collections::BTreeMap, io::{self, BorrowedBuf, BorrowedCursor, IoSlice, SeekFrom}, mem::MaybeUninit, ops::{Add, Range}, path::Path
 };
 
 use super::Backend;
 
-const PAGE_SIZE: usize = 1048576;
+/// The default size of a cache page.
+pub const PAGE_SIZE: usize = 1048576;
 
 pub enum PageType {
     FullPage {
@@ -17,18 +18,39 @@ pub enum PageType {
     },
 }
 
-pub struct PageRange {
+pub struct PageRange<const PAGE: usize = PAGE_SIZE> {
     pub start: u64,
     pub end: u64,
 }
 
-impl PageRange {
+impl<const PAGE: usize> PageRange<PAGE> {
+    /// Creates a range of bytes, which yields nothing if `start >= end`.
     pub fn new(start: u64, end: u64) -> Self {
         Self { start, end }
     }
+
+    /// Creates a range of bytes, returning `None` if `start > end`.
+    pub fn checked_new(start: u64, end: u64) -> Option<Self> {
+        (start <= end).then(|| Self::new(start, end))
+    }
+
+    /// Creates a range of `len` bytes from `start`, returning `None` if the
+    /// end overflows.
+    #[allow(dead_code)]
+    pub fn with_len(start: u64, len: u64) -> Option<Self> {
+        start.checked_add(len).map(|end| Self::new(start, end))
+    }
+}
+
+impl<const PAGE: usize> TryFrom<Range<u64>> for PageRange<PAGE> {
+    type Error = Range<u64>;
+
+    fn try_from(range: Range<u64>) -> Result<Self, Range<u64>> {
+        Self::checked_new(range.start, range.end).ok_or(range)
+    }
 }
 
-impl Iterator for PageRange {
+impl<const PAGE: usize> Iterator for PageRange<PAGE> {
     type Item = PageType;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -37,12 +59,16 @@ impl Iterator for PageRange {
         }
 
         let start = self.start;
-        let number = start / PAGE_SIZE as u64;
-        let offset = start % PAGE_SIZE as u64;
-        let expected_end = (start + 1).next_multiple_of(PAGE_SIZE as u64);
+        let number = start / PAGE as u64;
+        let offset = start % PAGE as u64;
+        // The end of the current page may overflow, in which case the range
+        // must end within this page.
+        let expected_end = (start + 1).checked_next_multiple_of(PAGE as u64);
+        let within = expected_end.is_some_and(|end| end <= self.end);
+        let expected_end = expected_end.unwrap_or(u64::MAX);
 
         if offset == 0 {
-            if expected_end <= self.end {
+            if within {
                 self.start = expected_end;
                 return Some(PageType::FullPage { number });
             } else {
@@ -54,7 +80,7 @@ impl Iterator for PageRange {
                 });
             }
         } else {
-            if expected_end <= self.end {
+            if within {
                 self.start = expected_end;
                 return Some(PageType::PartialPage {
                     number,
@@ -73,28 +99,91 @@ impl Iterator for PageRange {
     }
 }
 
+/// The maximum size of a batched write in `real_flush`.
+const MAX_BATCH: usize = 16 << 20;
+
+/// The CRC-32 (IEEE) of `data`, for the integrity checks of the cache.
+fn crc32(data: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 1 == 1 {
+                    (crc >> 1) ^ 0xedb8_8320
+                } else {
+                    crc >> 1
+                };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+
+    let crc = (data.iter()).fold(!0, |crc, byte| {
+        TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    });
+    !crc
+}
+
 /// A page in the cache.
-pub struct CachePage {
-    pub data: Box<[u8; PAGE_SIZE]>,
+pub struct CachePage<const PAGE: usize = PAGE_SIZE> {
+    pub data: Box<[u8; PAGE]>,
+    /// The number of bytes in this page that lie within the file, which is
+    /// less than `PAGE` only for the tail page of a file whose size is not
+    /// page-aligned.
+    pub len: usize,
 }
 
-impl CachePage {
-    pub fn new() -> Self {
+impl<const PAGE: usize> CachePage<PAGE> {
+    pub fn new(len: usize) -> Self {
         unsafe {
             Self {
                 data: Box::new_zeroed().assume_init(),
+                len,
             }
         }
     }
+
+    /// Creates a page whose first `stored` bytes are read from `reader`, and
+    /// the rest zeroed.
+    ///
+    /// The read part is never zeroed beforehand, which saves a memset of up to
+    /// `PAGE` bytes if `reader` implements [`Read::read_buf`](io::Read::read_buf).
+    pub fn read_from(
+        reader: &mut impl io::Read,
+        len: usize,
+        stored: usize,
+    ) -> io::Result<Self> {
+        // SAFETY: An array of `MaybeUninit` needs no initialization.
+        let mut data =
+            unsafe { Box::<[MaybeUninit<u8>; PAGE]>::new_uninit().assume_init() };
+        let (head, tail) = data.split_at_mut(stored);
+        tail.fill(MaybeUninit::new(0));
+        reader.read_buf_exact(BorrowedBuf::from(head).unfilled())?;
+        // SAFETY: The first `stored` bytes are filled by the read above, and the
+        // rest are zeroed.
+        let data = unsafe { Box::from_raw(Box::into_raw(data).cast::<[u8; PAGE]>()) };
+        Ok(Self { data, len })
+    }
+
+    /// The part of the page that lies within the file.
+    pub fn valid(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
 }
 
-impl AsRef<[u8]> for CachePage {
+impl<const PAGE: usize> AsRef<[u8]> for CachePage<PAGE> {
     fn as_ref(&self) -> &[u8] {
         self.data.as_ref()
     }
 }
 
-impl AsMut<[u8]> for CachePage {
+impl<const PAGE: usize> AsMut<[u8]> for CachePage<PAGE> {
     fn as_mut(&mut self) -> &mut [u8] {
         self.data.as_mut()
     }
@@ -104,15 +193,41 @@ impl AsMut<[u8]> for CachePage {
 ///
 /// Will flush the dirty pages to the disk if and only if the `real_flush` is
 /// called.
-pub struct CachedBackend<B: Backend> {
+///
+/// Writes past the end extend the file like [`std::fs::File`], so
+/// `SeekFrom::End` is relative to the grown length even before it's flushed.
+///
+/// The size of the cache pages is `PAGE` bytes, which defaults to 1MiB. Smaller
+/// pages reduce the read-modify-write amplification of partial writes.
+///
+/// The position of the inner backend is independent of the one of the cache.
+/// After every operation, it's left where the last access to the backend
+/// ended, or unknown if that access failed. The position is tracked to save
+/// the seeks, and never queried from the backend, since every query may cost
+/// as much as a seek, e.g. for `UnicoBackend`. Therefore any direct access to
+/// the inner backend must go through [`Self::backend_mut`], which forgets the
+/// tracked position.
+pub struct CachedBackend<B: Backend, const PAGE: usize = PAGE_SIZE> {
     backend: B,
-    cache: BTreeMap<u64, CachePage>,
+    cache: BTreeMap<u64, CachePage<PAGE>>,
     dirty: BTreeMap<u64, bool>,
     my_pos: u64, // seeking may also be very expensive
-    my_len: u64, // we assume that the length of the file is fixed
+    my_len: u64, // grows with the writes past the end
+    stored_len: u64, // the length in the backend, behind `my_len` until flushed
+    auto_flush: usize, // 0 for never
+    writes: usize,     // since the last real flush
+    // The error of the last automatic flush, reported by the next write or flush.
+    flush_error: Option<io::Error>,
+    read_ahead: u64,   // 0 for never
+    last_page: Option<u64>,
+    backend_pos: Option<u64>, // None if unknown
+    punch_holes: bool,        // false once the backend fails to
+    closed: bool,             // no flush on drop once closed
+    // The CRCs of the clean pages, if checked.
+    checksums: Option<BTreeMap<u64, u32>>,
 }
 
-impl<B: Backend> CachedBackend<B> {
+impl<B: Backend, const PAGE: usize> CachedBackend<B, PAGE> {
     pub fn new(mut backend: B) -> Self {
         let my_len = backend.seek(SeekFrom::End(0)).unwrap();
         backend.seek(SeekFrom::Start(0)).unwrap();
@@ -127,11 +242,264 @@ impl<B: Backend> CachedBackend<B> {
             dirty: BTreeMap::new(),
             my_pos: 0,
             my_len: len,
+            stored_len: len,
+            auto_flush: 0,
+            writes: 0,
+            flush_error: None,
+            read_ahead: 0,
+            last_page: None,
+            backend_pos: None,
+            punch_holes: true,
+            closed: false,
+            checksums: None,
+        }
+    }
+
+    /// Calls `real_flush` automatically after every `writes` non-empty calls
+    /// to `write`, bounding the amount of data lost on a crash. `0` disables
+    /// it.
+    ///
+    /// The bytes of the write triggering the flush are accepted anyway, and a
+    /// failure of the flush is returned by the next `write` or `flush`.
+    #[allow(dead_code)]
+    pub fn auto_flush_every(mut self, writes: usize) -> Self {
+        self.auto_flush = writes;
+        self
+    }
+
+    /// Prefetches up to `pages` following pages in one backend read when a
+    /// cache miss continues a forward-sequential read. `0` disables it.
+    #[allow(dead_code)]
+    pub fn read_ahead(mut self, pages: usize) -> Self {
+        self.read_ahead = pages as u64;
+        self
+    }
+
+    /// Keeps a CRC of every clean page in the cache, and verifies it before
+    /// the page is used, logging the pages mutated in memory behind the cache,
+    /// e.g. by a rogue pointer.
+    ///
+    /// This is meant for debugging corruption only, since every access to a
+    /// page computes the CRC of the whole page.
+    #[allow(dead_code)]
+    pub fn with_integrity_checks(mut self, enabled: bool) -> Self {
+        self.checksums = enabled.then(|| {
+            let clean = self.dirty.iter().filter(|(_, dirty)| !**dirty);
+            clean
+                .map(|(number, _)| (*number, crc32(&self.cache[number].data[..])))
+                .collect()
+        });
+        self
+    }
+
+    /// Flushes the cache like `real_flush` and closes the backend, returning
+    /// the error of the flush, e.g. when the disk is full, which `Drop` can
+    /// only log.
+    #[allow(dead_code)]
+    pub fn close(mut self) -> io::Result<()> {
+        self.closed = true;
+        self.real_flush()
+    }
+
+    /// The inner backend, whose position may be moved freely.
+    #[allow(dead_code)]
+    pub fn backend_mut(&mut self) -> &mut B {
+        self.backend_pos = None;
+        &mut self.backend
+    }
+
+    /// Checks that every clean page in the cache matches its content in the
+    /// backend, which catches the write-back path diverging from the cache.
+    #[cfg(any(test, debug_assertions))]
+    #[allow(dead_code)]
+    pub fn verify_against_backend(&mut self) -> io::Result<bool> {
+        // The backend may have been touched directly.
+        self.backend_pos = None;
+        let origin_pos = self.backend.stream_position()?;
+        let clean: Vec<u64> = (self.dirty.iter())
+            .filter_map(|(number, dirty)| (!dirty).then_some(*number))
+            .collect();
+
+        let mut consistent = true;
+        for number in clean {
+            let stored = self.load_page(number)?;
+            if self.cache[&number].valid() != stored.valid() {
+                consistent = false;
+                break;
+            }
+        }
+
+        self.seek_backend(origin_pos)?;
+        Ok(consistent)
+    }
+
+    /// Seeks the backend to `pos`, unless it's known to be there already.
+    ///
+    /// Every access to the backend seeks with this function first, so the
+    /// position of the backend never needs restoring.
+    fn seek_backend(&mut self, pos: u64) -> io::Result<()> {
+        if self.backend_pos != Some(pos) {
+            self.backend_pos = None;
+            self.backend.seek(SeekFrom::Start(pos))?;
+            self.backend_pos = Some(pos);
+        }
+        Ok(())
+    }
+
+    /// Marks the cached page `number` clean, recording its CRC if checked.
+    fn mark_clean(&mut self, number: u64) {
+        self.dirty.insert(number, false);
+        if let Some(checksums) = &mut self.checksums {
+            checksums.insert(number, crc32(&self.cache[&number].data[..]));
+        }
+    }
+
+    /// Verifies the CRC of the page `number` if it's clean and checked, and
+    /// logs the mismatch.
+    fn check_page(&self, number: u64) -> bool {
+        let Some(expected) = (self.checksums.as_ref()).and_then(|c| c.get(&number))
+        else {
+            return true;
+        };
+        let actual = crc32(&self.cache[&number].data[..]);
+        if actual != *expected {
+            eprintln!(
+                "the clean page at {:#x} is mutated in the cache: expected CRC \
+                 {expected:#010x}, got {actual:#010x}",
+                number * PAGE as u64,
+            );
         }
+        actual == *expected
+    }
+
+    /// Records the position of the backend after an operation ending at `end`,
+    /// or forgets it if the operation failed halfway.
+    fn track_backend<T>(&mut self, result: io::Result<T>, end: u64) -> io::Result<T> {
+        self.backend_pos = result.is_ok().then_some(end);
+        result
+    }
+
+    /// The valid length of the page `number`.
+    fn page_len(&self, number: u64) -> usize {
+        let len = self.my_len.saturating_sub(number * PAGE as u64);
+        len.min(PAGE as u64) as usize
+    }
+
+    /// The number of bytes stored in the backend out of the `len` bytes at
+    /// `start`, the rest of which are only written in the cache so far.
+    fn stored(&self, start: u64, len: usize) -> usize {
+        let stored = self.stored_len.saturating_sub(start);
+        stored.min(len as u64) as usize
+    }
+
+    /// Extends the file to `end` if it's past the end, growing the valid
+    /// length of the cached pages on the way.
+    ///
+    /// The new part reads back as zeros, just like a file, and it's stored in
+    /// the backend once the tail page is flushed.
+    fn grow(&mut self, end: u64) {
+        if end <= self.my_len {
+            return;
+        }
+        let tail = self.my_len / PAGE as u64;
+        self.my_len = end;
+        for (number, page) in self.cache.range_mut(tail..) {
+            let len = end.saturating_sub(number * PAGE as u64);
+            page.len = len.min(PAGE as u64) as usize;
+        }
+    }
+
+    /// Reads the page `number` from the backend, leaving the part past the end
+    /// of the file zeroed.
+    fn load_page(&mut self, number: u64) -> io::Result<CachePage<PAGE>> {
+        let len = self.page_len(number);
+        let start = number * PAGE as u64;
+        let stored = self.stored(start, len);
+        self.seek_backend(start)?;
+        let result = CachePage::read_from(&mut self.backend, len, stored);
+        self.track_backend(result, start + stored as u64)
+    }
+
+    /// Gets the page `number` for reading, loading it on a cache miss together
+    /// with the pages read ahead.
+    ///
+    /// Only the pages not cached yet are loaded, so the dirty ones are never
+    /// overwritten.
+    fn read_page(&mut self, number: u64) -> io::Result<&CachePage<PAGE>> {
+        let sequential = self.last_page.is_some_and(|last| last + 1 == number);
+        self.last_page = Some(number);
+        if !self.cache.contains_key(&number) {
+            let ahead = if sequential { self.read_ahead } else { 0 };
+            let end = (number + 1..=number + ahead)
+                .find(|n| self.page_len(*n) == 0 || self.cache.contains_key(n))
+                .unwrap_or(number + ahead + 1);
+            self.load_pages(number..end)?;
+        }
+        self.check_page(number);
+        Ok(&self.cache[&number])
+    }
+
+    /// Writes the contiguous cached pages `numbers` back to the backend in one
+    /// vectored write, and marks them clean.
+    fn store_pages(&mut self, numbers: &[u64]) -> io::Result<()> {
+        let start = numbers[0] * PAGE as u64;
+        self.seek_backend(start)?;
+        // Only the valid part is written, so nothing is written past the end of
+        // the file.
+        let len: usize = numbers.iter().map(|n| self.cache[n].len).sum();
+        let mut pages: Vec<IoSlice<'_>> =
+            numbers.iter().map(|n| IoSlice::new(self.cache[n].valid())).collect();
+        let result = write_all_vectored(&mut self.backend, &mut pages);
+        self.track_backend(result, start + len as u64)?;
+        self.stored_len = self.stored_len.max(start + len as u64);
+
+        for number in numbers {
+            self.mark_clean(*number);
+        }
+        Ok(())
+    }
+
+    /// Punches the contiguous all-zero cached pages `numbers` out of the
+    /// backend instead of writing them, and marks them clean.
+    fn punch_pages(&mut self, numbers: &[u64]) -> io::Result<()> {
+        let start = numbers[0] * PAGE as u64;
+        let len: usize = numbers.iter().map(|n| self.cache[n].len).sum();
+        // The position of the backend is left untouched.
+        self.backend.punch_hole(start, len as u64)?;
+
+        for number in numbers {
+            self.mark_clean(*number);
+        }
+        Ok(())
+    }
+
+    /// Loads the uncached pages `numbers` from the backend in one read.
+    fn load_pages(&mut self, numbers: Range<u64>) -> io::Result<()> {
+        if numbers.end - numbers.start == 1 {
+            let cache = self.load_page(numbers.start)?;
+            self.cache.insert(numbers.start, cache);
+            self.mark_clean(numbers.start);
+            return Ok(());
+        }
+
+        let start = numbers.start * PAGE as u64;
+        let len: usize = numbers.clone().map(|n| self.page_len(n)).sum();
+        let stored = self.stored(start, len);
+        let mut buf = vec![0; len];
+        self.seek_backend(start)?;
+        let result = self.backend.read_exact(&mut buf[..stored]);
+        self.track_backend(result, start + stored as u64)?;
+
+        for (number, chunk) in numbers.zip(buf.chunks(PAGE)) {
+            let cache = CachePage::read_from(&mut &chunk[..], chunk.len(), chunk.len())?;
+            self.cache.insert(number, cache);
+            self.mark_clean(number);
+        }
+        Ok(())
     }
 }
 
-impl<B: Backend> Backend for CachedBackend<B> {
+impl<B: Backend, const PAGE: usize> Backend for CachedBackend<B, PAGE> {
     fn open<P: AsRef<Path> + Send>(path: P) -> io::Result<Self> {
         B::open(path).map(Self::new)
     }
@@ -173,64 +541,72 @@ impl<B: Backend> Backend for CachedBackend<B> {
     }
 
     fn real_flush(&mut self) -> io::Result<()> {
-        let origin_pos = self.backend.stream_position()?;
-
-        for (offset, page) in self.cache.iter_mut() {
-            if let Some(dirty) = self.dirty.get_mut(offset) {
-                if *dirty {
-                    self.backend.seek(SeekFrom::Start(*offset))?;
-                    self.backend.write_all(page.data.as_ref())?;
-                    *dirty = false;
+        let dirty: Vec<(u64, bool)> = (self.dirty.iter())
+            .filter(|(_, dirty)| **dirty)
+            .map(|(number, _)| {
+                let page = &self.cache[number];
+                let zero = page.valid().iter().all(|b| *b == 0);
+                // Punching never extends the backend, so the grown part is
+                // always written.
+                let stored = number * PAGE as u64 + page.len as u64 <= self.stored_len;
+                (*number, self.punch_holes && zero && stored)
+            })
+            .collect();
+        // Each run of contiguous dirty pages is written in batches, except that
+        // the all-zero ones are punched out to keep the image sparse, falling
+        // back to writing if the backend fails to.
+        for run in dirty.chunk_by(|a, b| a.0 + 1 == b.0 && a.1 == b.1) {
+            let numbers: Vec<u64> = run.iter().map(|(number, _)| *number).collect();
+            if run[0].1 && self.punch_holes {
+                if self.punch_pages(&numbers).is_ok() {
+                    continue;
                 }
+                self.punch_holes = false;
+            }
+            for batch in numbers.chunks((MAX_BATCH / PAGE).max(1)) {
+                self.store_pages(batch)?;
             }
         }
 
         self.backend.real_flush()?;
-        self.backend.seek(SeekFrom::Start(origin_pos))?;
+        self.writes = 0;
+        // Everything is stored in spite of the failed automatic flush.
+        self.flush_error = None;
 
         Ok(())
     }
 }
 
-impl<B: Backend> io::Read for CachedBackend<B> {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+impl<B: Backend, const PAGE: usize> CachedBackend<B, PAGE> {
+    /// Reads up to `len` bytes at the current position, passing the bytes of
+    /// every page to `sink` in order, and returns the number of bytes read.
+    fn read_with(
+        &mut self,
+        len: usize,
+        mut sink: impl FnMut(&[u8]),
+    ) -> io::Result<usize> {
         let start = self.my_pos;
         let mut read = 0;
 
-        for page in PageRange::new(start, start + buf.len() as u64) {
+        // Reads stop at the end of the file, see `Backend`.
+        let end = (start + len as u64).min(self.my_len);
+        if start >= end {
+            return Ok(0);
+        }
+        for page in PageRange::<PAGE>::new(start, end) {
             match page {
                 PageType::FullPage { number } => {
-                    if let Some(cache) = self.cache.get(&number) {
-                        buf[read..read + PAGE_SIZE].copy_from_slice(cache.as_ref());
-                    } else {
-                        let mut cache = CachePage::new();
-                        self.backend
-                            .seek(SeekFrom::Start(number * PAGE_SIZE as u64))?;
-                        self.backend.read_exact(cache.as_mut())?;
-                        buf[read..read + PAGE_SIZE].copy_from_slice(cache.as_ref());
-                        self.cache.insert(number, cache);
-                        self.dirty.insert(number, false);
-                    }
-                    read += PAGE_SIZE;
+                    let cache = self.read_page(number)?;
+                    sink(cache.as_ref());
+                    read += PAGE;
                 }
                 PageType::PartialPage {
                     number,
                     offset,
                     size,
                 } => {
-                    if let Some(cache) = self.cache.get(&number) {
-                        buf[read..read + size]
-                            .copy_from_slice(&cache.data[offset..offset + size]);
-                    } else {
-                        let mut cache = CachePage::new();
-                        self.backend
-                            .seek(SeekFrom::Start(number * PAGE_SIZE as u64))?;
-                        self.backend.read_exact(cache.as_mut())?;
-                        buf[read..read + size]
-                            .copy_from_slice(&cache.data[offset..offset + size]);
-                        self.cache.insert(number, cache);
-                        self.dirty.insert(number, false);
-                    }
+                    let cache = self.read_page(number)?;
+                    sink(&cache.data[offset..offset + size]);
                     read += size;
                 }
             }
@@ -241,24 +617,77 @@ impl<B: Backend> io::Read for CachedBackend<B> {
     }
 }
 
-impl<B: Backend> io::Write for CachedBackend<B> {
+impl<B: Backend, const PAGE: usize> io::Read for CachedBackend<B, PAGE> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+        self.read_with(buf.len(), |data| {
+            buf[read..read + data.len()].copy_from_slice(data);
+            read += data.len();
+        })
+    }
+
+    /// Copies the cached pages straight into the unfilled part of `cursor`,
+    /// which is never zeroed beforehand.
+    fn read_buf(&mut self, mut cursor: BorrowedCursor<'_>) -> io::Result<()> {
+        self.read_with(cursor.capacity(), |data| {
+            cursor.append(data);
+        })
+        .map(|_| ())
+    }
+}
+
+/// Writes all of `bufs` to `writer` without copying them together, retrying
+/// the partial writes.
+fn write_all_vectored(
+    writer: &mut impl io::Write,
+    mut bufs: &mut [IoSlice<'_>],
+) -> io::Result<()> {
+    IoSlice::advance_slices(&mut bufs, 0);
+    while !bufs.is_empty() {
+        match writer.write_vectored(bufs) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(written) => IoSlice::advance_slices(&mut bufs, written),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+impl<B: Backend, const PAGE: usize> io::Write for CachedBackend<B, PAGE> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Nothing is written before the failed automatic flush is reported.
+        self.flush()?;
+        if buf.is_empty() {
+            return Ok(0);
+        }
         let start = self.my_pos;
         let mut written = 0;
 
-        for page in PageRange::new(start, start + buf.len() as u64) {
+        for page in PageRange::<PAGE>::new(start, start + buf.len() as u64) {
+            let number = match page {
+                PageType::FullPage { number } | PageType::PartialPage { number, .. } => {
+                    number
+                }
+            };
+            // The page is about to be dirty, and no longer checked.
+            self.check_page(number);
+            if let Some(checksums) = &mut self.checksums {
+                checksums.remove(&number);
+            }
+
             match page {
                 PageType::FullPage { number } => {
                     if let Some(page) = self.cache.get_mut(&number) {
                         page.data
-                            .copy_from_slice(&buf[written..written + PAGE_SIZE]);
+                            .copy_from_slice(&buf[written..written + PAGE]);
                     } else {
-                        let mut cache = CachePage::new();
-                        cache.data[..].copy_from_slice(&buf[written..]);
+                        let mut cache = CachePage::new(self.page_len(number));
+                        cache.data[..].copy_from_slice(&buf[written..written + PAGE]);
                         self.cache.insert(number, cache);
                     }
                     self.dirty.insert(number, true);
-                    written += PAGE_SIZE;
+                    written += PAGE;
                 }
                 PageType::PartialPage {
                     number,
@@ -269,14 +698,9 @@ impl<B: Backend> io::Write for CachedBackend<B> {
                         page.data[offset..offset + size]
                             .copy_from_slice(&buf[written..written + size]);
                     } else {
-                        let mut cache = CachePage::new();
-                        {
-                            let origin_pos = self.backend.stream_position()?;
-                            self.backend
-                                .seek(SeekFrom::Start(number * PAGE_SIZE as u64))?;
-                            self.backend.read_exact(cache.data.as_mut())?;
-                            self.backend.seek(SeekFrom::Start(origin_pos))?;
-                        }
+                        // The page is read in once, and the following partial
+                        // writes to it hit the cache.
+                        let mut cache = self.load_page(number)?;
                         cache.data[offset..offset + size]
                             .copy_from_slice(&buf[written..written + size]);
                         self.cache.insert(number, cache);
@@ -285,25 +709,41 @@ impl<B: Backend> io::Write for CachedBackend<B> {
                     written += size;
                 }
             }
+            // The file grows only by what is copied into the cache, in case
+            // loading the next page fails.
+            self.grow(start + written as u64);
         }
 
         self.my_pos += written as u64;
+
+        self.writes += 1;
+        if self.auto_flush != 0 && self.writes >= self.auto_flush {
+            self.flush_error = self.real_flush().err();
+        }
         Ok(written)
     }
 
-    /// We don't need to flush the cache here
+    /// We don't need to flush the cache here, but only to report the failure of
+    /// the last automatic flush.
     fn flush(&mut self) -> io::Result<()> {
-        Ok(())
+        self.flush_error.take().map_or(Ok(()), Err)
     }
 }
 
-impl<B: Backend> io::Seek for CachedBackend<B> {
+impl<B: Backend, const PAGE: usize> io::Seek for CachedBackend<B, PAGE> {
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
-        self.my_pos = match pos {
-            SeekFrom::Start(pos) => pos,
-            SeekFrom::End(pos) => self.my_len.wrapping_add_signed(pos),
-            SeekFrom::Current(pos) => self.my_pos.wrapping_add_signed(pos),
+        let new_pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            // The length includes the writes past the end not flushed yet.
+            SeekFrom::End(pos) => self.my_len.checked_add_signed(pos),
+            SeekFrom::Current(pos) => self.my_pos.checked_add_signed(pos),
         };
+        self.my_pos = new_pos.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
         Ok(self.my_pos)
     }
 
@@ -312,8 +752,314 @@ impl<B: Backend> io::Seek for CachedBackend<B> {
     }
 }
 
-impl<B: Backend> Drop for CachedBackend<B> {
+impl<B: Backend, const PAGE: usize> Drop for CachedBackend<B, PAGE> {
+    /// Flushes the cache on a best-effort basis. Use `close` to handle the
+    /// errors instead.
     fn drop(&mut self) {
-        self.real_flush().unwrap();
+        if self.closed {
+            return;
+        }
+        if let Err(err) = self.real_flush() {
+            eprintln!("failed to flush the cache on drop: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    use super::{crc32, CachedBackend, PageRange, PageType};
+    use crate::backend::{Backend, MemoryBackend, RWCount};
+
+    fn pages<const PAGE: usize>(range: PageRange<PAGE>) -> Vec<(u64, usize, usize)> {
+        range
+            .map(|page| match page {
+                PageType::FullPage { number } => (number, 0, PAGE),
+                PageType::PartialPage {
+                    number,
+                    offset,
+                    size,
+                } => (number, offset, size),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn page_range() {
+        type Range = PageRange<16>;
+
+        assert_eq!(pages(Range::new(3, 10)), [(0, 3, 7)]);
+        assert_eq!(pages(Range::new(16, 32)), [(1, 0, 16)]);
+        assert_eq!(
+            pages(Range::new(20, 70)),
+            [(1, 4, 12), (2, 0, 16), (3, 0, 16), (4, 0, 6)]
+        );
+
+        assert!(pages(Range::new(10, 10)).is_empty());
+        assert!(pages(Range::new(10, 3)).is_empty());
+        assert!(Range::checked_new(10, 3).is_none());
+        #[allow(clippy::reversed_empty_ranges)]
+        let reversed = 10..3;
+        assert!(Range::try_from(reversed).is_err());
+        assert!(Range::with_len(u64::MAX - 1, 2).is_none());
+
+        let tail = pages(Range::new(u64::MAX - 20, u64::MAX));
+        assert_eq!(tail.iter().map(|p| p.2).sum::<usize>(), 20);
+    }
+
+    #[test]
+    fn read_ahead() {
+        let image = MemoryBackend::new(4096 * 10 + 100);
+        let mut cached = CachedBackend::<_, 4096>::new(RWCount::new(image)).read_ahead(4);
+        cached.seek(SeekFrom::Start(4096 * 2)).unwrap();
+        cached.write_all(b"dirty").unwrap();
+        cached.backend.read_count = 0;
+
+        let mut buf = vec![0; 4096];
+        cached.seek(SeekFrom::Start(0)).unwrap();
+        for _ in 0..10 {
+            cached.read_exact(&mut buf).unwrap();
+        }
+        assert_eq!(cached.read(&mut buf).unwrap(), 100);
+
+        // Page 0 and 1 are missed separately, page 2 is cached and dirty, and
+        // the rest are read ahead by page 3 and 8.
+        assert_eq!(cached.backend.read_count, 4);
+        assert!(cached.dirty[&2]);
+        assert_eq!(cached.cache[&10].len, 100);
+
+        cached.seek(SeekFrom::Start(4096 * 2)).unwrap();
+        cached.read_exact(&mut buf[..5]).unwrap();
+        assert_eq!(&buf[..5], b"dirty");
+    }
+
+    #[test]
+    fn verify_against_backend() {
+        let image = MemoryBackend::new(4096 * 4);
+        let mut cached = CachedBackend::<_, 4096>::new(RWCount::new(image));
+        cached.seek(SeekFrom::Start(4000)).unwrap();
+        cached.write_all(&[1; 5000]).unwrap();
+        cached.real_flush().unwrap();
+
+        let mut buf = vec![0; 4096 * 4];
+        cached.seek(SeekFrom::Start(0)).unwrap();
+        cached.read_exact(&mut buf).unwrap();
+        assert!(cached.verify_against_backend().unwrap());
+
+        // Corrupt the backend behind the cache.
+        cached.backend.seek(SeekFrom::Start(4096 * 3)).unwrap();
+        cached.backend.write_all(b"corrupted").unwrap();
+        assert!(!cached.verify_against_backend().unwrap());
+    }
+
+    #[test]
+    fn coalesced_seeks() {
+        let image = MemoryBackend::new(4096 * 10);
+        let mut cached = CachedBackend::<_, 4096>::new(RWCount::new(image));
+        cached.backend.seek_count = 0;
+
+        let mut buf = vec![0; 4096];
+        for _ in 0..4 {
+            cached.read_exact(&mut buf).unwrap();
+        }
+        // Only the first miss seeks, and the others continue where it stops.
+        assert_eq!(cached.backend.seek_count, 1);
+
+        cached.seek(SeekFrom::Start(4096 * 8)).unwrap();
+        cached.write_all(&[1; 4096 * 2]).unwrap();
+        cached.real_flush().unwrap();
+        // Page 8 and 9 are written with one seek, and no seek back.
+        assert_eq!(cached.backend.seek_count, 2);
+        assert!(cached.verify_against_backend().unwrap());
+    }
+
+    #[test]
+    fn tracked_backend_pos() {
+        let mut image = MemoryBackend::new(4096 * 4);
+        let pattern: Vec<u8> = (0..4096 * 4).map(|i| i as u8).collect();
+        image.write_all(&pattern).unwrap();
+        let mut cached = CachedBackend::<_, 4096>::new(RWCount::new(image));
+
+        // A partial read of page 1 leaves the backend right after the page.
+        let mut buf = [0; 10];
+        cached.seek(SeekFrom::Start(4096 + 100)).unwrap();
+        cached.read_exact(&mut buf).unwrap();
+        assert_eq!(cached.backend.stream_position().unwrap(), 4096 * 2);
+        assert_eq!(cached.backend_pos, Some(4096 * 2));
+
+        // Moving the backend directly is noticed by the next miss.
+        cached.backend_mut().seek(SeekFrom::Start(10)).unwrap();
+        cached.read_exact(&mut buf).unwrap();
+        cached.seek(SeekFrom::Start(4096 * 3 - 5)).unwrap();
+        cached.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [251, 252, 253, 254, 255, 0, 1, 2, 3, 4]);
+        assert_eq!(cached.backend.stream_position().unwrap(), 4096 * 4);
+    }
+
+    #[test]
+    fn punched_flush() {
+        let image = MemoryBackend::new(4096 * 10);
+        let mut cached = CachedBackend::<_, 4096>::new(RWCount::new(image));
+        cached.write_all(&[1; 4096 * 10]).unwrap();
+        cached.real_flush().unwrap();
+
+        cached.seek(SeekFrom::Start(4096 * 2)).unwrap();
+        cached.write_all(&[0; 4096 * 3]).unwrap();
+        cached.write_all(&[2; 10]).unwrap();
+        cached.backend.write_count = 0;
+        cached.real_flush().unwrap();
+        // Page 2 to 4 are punched, and only page 5 is written.
+        assert_eq!(cached.backend.write_count, 1);
+        assert!(cached.verify_against_backend().unwrap());
+    }
+
+    #[test]
+    fn batched_flush() {
+        let image = MemoryBackend::new(4096 * 10);
+        let mut cached = CachedBackend::<_, 4096>::new(RWCount::new(image));
+        cached.seek(SeekFrom::Start(100)).unwrap();
+        cached.write_all(&[1; 4096 * 4]).unwrap();
+        cached.seek(SeekFrom::Start(4096 * 8)).unwrap();
+        cached.write_all(&[2; 10]).unwrap();
+
+        cached.real_flush().unwrap();
+        // Page 0 to 4 are written at once, and page 8 alone.
+        assert_eq!(cached.backend.write_count, 2);
+        assert!(cached.dirty.values().all(|dirty| !dirty));
+        assert!(cached.verify_against_backend().unwrap());
+    }
+
+    #[test]
+    fn combined_partial_writes() {
+        let image = MemoryBackend::new(4096 * 4);
+        let mut cached = CachedBackend::<_, 4096>::new(RWCount::new(image));
+        cached.backend.read_count = 0;
+
+        cached.seek(SeekFrom::Start(4096 + 10)).unwrap();
+        for i in 0..100u8 {
+            cached.write_all(&[i; 10]).unwrap();
+        }
+        // Only the first write reads page 1 in, and the rest hit the cache.
+        assert_eq!(cached.backend.read_count, 1);
+
+        cached.backend.write_count = 0;
+        cached.real_flush().unwrap();
+        assert_eq!(cached.backend.write_count, 1);
+        assert!(cached.verify_against_backend().unwrap());
+    }
+
+    #[test]
+    fn closed_when_full() {
+        let image = MemoryBackend::new(4096).with_limit(4096 * 2);
+        let mut cached = CachedBackend::<_, 4096>::new(image);
+        cached.write_all(b"within the limit").unwrap();
+        cached.real_flush().unwrap();
+
+        // The write past the limit fails only when it's flushed.
+        cached.seek(SeekFrom::Start(4096 * 2)).unwrap();
+        cached.write_all(b"past the limit").unwrap();
+        let err = cached.close().unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOSPC));
+
+        // Dropping without closing only logs the error.
+        let image = MemoryBackend::new(4096).with_limit(4096);
+        let mut cached = CachedBackend::<_, 4096>::new(image);
+        cached.seek(SeekFrom::Start(4096)).unwrap();
+        cached.write_all(b"past the limit").unwrap();
+        drop(cached);
+    }
+
+    #[test]
+    fn auto_flush_failed() {
+        let image = MemoryBackend::new(4096).with_limit(4096);
+        let mut cached = CachedBackend::<_, 4096>::new(image).auto_flush_every(1);
+        cached.seek(SeekFrom::Start(4096)).unwrap();
+        // The bytes are accepted in spite of the failed flush, which is reported
+        // by the next write instead.
+        assert_eq!(cached.write(b"past the limit").unwrap(), 14);
+        let err = cached.write(b"again").unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOSPC));
+        assert_eq!(cached.stream_position().unwrap(), 4096 + 14);
+        cached.flush().unwrap();
+    }
+
+    #[test]
+    fn integrity_checks() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+
+        let image = MemoryBackend::new(4096 * 2);
+        let mut cached = CachedBackend::<_, 4096>::new(image).with_integrity_checks(true);
+        let mut buf = [0; 16];
+        cached.read_exact(&mut buf).unwrap();
+        assert!(cached.check_page(0));
+
+        // A rogue write to a clean page, behind the cache.
+        cached.cache.get_mut(&0).unwrap().data[42] = 1;
+        assert!(!cached.check_page(0));
+
+        // Dirty pages are not checked until they're flushed.
+        cached.seek(SeekFrom::Start(4096)).unwrap();
+        cached.write_all(b"dirty").unwrap();
+        cached.cache.get_mut(&1).unwrap().data[42] = 1;
+        assert!(cached.check_page(1));
+        cached.real_flush().unwrap();
+        assert!(cached.check_page(1));
+        cached.cache.get_mut(&1).unwrap().data[42] = 2;
+        assert!(!cached.check_page(1));
+    }
+
+    #[test]
+    fn extended_by_write() {
+        let image = MemoryBackend::new(4096 * 2 + 100);
+        let mut cached = CachedBackend::<_, 4096>::new(RWCount::new(image));
+        let mut buf = vec![0; 4096];
+        cached.read_exact(&mut buf).unwrap();
+        cached.read_exact(&mut buf).unwrap();
+
+        // Page 2 is cached before the write grows it.
+        assert_eq!(cached.read(&mut buf).unwrap(), 100);
+        assert_eq!(cached.seek(SeekFrom::End(4096)).unwrap(), 4096 * 3 + 100);
+        cached.write_all(b"tail").unwrap();
+        assert_eq!(cached.seek(SeekFrom::End(0)).unwrap(), 4096 * 3 + 104);
+        assert!(cached.seek(SeekFrom::End(-(4096 * 4))).is_err());
+
+        cached.seek(SeekFrom::End(-4)).unwrap();
+        cached.read_exact(&mut buf[..4]).unwrap();
+        assert_eq!(&buf[..4], b"tail");
+        cached.seek(SeekFrom::Start(4096 * 2 + 100)).unwrap();
+        cached.read_exact(&mut buf).unwrap();
+        assert!(buf.iter().all(|b| *b == 0));
+
+        // Only page 3 is dirty, and it extends the backend though the gap
+        // before it is never written.
+        cached.real_flush().unwrap();
+        assert_eq!(
+            cached.backend.seek(SeekFrom::End(0)).unwrap(),
+            4096 * 3 + 104
+        );
+        assert!(cached.verify_against_backend().unwrap());
+
+        // An all-zero tail past the end is written rather than punched.
+        cached.seek(SeekFrom::End(0)).unwrap();
+        cached.write_all(&[0; 10]).unwrap();
+        cached.real_flush().unwrap();
+        assert_eq!(
+            cached.backend.seek(SeekFrom::End(0)).unwrap(),
+            4096 * 3 + 114
+        );
+    }
+
+    #[test]
+    fn empty_write_past_end() {
+        let image = MemoryBackend::new(4096 + 100);
+        let mut cached = CachedBackend::<_, 4096>::new(image);
+        cached.seek(SeekFrom::End(4096)).unwrap();
+        assert_eq!(cached.write(&[]).unwrap(), 0);
+
+        // Like a file, the seek past the end alone doesn't extend it.
+        assert_eq!(cached.seek(SeekFrom::End(0)).unwrap(), 4096 + 100);
+        cached.real_flush().unwrap();
+        assert_eq!(cached.backend.seek(SeekFrom::End(0)).unwrap(), 4096 + 100);
     }
 }
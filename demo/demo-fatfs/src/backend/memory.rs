@@ -0,0 +1,377 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, IoSlice, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use super::Backend;
+
+type Image = Arc<Mutex<Vec<u8>>>;
+
+/// All the in-memory images, keyed by their paths.
+static REGISTRY: Mutex<BTreeMap<PathBuf, Image>> = Mutex::new(BTreeMap::new());
+
+fn registry() -> MutexGuard<'static, BTreeMap<PathBuf, Image>> {
+    REGISTRY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A backend storing the image in a growable buffer in memory, useful for
+/// tests that should not touch the disk.
+///
+/// Images are shared by path: opening the same path twice gives two handles
+/// (with their own positions) to the same buffer.
+#[allow(dead_code)]
+pub struct MemoryBackend {
+    image: Image,
+    pos: u64,
+    limit: Option<u64>, // the length writes fail past, like a full disk
+}
+
+#[allow(dead_code)]
+impl MemoryBackend {
+    /// Create an anonymous image which is not registered to any path.
+    pub fn new(size: u64) -> Self {
+        Self::from_image(Arc::new(Mutex::new(vec![0; size as usize])))
+    }
+
+    fn from_image(image: Image) -> Self {
+        Self {
+            image,
+            pos: 0,
+            limit: None,
+        }
+    }
+
+    /// Fails the writes through this handle past `len` bytes with `ENOSPC`,
+    /// simulating a full disk.
+    pub fn with_limit(mut self, len: u64) -> Self {
+        self.limit = Some(len);
+        self
+    }
+
+    fn data(&self) -> MutexGuard<'_, Vec<u8>> {
+        self.image.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The current length of the image.
+    pub fn len(&self) -> u64 {
+        self.data().len() as u64
+    }
+
+    /// Truncate or extend the image with zeros, just like
+    /// [`std::fs::File::set_len`]. The position is left unchanged.
+    pub fn set_len(&self, size: u64) -> io::Result<()> {
+        let size = usize::try_from(size)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "size too large"))?;
+        self.data().resize(size, 0);
+        Ok(())
+    }
+
+    /// Remove the image at `path` from the registry. Existing handles keep
+    /// their data alive.
+    pub fn remove<P: AsRef<Path>>(path: P) -> bool {
+        registry().remove(path.as_ref()).is_some()
+    }
+
+    /// Take a snapshot of the whole image.
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.data().clone()
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn open<P: AsRef<Path> + Send>(path: P) -> io::Result<Self> {
+        registry()
+            .get(path.as_ref())
+            .cloned()
+            .map(Self::from_image)
+            .ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+
+    fn create<P: AsRef<Path> + Send, F: FnOnce(&mut Self) -> io::Result<()>>(
+        path: P,
+        size: u64,
+        init: F,
+    ) -> io::Result<Self> {
+        let image = registry()
+            .entry(path.as_ref().to_path_buf())
+            .or_default()
+            .clone();
+        let mut backend = Self::from_image(image);
+        backend.data().clear();
+        backend.set_len(size)?;
+        init(&mut backend).map(|_| backend)
+    }
+
+    fn create_new<P: AsRef<Path> + Send, F: FnOnce(&mut Self) -> io::Result<()>>(
+        path: P,
+        size: u64,
+        init: F,
+    ) -> io::Result<Self> {
+        let image = {
+            let mut registry = registry();
+            if registry.contains_key(path.as_ref()) {
+                return Err(io::ErrorKind::AlreadyExists.into());
+            }
+            let image = Image::default();
+            registry.insert(path.as_ref().to_path_buf(), image.clone());
+            image
+        };
+        let mut backend = Self::from_image(image);
+        backend.set_len(size)?;
+        init(&mut backend).map(|_| backend)
+    }
+
+    /// Zero the range, clamped to the image like `fallocate` with
+    /// `FALLOC_FL_KEEP_SIZE`.
+    fn punch_hole(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        let mut data = self.data();
+        let start = usize::try_from(offset).map_or(data.len(), |o| o.min(data.len()));
+        let end = usize::try_from(offset.saturating_add(len))
+            .map_or(data.len(), |e| e.min(data.len()));
+        data[start..end].fill(0);
+        Ok(())
+    }
+}
+
+impl io::Read for MemoryBackend {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.data();
+        let start = usize::try_from(self.pos).map_or(data.len(), |p| p.min(data.len()));
+        let size = buf.len().min(data.len() - start);
+        buf[..size].copy_from_slice(&data[start..start + size]);
+        drop(data);
+
+        self.pos += size as u64;
+        Ok(size)
+    }
+}
+
+impl io::Write for MemoryBackend {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let start = usize::try_from(self.pos).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, "position too large")
+        })?;
+        let end = start.checked_add(buf.len()).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "write past the largest position")
+        })?;
+        if self.limit.is_some_and(|limit| end as u64 > limit) {
+            return Err(io::Error::from_raw_os_error(libc::ENOSPC));
+        }
+
+        let mut data = self.data();
+        // Writing past the end fills the gap with zeros, just like a file.
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[start..end].copy_from_slice(buf);
+        drop(data);
+
+        self.pos = end as u64;
+        Ok(buf.len())
+    }
+
+    /// Writes all the slices in one call, like `writev` on a file.
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let mut written = 0;
+        for buf in bufs {
+            match self.write(buf) {
+                Ok(size) => written += size,
+                Err(err) if written == 0 => return Err(err),
+                Err(_) => break,
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl io::Seek for MemoryBackend {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(pos) => self.len().checked_add_signed(pos),
+            SeekFrom::Current(pos) => self.pos.checked_add_signed(pos),
+        };
+        self.pos = new_pos.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.pos)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BorrowedBuf, Read, Seek, SeekFrom, Write},
+        mem::MaybeUninit,
+    };
+
+    use super::MemoryBackend;
+    use crate::backend::{Backend, CachedBackend, RWCount};
+
+    #[test]
+    fn seek_and_extend() {
+        let mut backend = MemoryBackend::new(4);
+        assert_eq!(backend.seek(SeekFrom::End(2)).unwrap(), 6);
+        backend.write_all(&[1, 2]).unwrap();
+        assert_eq!(backend.to_vec(), [0, 0, 0, 0, 0, 0, 1, 2]);
+
+        assert!(backend.seek(SeekFrom::Current(-9)).is_err());
+        assert_eq!(backend.stream_position().unwrap(), 8);
+
+        let mut buf = [0; 4];
+        assert_eq!(backend.read(&mut buf).unwrap(), 0);
+
+        backend.set_len(2).unwrap();
+        assert_eq!(backend.len(), 2);
+
+        backend.seek(SeekFrom::Start(u64::MAX)).unwrap();
+        let err = backend.write(&[1]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(backend.len(), 2);
+    }
+
+    #[test]
+    fn registry() {
+        let path = "memory-registry.img";
+        let mut created =
+            MemoryBackend::create(path, 16, |b| b.write_all(b"unico")).unwrap();
+        assert!(MemoryBackend::create_new(path, 16, |_| Ok(())).is_err());
+
+        let mut opened = MemoryBackend::open(path).unwrap();
+        let mut buf = [0; 5];
+        opened.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"unico");
+
+        created.write_all(b"!").unwrap();
+        opened.read_exact(&mut buf[..1]).unwrap();
+        assert_eq!(buf[0], b'!');
+
+        assert!(MemoryBackend::remove(path));
+        assert!(MemoryBackend::open(path).is_err());
+    }
+
+    #[test]
+    fn cached() {
+        let path = "memory-cached.img";
+        let mut cached =
+            CachedBackend::<MemoryBackend>::create(path, 1 << 21, |_| Ok(())).unwrap();
+        cached.seek(SeekFrom::Start(100)).unwrap();
+        cached.write_all(b"cached").unwrap();
+
+        // Nothing is written back before the real flush.
+        let mut inner = MemoryBackend::open(path).unwrap();
+        assert_eq!(&inner.to_vec()[100..106], &[0; 6]);
+
+        cached.real_flush().unwrap();
+        let mut buf = [0; 6];
+        inner.seek(SeekFrom::Start(100)).unwrap();
+        inner.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"cached");
+
+        MemoryBackend::remove(path);
+    }
+
+    #[test]
+    fn cached_tail_page() {
+        let path = "memory-cached-tail.img";
+        let mut cached =
+            CachedBackend::<MemoryBackend, 4096>::create(path, 5000, |_| Ok(())).unwrap();
+        cached.seek(SeekFrom::Start(4500)).unwrap();
+        cached.write_all(b"tail").unwrap();
+        cached.seek(SeekFrom::Start(4000)).unwrap();
+        cached.write_all(b"head").unwrap();
+        cached.real_flush().unwrap();
+
+        let inner = MemoryBackend::open(path).unwrap();
+        let image = inner.to_vec();
+        assert_eq!(image.len(), 5000);
+        assert_eq!(&image[4000..4004], b"head");
+        assert_eq!(&image[4500..4504], b"tail");
+
+        MemoryBackend::remove(path);
+    }
+
+    #[test]
+    fn cached_auto_flush() {
+        let path = "memory-cached-auto.img";
+        let mut cached =
+            CachedBackend::<MemoryBackend, 4096>::create(path, 8192, |_| Ok(()))
+                .unwrap()
+                .auto_flush_every(2);
+        let inner = MemoryBackend::open(path).unwrap();
+
+        cached.write_all(b"ab").unwrap();
+        assert_eq!(&inner.to_vec()[..2], &[0, 0]);
+        // Empty writes don't count towards the automatic flush.
+        assert_eq!(cached.write(&[]).unwrap(), 0);
+        assert_eq!(&inner.to_vec()[..2], &[0, 0]);
+        cached.write_all(b"cd").unwrap();
+        assert_eq!(&inner.to_vec()[..4], b"abcd");
+        assert_eq!(cached.stream_position().unwrap(), 4);
+
+        MemoryBackend::remove(path);
+    }
+
+    #[test]
+    fn cached_small_pages() {
+        let mut cached = CachedBackend::<_, 4096>::new(MemoryBackend::new(16384));
+        let data: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+        cached.seek(SeekFrom::Start(3000)).unwrap();
+        cached.write_all(&data).unwrap();
+
+        let mut buf = vec![0; data.len()];
+        cached.seek(SeekFrom::Start(3000)).unwrap();
+        cached.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data);
+    }
+
+    #[test]
+    fn cached_read_buf() {
+        let mut cached =
+            RWCount::new(CachedBackend::<_, 4096>::new(MemoryBackend::new(16384)));
+        let data: Vec<u8> = (0..6000).map(|i| i as u8).collect();
+        cached.seek(SeekFrom::Start(3000)).unwrap();
+        cached.write_all(&data).unwrap();
+
+        let mut memory = [MaybeUninit::uninit(); 6000];
+        let mut buf = BorrowedBuf::from(&mut memory[..]);
+        cached.seek(SeekFrom::Start(3000)).unwrap();
+        cached.read_buf(buf.unfilled()).unwrap();
+        assert_eq!(buf.filled(), data);
+        assert_eq!(cached.read_count, 1);
+
+        // Reads stop at the end of the file.
+        buf.clear();
+        cached.seek(SeekFrom::Start(16000)).unwrap();
+        cached.read_buf(buf.unfilled()).unwrap();
+        assert_eq!(buf.len(), 384);
+    }
+
+    #[test]
+    fn cached_multi_page_write() {
+        let path = "memory-cached-multi.img";
+        let mut cached =
+            CachedBackend::<MemoryBackend, 4096>::create(path, 16384, |_| Ok(())).unwrap();
+        let data: Vec<u8> = (0..4096 * 3).map(|i| (i / 4096) as u8 + 1).collect();
+        cached.write_all(&data).unwrap();
+        cached.real_flush().unwrap();
+
+        let inner = MemoryBackend::open(path).unwrap();
+        assert_eq!(&inner.to_vec()[..data.len()], data);
+
+        MemoryBackend::remove(path);
+    }
+}
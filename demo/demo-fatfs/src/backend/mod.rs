@@ -1,17 +1,23 @@
 use std::{
-    io::{self, IoSlice, SeekFrom},
+    future::poll_fn,
+    io::{self, BorrowedCursor, IoSlice, SeekFrom},
     path::Path,
+    pin::Pin,
 };
 
 use tokio::{
     fs::File,
-    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, ReadBuf},
 };
 use unico::asym::AsymWait;
 
 #[allow(dead_code)]
 /// The backend which takes care of the actual file operations for the FAT
 /// filesystem images.
+///
+/// Reads behave like [`std::fs::File`]: a read returns the bytes available
+/// before the end of the image, possibly fewer than requested, and `0` at or
+/// past the end.
 pub trait Backend: Sized + io::Read + io::Write + io::Seek {
     /// Open an existing image file. The file must already exist.
     fn open<P: AsRef<Path> + Send>(path: P) -> io::Result<Self>;
@@ -45,6 +51,29 @@ pub trait Backend: Sized + io::Read + io::Write + io::Seek {
     fn real_flush(&mut self) -> io::Result<()> {
         self.flush()
     }
+
+    /// Deallocate `len` bytes at `offset`, which then read back as zeros,
+    /// keeping the image sparse. Neither the length nor the position changes.
+    /// Returns an error of [`io::ErrorKind::Unsupported`] if the backend cannot
+    /// do this.
+    fn punch_hole(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        let _ = (offset, len);
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// Punch a hole in the file `fd` with `fallocate`.
+#[cfg(target_os = "linux")]
+fn punch_hole(fd: std::os::fd::RawFd, offset: u64, len: u64) -> io::Result<()> {
+    let (Ok(offset), Ok(len)) = (offset.try_into(), len.try_into()) else {
+        return Err(io::ErrorKind::InvalidInput.into());
+    };
+    let mode = libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE;
+    // SAFETY: `fallocate` only operates on the file descriptor.
+    match unsafe { libc::fallocate(fd, mode, offset, len) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
 }
 
 /// A backend using [`tokio::fs::File`] and unico, must be used in
@@ -90,6 +119,22 @@ impl Backend for UnicoBackend {
             .and_then(|file| file.set_len(size).wait().map(|_| Self { file }))
             .and_then(|mut backend| init(&mut backend).map(|_| backend))
     }
+
+    /// Flushes the buffered data and then `fsync`s the file, so that the data
+    /// is durable on the disk instead of staying in the OS page cache.
+    fn real_flush(&mut self) -> io::Result<()> {
+        self.file.flush().wait()?;
+        self.file.sync_all().wait()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn punch_hole(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        use std::os::fd::AsRawFd;
+
+        // The buffered writes must land before the hole is punched.
+        self.file.flush().wait()?;
+        punch_hole(self.file.as_raw_fd(), offset, len)
+    }
 }
 
 impl io::Read for UnicoBackend {
@@ -108,6 +153,19 @@ impl io::Read for UnicoBackend {
     fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
         self.file.read_exact(buf).wait().map(|_| ())
     }
+
+    /// Reads into the unfilled part of `cursor` through a [`ReadBuf`], so the
+    /// memory is never zeroed beforehand.
+    fn read_buf(&mut self, mut cursor: BorrowedCursor<'_>) -> io::Result<()> {
+        // SAFETY: `ReadBuf` never de-initializes the memory.
+        let mut buf = ReadBuf::uninit(unsafe { cursor.as_mut() });
+        poll_fn(|cx| Pin::new(&mut self.file).poll_read(cx, &mut buf)).wait()?;
+        let read = buf.filled().len();
+        // SAFETY: The first `read` bytes are filled by the file above.
+        unsafe { cursor.set_init(read) };
+        cursor.advance(read);
+        Ok(())
+    }
 }
 
 impl io::Write for UnicoBackend {
@@ -178,10 +236,128 @@ impl Backend for SyncBackend {
             .and_then(|file| file.set_len(size).map(|_| file))
             .and_then(|mut file| init(&mut file).map(|_| file))
     }
+
+    #[cfg(target_os = "linux")]
+    fn punch_hole(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        use std::os::fd::AsRawFd;
+
+        punch_hole(self.as_raw_fd(), offset, len)
+    }
 }
 
 mod cached;
 pub use cached::*;
 
+mod memory;
+#[allow(unused_imports)]
+pub use memory::MemoryBackend;
+
 mod rw_count;
 pub use rw_count::RWCount;
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::IntoFuture,
+        io::{self, SeekFrom},
+        path::PathBuf,
+    };
+
+    use unico::asym::sync;
+
+    use super::{Backend, CachedBackend, MemoryBackend, SyncBackend, UnicoBackend};
+
+    const LEN: u64 = 10_000;
+
+    /// Reads around and past the end of a fresh image, recording every result.
+    fn reads_past_end<B: Backend>(path: PathBuf) -> io::Result<Vec<usize>> {
+        let mut backend = B::create(&path, LEN, |_| Ok(()))?;
+        let mut buf = [0; 64];
+        let mut results = Vec::new();
+        for pos in [LEN - 10, LEN, LEN + 4096] {
+            backend.seek(SeekFrom::Start(pos))?;
+            results.push(backend.read(&mut buf)?);
+            results.push(backend.read(&mut buf)?);
+        }
+        backend.seek(SeekFrom::Start(0))?;
+        results.push(backend.read_to_end(&mut Vec::new())?);
+        drop(backend);
+
+        let _ = std::fs::remove_file(&path);
+        MemoryBackend::remove(&path);
+        Ok(results)
+    }
+
+    /// Seeks relative to the end of a fresh image after a write extends it,
+    /// recording every position.
+    fn seeks_grown<B: Backend>(path: PathBuf) -> io::Result<Vec<u64>> {
+        let mut backend = B::create(&path, LEN, |_| Ok(()))?;
+        let mut positions = vec![backend.seek(SeekFrom::End(100))?];
+        backend.write_all(b"tail")?;
+        positions.push(backend.seek(SeekFrom::End(0))?);
+        positions.push(backend.seek(SeekFrom::End(-4))?);
+
+        let mut buf = [0; 4];
+        backend.read_exact(&mut buf)?;
+        assert_eq!(&buf, b"tail");
+        drop(backend);
+
+        let mut reopened = B::open(&path)?;
+        positions.push(reopened.seek(SeekFrom::End(0))?);
+        drop(reopened);
+
+        let _ = std::fs::remove_file(&path);
+        MemoryBackend::remove(&path);
+        Ok(positions)
+    }
+
+    #[test]
+    fn reads_past_end_conform() {
+        let dir = std::env::temp_dir();
+        let file =
+            |name: &str| dir.join(format!("demo-fatfs-{}-{name}", std::process::id()));
+
+        let expected = [10, 0, 0, 0, 0, 0, LEN as usize];
+        let results = [
+            reads_past_end::<MemoryBackend>(file("memory")).unwrap(),
+            reads_past_end::<CachedBackend<MemoryBackend, 4096>>(file("cached-memory"))
+                .unwrap(),
+            reads_past_end::<SyncBackend>(file("sync")).unwrap(),
+            reads_past_end::<CachedBackend<SyncBackend>>(file("cached-sync")).unwrap(),
+            tokio::runtime::Runtime::new()
+                .unwrap()
+                .block_on(
+                    sync(|| reads_past_end::<UnicoBackend>(file("unico"))).into_future(),
+                )
+                .unwrap(),
+        ];
+        for result in results {
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn seeks_grown_conform() {
+        let dir = std::env::temp_dir();
+        let file =
+            |name: &str| dir.join(format!("demo-fatfs-{}-g-{name}", std::process::id()));
+
+        let expected = [LEN + 100, LEN + 104, LEN + 100, LEN + 104];
+        let results = [
+            seeks_grown::<MemoryBackend>(file("memory")).unwrap(),
+            seeks_grown::<CachedBackend<MemoryBackend, 4096>>(file("cached-memory"))
+                .unwrap(),
+            seeks_grown::<SyncBackend>(file("sync")).unwrap(),
+            seeks_grown::<CachedBackend<SyncBackend>>(file("cached-sync")).unwrap(),
+            tokio::runtime::Runtime::new()
+                .unwrap()
+                .block_on(
+                    sync(|| seeks_grown::<UnicoBackend>(file("unico"))).into_future(),
+                )
+                .unwrap(),
+        ];
+        for result in results {
+            assert_eq!(result, expected);
+        }
+    }
+}
@@ -70,6 +70,10 @@ impl<B: Backend> Backend for RWCount<B> {
     fn real_flush(&mut self) -> std::io::Result<()> {
         self.backend.real_flush()
     }
+
+    fn punch_hole(&mut self, offset: u64, len: u64) -> std::io::Result<()> {
+        self.backend.punch_hole(offset, len)
+    }
 }
 
 impl<B: Backend> std::io::Read for RWCount<B> {
@@ -77,6 +81,11 @@ impl<B: Backend> std::io::Read for RWCount<B> {
         self.read_count += 1;
         self.backend.read(buf)
     }
+
+    fn read_buf(&mut self, cursor: std::io::BorrowedCursor<'_>) -> std::io::Result<()> {
+        self.read_count += 1;
+        self.backend.read_buf(cursor)
+    }
 }
 
 impl<B: Backend> std::io::Write for RWCount<B> {
@@ -85,6 +94,11 @@ impl<B: Backend> std::io::Write for RWCount<B> {
         self.backend.write(buf)
     }
 
+    fn write_vectored(&mut self, bufs: &[std::io::IoSlice<'_>]) -> std::io::Result<usize> {
+        self.write_count += 1;
+        self.backend.write_vectored(bufs)
+    }
+
     fn flush(&mut self) -> std::io::Result<()> {
         self.backend.flush()
     }
@@ -1,6 +1,8 @@
 #![feature(allocator_api)]
+#![feature(core_io_borrowed_buf)]
 #![feature(future_join)]
 #![feature(new_uninit)]
+#![feature(read_buf)]
 
 mod backend;
 
@@ -12,11 +14,10 @@ use std::{
 
 use backend::{Backend, CachedBackend, RWCount, SyncBackend, UnicoBackend};
 use fatfs::{FatType, FsOptions};
-use futures::{stream::FuturesUnordered, StreamExt};
 use rand::{RngCore, SeedableRng};
 use sha2::Digest;
 use unico::{
-    asym::sync,
+    asym::{join_all, sync},
     context::{boost::Boost, global_resumer},
     stack::global_stack_allocator,
 };
@@ -144,25 +145,22 @@ fn async_main<B: Backend>() {
         .unwrap();
 
     runtime.block_on(async move {
-        let tasks: Vec<_> = (0..JOBS)
-            .map(|i| {
-                tokio::spawn(async move {
-                    sync(|| {
-                        do_job::<B>(
-                            format!("{}.img", i),
-                            fs_size(i, JOBS),
-                            file_size(i, JOBS),
-                            i as u64,
-                        )
-                        .unwrap()
-                    })
-                    .await;
-                })
+        let jobs = (0..JOBS).map(|i| {
+            sync(move || {
+                do_job::<B>(
+                    format!("{}.img", i),
+                    fs_size(i, JOBS),
+                    file_size(i, JOBS),
+                    i as u64,
+                )
+                .unwrap()
             })
-            .collect();
+        });
 
-        for task in tasks {
-            task.await.unwrap();
+        for result in join_all(jobs).await {
+            if let Err(payload) = result {
+                std::panic::resume_unwind(payload);
+            }
         }
     });
 }
@@ -2,9 +2,8 @@
 
 use std::{alloc::Global, hint::black_box, iter, time::Instant};
 
-use spin_on::spin_on;
 use time::{ext::InstantExt, Duration};
-use unico::asym::sync;
+use unico::{asym::sync, runtime::LocalRuntime};
 use unico_context::{boost::Boost, global_resumer};
 use unico_stack::global_stack_allocator;
 
@@ -13,9 +12,10 @@ global_stack_allocator!(Global);
 
 #[inline(never)]
 fn test(times: u32) -> Duration {
+    let runtime = LocalRuntime::new();
     let start = Instant::now();
     for _ in 0..times {
-        spin_on(black_box(async {
+        runtime.block_on(black_box(async {
             sync(|| {}).await;
         }));
     }
@@ -23,7 +23,7 @@ fn test(times: u32) -> Duration {
 
     let start = Instant::now();
     for _ in 0..times {
-        spin_on(black_box(async {}));
+        runtime.block_on(black_box(async {}));
     }
     let direct = Instant::now().signed_duration_since(start) / times;
 
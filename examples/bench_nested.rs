@@ -3,9 +3,11 @@
 use std::{alloc::Global, hint::black_box, io::Read, iter, time::Instant};
 
 use futures_lite::{AsyncRead, AsyncReadExt};
-use spin_on::spin_on;
 use time::{ext::InstantExt, Duration};
-use unico::asym::{sync, AsymWait};
+use unico::{
+    asym::{sync, AsymWait},
+    runtime::LocalRuntime,
+};
 use unico_context::{boost::Boost, global_resumer};
 use unico_stack::global_stack_allocator;
 
@@ -57,9 +59,10 @@ impl std::iter::Sum for TestResult {
 #[inline(never)]
 fn test(times: u32) -> TestResult {
     const SIZE: usize = 600;
+    let runtime = LocalRuntime::new();
 
     let start = Instant::now();
-    spin_on(black_box(async {
+    runtime.block_on(black_box(async {
         for _ in 0..times {
             let r: &[u8] = &[0x12; SIZE];
             let mut buf = [0u8; SIZE];
@@ -69,7 +72,7 @@ fn test(times: u32) -> TestResult {
     let synced = Instant::now().signed_duration_since(start) / times;
 
     let start = Instant::now();
-    spin_on(black_box(async {
+    runtime.block_on(black_box(async {
         for _ in 0..times {
             let r: &[u8] = &[0x12; SIZE];
             let mut buf = [0u8; SIZE];
@@ -0,0 +1,69 @@
+#![feature(allocator_api)]
+
+use std::{alloc::Global, hint::black_box, iter, time::Instant};
+
+use time::{ext::InstantExt, Duration};
+use unico::{
+    asym::{sync, CoPool},
+    runtime::LocalRuntime,
+};
+use unico_context::{boost::Boost, global_resumer};
+use unico_stack::global_stack_allocator;
+
+global_resumer!(Boost);
+global_stack_allocator!(Global);
+
+static POOL: CoPool = CoPool::new(1);
+
+/// The cost of running an empty block on a new coroutine and on a pooled one.
+#[inline(never)]
+fn test(times: u32) -> (Duration, Duration) {
+    let runtime = LocalRuntime::new();
+    let start = Instant::now();
+    for _ in 0..times {
+        runtime.block_on(black_box(async {
+            sync(|| {}).await;
+        }));
+    }
+    let synced = Instant::now().signed_duration_since(start) / times;
+
+    let start = Instant::now();
+    for _ in 0..times {
+        runtime.block_on(black_box(async {
+            POOL.run(|| {}).await;
+        }));
+    }
+    let pooled = Instant::now().signed_duration_since(start) / times;
+
+    let start = Instant::now();
+    for _ in 0..times {
+        runtime.block_on(black_box(async {}));
+    }
+    let direct = Instant::now().signed_duration_since(start) / times;
+
+    (synced - direct, pooled - direct)
+}
+
+fn main() {
+    const NUMS: &[u32] = &[1, 2, 4, 8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+
+    let (synced, pooled) = NUMS.iter().fold(
+        (Duration::ZERO, Duration::ZERO),
+        |(synced_acc, pooled_acc), &num| {
+            let repeat = 1048576 / num;
+
+            let (synced, pooled) = iter::repeat_with(|| test(num))
+                .take(repeat as usize)
+                .fold((Duration::ZERO, Duration::ZERO), |(s, p), (ds, dp)| {
+                    (s + ds, p + dp)
+                });
+            let (synced, pooled) = (synced / repeat, pooled / repeat);
+
+            println!("repeat {} times: sync {}, pool {}", num, synced, pooled);
+            (synced_acc + synced, pooled_acc + pooled)
+        },
+    );
+
+    let len = NUMS.len() as u32;
+    println!("avr: sync {}, pool {}", synced / len, pooled / len);
+}
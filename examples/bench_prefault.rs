@@ -0,0 +1,77 @@
+#![feature(allocator_api)]
+
+use std::{
+    alloc::{Global, Layout},
+    hint::black_box,
+    time::Instant,
+};
+
+use time::{ext::InstantExt, Duration};
+use unico::sym::Co;
+use unico_context::{boost::Boost, global_resumer};
+use unico_stack::global_stack_allocator;
+
+global_resumer!(Boost);
+global_stack_allocator!(Global);
+
+const STACK_SIZE: usize = 1 << 20;
+const DEPTH: u32 = 192;
+
+#[inline(never)]
+fn recurse(depth: u32) -> u32 {
+    let buf = black_box([depth as u8; 4096]);
+    match depth {
+        0 => buf[0].into(),
+        _ => recurse(depth - 1) + u32::from(buf[1]),
+    }
+}
+
+/// Creates a coroutine on a fresh stack, and measures the creation and the
+/// first deep call inside separately.
+fn test(prefault: bool) -> (Duration, Duration) {
+    let layout = Layout::from_size_align(STACK_SIZE, 4096).unwrap();
+
+    let start = Instant::now();
+    let co = (Co::builder().on(layout).prefault(prefault))
+        .spawn(|co| {
+            black_box(recurse(DEPTH));
+            co.unwrap()
+        })
+        .unwrap();
+    let created = Instant::now();
+    assert!(co.resume().is_none());
+    let run = Instant::now();
+
+    (
+        created.signed_duration_since(start),
+        run.signed_duration_since(created),
+    )
+}
+
+/// The mean and the standard deviation.
+fn stats(samples: &[Duration]) -> (Duration, Duration) {
+    let len = samples.len() as f64;
+    let mean = samples.iter().map(|d| d.as_seconds_f64()).sum::<f64>() / len;
+    let variance = (samples.iter())
+        .map(|d| (d.as_seconds_f64() - mean).powi(2))
+        .sum::<f64>()
+        / len;
+    (
+        Duration::seconds_f64(mean),
+        Duration::seconds_f64(variance.sqrt()),
+    )
+}
+
+fn main() {
+    const REPEAT: usize = 4096;
+
+    for prefault in [false, true] {
+        let (create, run): (Vec<_>, Vec<_>) = (0..REPEAT).map(|_| test(prefault)).unzip();
+        let (create_mean, create_dev) = stats(&create);
+        let (run_mean, run_dev) = stats(&run);
+        println!(
+            "prefault = {prefault}: create {create_mean} (± {create_dev}), \
+             run {run_mean} (± {run_dev})"
+        );
+    }
+}
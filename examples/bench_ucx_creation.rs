@@ -0,0 +1,47 @@
+use std::{hint::black_box, ptr::NonNull, time::Instant};
+
+use time::{ext::InstantExt, Duration};
+use unico_context::{
+    ucx::{Ucontext, Ucx},
+    Resume,
+};
+
+const STACK_SIZE: usize = 16384;
+
+unsafe extern "C" fn entry<R: Resume<Context = Ucx> + Default>(
+    mut cx: NonNull<Ucx>,
+    data: *mut (),
+) -> ! {
+    loop {
+        // SAFETY: `cx` is the context resuming this one.
+        let t = unsafe { R::default().resume(cx, data) };
+        cx = t.context.unwrap();
+    }
+}
+
+/// The cost of creating a context and switching into it for the first time.
+#[inline(never)]
+fn test<R: Resume<Context = Ucx> + Default>(resumer: R, times: u32) -> Duration {
+    let mut stack = vec![0u8; STACK_SIZE];
+    let stack = NonNull::from(&mut stack[..]);
+
+    let start = Instant::now();
+    for _ in 0..times {
+        // SAFETY: The stack outlives the context, which is abandoned before the
+        // stack is reused.
+        unsafe {
+            let cx = black_box(resumer.new_on(stack, entry::<R>).unwrap());
+            resumer.resume(cx, std::ptr::null_mut());
+        }
+    }
+    Instant::now().signed_duration_since(start) / times
+}
+
+fn main() {
+    const TIMES: u32 = 1048576;
+
+    let slow = test(Ucontext::DEFAULT, TIMES);
+    println!("getcontext: {slow}");
+    let fast = test(Ucontext::fast_create(), TIMES);
+    println!("fast_create: {fast}");
+}
@@ -1,25 +1,50 @@
 #![feature(allocator_api)]
 
 use std::{
-    alloc::{Allocator, Global},
+    alloc::{Allocator, Global, GlobalAlloc, Layout, System},
     hint::black_box,
     iter,
+    sync::atomic::{AtomicUsize, Ordering::Relaxed},
     time::Instant,
 };
 
 use futures_lite::future::yield_now;
-use spin_on::spin_on;
 use time::{ext::InstantExt, Duration};
-use unico::asym::{sync, AsymWait};
+use unico::{
+    asym::{sync, AsymWait},
+    runtime::LocalRuntime,
+};
 use unico_context::{boost::Boost, global_resumer};
 use unico_stack::global_stack_allocator;
 
 global_resumer!(Boost);
 global_stack_allocator!(Global);
 
+/// Counts the heap allocations to check that waiting allocates nothing.
+struct Counting(AtomicUsize);
+
+// SAFETY: Every allocation is forwarded to `System` as is, which upholds the
+// contract of `GlobalAlloc`. Counting the call never allocates nor panics.
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.0.fetch_add(1, Relaxed);
+        // SAFETY: The contract is the same.
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: `ptr` is allocated by `System` in `alloc` with `layout`.
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static COUNTING: Counting = Counting(AtomicUsize::new(0));
+
 struct TestResult {
     pub duration: Duration,
     pub baseline: Duration,
+    pub allocations: usize,
 }
 
 impl std::iter::Sum for TestResult {
@@ -28,10 +53,12 @@ impl std::iter::Sum for TestResult {
             Self {
                 duration: Duration::ZERO,
                 baseline: Duration::ZERO,
+                allocations: 0,
             },
             |acc, r| Self {
                 duration: acc.duration + r.duration,
                 baseline: acc.baseline + r.baseline,
+                allocations: acc.allocations + r.allocations,
             },
         )
     }
@@ -39,8 +66,10 @@ impl std::iter::Sum for TestResult {
 
 #[inline(never)]
 fn test(times: u32) -> TestResult {
+    let runtime = LocalRuntime::new();
+    let allocations = COUNTING.0.load(Relaxed);
     let start = Instant::now();
-    spin_on(black_box(async {
+    runtime.block_on(black_box(async {
         sync(|| {
             for _ in 0..times {
                 yield_now().wait();
@@ -49,9 +78,10 @@ fn test(times: u32) -> TestResult {
         .await;
     }));
     let synced = Instant::now().signed_duration_since(start) / times;
+    let allocations = COUNTING.0.load(Relaxed) - allocations;
 
     let start = Instant::now();
-    spin_on(black_box(async {
+    runtime.block_on(black_box(async {
         for _ in 0..times {
             yield_now().await;
         }
@@ -61,6 +91,7 @@ fn test(times: u32) -> TestResult {
     TestResult {
         duration: synced,
         baseline: direct,
+        allocations,
     }
 }
 
@@ -80,10 +111,11 @@ fn main() {
         let baseline = result.baseline / repeat;
 
         let diff = duration - baseline;
+        let allocations = result.allocations / repeat as usize;
 
         println!(
-            "yield {} times: {}, duration: {}, baseline: {}",
-            num, diff, duration, baseline
+            "yield {} times: {}, duration: {}, baseline: {}, allocations: {}",
+            num, diff, duration, baseline, allocations
         );
         acc + diff
     });
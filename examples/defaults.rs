@@ -0,0 +1,23 @@
+use std::future::IntoFuture;
+
+use futures_lite::future::yield_now;
+use unico::{
+    asym::{sync, AsymWait},
+    runtime::block_on,
+};
+
+// No `global_resumer!` or `global_stack_allocator!` is needed, since the
+// `default-boost` and `default-global-stack` features install the defaults.
+fn main() {
+    let info = unico::context::backend_info();
+    assert_eq!(info.name, "boost");
+
+    let sum = block_on(
+        sync(|| {
+            yield_now().wait();
+            (1..=10).sum::<u32>()
+        })
+        .into_future(),
+    );
+    assert_eq!(sum, 55);
+}
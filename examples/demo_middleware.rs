@@ -9,10 +9,9 @@ use std::{
 };
 
 use futures_lite::future::yield_now;
-use spin_on::spin_on;
 use time::{ext::InstantExt, Duration};
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
-use unico::asym::{sync, AsymWait};
+use unico::asym::{sync_after, AsymWait};
 use unico_context::{boost::Boost, global_resumer};
 use unico_stack::global_stack_allocator;
 
@@ -74,13 +73,16 @@ fn main() {
 
     // 3. async with unico
     {
-        let f = async {
-            // async file
-            let mut input_file = tokio::fs::File::open(input_file).await.unwrap();
-            let mut output_file =
+        // async file
+        let setup = async {
+            let input_file = tokio::fs::File::open(input_file).await.unwrap();
+            let output_file =
                 tokio::fs::File::create("./target/LICENSE-APACHE-demo-unico")
                     .await
                     .unwrap();
+            (input_file, output_file)
+        };
+        let f = sync_after(setup, |(mut input_file, mut output_file)| {
             // async read and write wrapped by unico
             let input_fn = |buf: &mut [u8]| {
                 AsyncReadExt::read(&mut input_file, buf)
@@ -89,8 +91,8 @@ fn main() {
             };
             let output_fn =
                 |buf: &[u8]| output_file.write_all(buf).wait().map_err(|_| ());
-            sync(|| demo_middleware(input_fn, output_fn).unwrap()).await
-        };
+            demo_middleware(input_fn, output_fn).unwrap()
+        });
 
         let result = tokio::runtime::Builder::new_current_thread()
             .enable_all()
@@ -3,8 +3,10 @@
 use std::{alloc::Global, io::Read};
 
 use futures_lite::{AsyncRead, AsyncReadExt};
-use spin_on::spin_on;
-use unico::asym::{sync, AsymWait};
+use unico::{
+    asym::{sync, AsymWait},
+    runtime::block_on,
+};
 use unico_context::{boost::Boost, global_resumer};
 use unico_stack::global_stack_allocator;
 
@@ -34,7 +36,7 @@ async fn read_direct(
 }
 
 fn main() {
-    spin_on(async move {
+    block_on(async move {
         let r: &[u8] = &[0x12; 6];
         let mut buf1 = [0u8; 6];
         let mut buf2 = [0u8; 6];
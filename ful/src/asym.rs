@@ -3,15 +3,16 @@ use alloc::boxed::Box;
 #[cfg(any(feature = "unwind", feature = "std"))]
 use core::{any::Any, panic::AssertUnwindSafe};
 use core::{
+    fmt,
     marker::PhantomData,
-    mem::MaybeUninit,
+    mem::{ManuallyDrop, MaybeUninit},
     ops::{Coroutine, CoroutineState},
     panic::UnwindSafe,
     pin::Pin,
     ptr,
 };
 
-use unico_stack::{Global, Stack};
+use unico_stack::{Global, IntoStack};
 
 #[cfg(any(feature = "unwind", feature = "std"))]
 use crate::unwind::*;
@@ -62,6 +63,7 @@ enum Payload<Y> {
 //     C <- resume <--------------- end execution
 pub struct Gn<'a, C, Y = (), R = ()> {
     inner: Option<Co>,
+    name: Option<&'static str>,
     marker: PhantomGn<'a, C, Y, R>,
 }
 type PhantomGn<'a, C, Y, R> =
@@ -92,7 +94,7 @@ impl Gn<'static, ()> {
 impl<'a, F, C, Y, R, S, P> Build<F, S, P> for Gn<'a, C, Y, R>
 where
     F: FnOnce(&mut YieldHandle<Y, R>, R) -> C + Send + 'a,
-    S: Into<Stack>,
+    S: IntoStack,
     P: PanicHook,
 {
     fn build(builder: Builder<S, P>, arg: F) -> Result<Self, Self::Error> {
@@ -104,7 +106,7 @@ where
 impl<F, C, Y, R, S, P> BuildUnchecked<F, S, P> for Gn<'_, C, Y, R>
 where
     F: FnOnce(&mut YieldHandle<Y, R>, R) -> C,
-    S: Into<Stack>,
+    S: IntoStack,
     P: PanicHook,
 {
     type Error = NewError;
@@ -118,11 +120,12 @@ where
         builder: Builder<S, P>,
         func: F,
     ) -> Result<Self, Self::Error> {
+        let name = builder.name;
         // SAFETY: See the type's safety notice.
         let wrapper = move |co: Co| {
             // SAFETY: See step 1 of the type's safety notice.
             let (res, payload) = unsafe { co.resume_payloaded(ptr::null_mut()) };
-            let co = res.unwrap();
+            let co = res.expect("the generator is started by a finished control flow");
 
             // SAFETY: See step 2 of the type's safety notice.
             let initial = unsafe { payload.cast::<R>().read() };
@@ -130,27 +133,35 @@ where
                 inner: Some(co),
                 marker: PhantomData,
             };
+            // The return value is moved out by the resumer, so it must not be
+            // dropped here again.
             let c;
             #[cfg(any(feature = "unwind", feature = "std"))]
             let y = match catch_unwind(AssertUnwindSafe(|| func(&mut handle, initial))) {
                 Ok(complete) => {
-                    c = complete;
-                    Payload::<Y>::Complete(ptr::from_ref(&c).cast())
+                    c = ManuallyDrop::new(complete);
+                    Payload::<Y>::Complete(ptr::from_ref::<C>(&c).cast())
+                }
+                Err(payload) => {
+                    let payload = handle_exit(payload);
+                    #[cfg(feature = "std")]
+                    crate::sym::report_panic(&*payload, name);
+                    Payload::Panicked(payload)
                 }
-                Err(payload) => Payload::Panicked(handle_exit(payload)),
             };
             #[cfg(not(any(feature = "unwind", feature = "std")))]
             let y = {
-                c = func(&mut handle, initial);
-                Payload::<Y>::Complete((&c as *const C).cast())
+                c = ManuallyDrop::new(func(&mut handle, initial));
+                Payload::<Y>::Complete(ptr::from_ref::<C>(&c).cast())
             };
 
             let mut y = MaybeUninit::new(y);
-            let co = handle.inner.take().unwrap();
+            let co = (handle.inner.take())
+                .expect("the generator's resumer is taken by a nested yield handle");
 
             // SAFETY: See step 4 of the type's safety notice.
             let (res, _) = unsafe { co.resume_payloaded(y.as_mut_ptr().cast()) };
-            res.unwrap()
+            res.expect("the completed generator is resumed by a finished control flow")
         };
 
         // SAFETY: We here constrain the function to be the same lifetime as the
@@ -158,19 +169,44 @@ where
         // Besides, `func` is `Send`. Also see step 0 of the type's safety notice.
         Ok(Gn {
             inner: unsafe { builder.callcc_unchecked(wrapper) }?,
+            name,
             marker: PhantomData,
         })
     }
 }
 
 impl<C, Y, R> Gn<'_, C, Y, R> {
+    /// Whether the generator has returned or panicked, after which it must not
+    /// be resumed anymore.
+    pub fn is_finished(&self) -> bool {
+        self.inner.is_none()
+    }
+
+    /// The name of the generator set by [`Builder::name`], if any.
+    pub fn name(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    /// Resumes the generator with `resumed`, and returns what it yields or
+    /// returns next.
+    ///
+    /// The first value resumed is passed to the generator as the argument of
+    /// its function, and the following ones are returned from its calls to
+    /// [`YieldHandle::yield_`], like the `send` method of generators in other
+    /// languages.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the generator has completed, or resumes the panic of the
+    /// generator itself.
+    #[track_caller]
     pub fn resume(&mut self, resumed: R) -> CoroutineState<Y, C> {
         let co = (self.inner.take()).expect("coroutine resumed after completion");
         let mut m = MaybeUninit::new(resumed);
 
         // SAFETY: See step 2 and 4 of the type's safety notice.
         let (res, payload) = unsafe { co.resume_payloaded(m.as_mut_ptr().cast()) };
-        let co = res.unwrap();
+        let co = res.expect("the generator returned without reporting its completion");
 
         // SAFETY: See step 3 of the type's safety notice.
         match unsafe { payload.cast::<Payload<Y>>().read() } {
@@ -190,18 +226,100 @@ impl<C, Y, R> Gn<'_, C, Y, R> {
     }
 }
 
+impl<C, Y, R> fmt::Debug for Gn<'_, C, Y, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Gn")
+            .field("name", &self.name)
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
+
 impl<C, Y, R> Coroutine<R> for Gn<'_, C, Y, R> {
     type Yield = Y;
     type Return = C;
 
+    #[track_caller]
+    fn resume(mut self: Pin<&mut Self>, arg: R) -> CoroutineState<Y, C> {
+        (*self).resume(arg)
+    }
+}
+
+impl<'a, C, Y, R> Gn<'a, C, Y, R> {
+    /// Turns the generator into one that can look at its next state without
+    /// losing it. See [`Peekable::peek`] for more information.
+    pub fn peekable(self) -> Peekable<'a, C, Y, R> {
+        Peekable {
+            gn: self,
+            peeked: None,
+        }
+    }
+}
+
+/// A generator with a one-item pushback, which is useful for lookahead
+/// parsing.
+///
+/// This structure is created by [`Gn::peekable`].
+pub struct Peekable<'a, C, Y = (), R = ()> {
+    gn: Gn<'a, C, Y, R>,
+    peeked: Option<CoroutineState<Y, C>>,
+}
+
+impl<C, Y, R> Peekable<'_, C, Y, R> {
+    /// Resumes the generator like [`Gn::resume`], but keeps what it yields or
+    /// returns, which is then returned by the next call to
+    /// [`Peekable::resume`] instead of resuming the generator again.
+    ///
+    /// If some state is already kept, it's returned directly and `resumed` is
+    /// dropped.
+    ///
+    /// # Panics
+    ///
+    /// See [`Gn::resume`].
+    #[track_caller]
+    pub fn peek(&mut self, resumed: R) -> &CoroutineState<Y, C> {
+        let state = match self.peeked.take() {
+            Some(state) => state,
+            None => self.gn.resume(resumed),
+        };
+        self.peeked.insert(state)
+    }
+
+    /// Returns the state kept by [`Peekable::peek`] and drops `resumed`, or
+    /// resumes the generator with `resumed` if nothing is kept.
+    ///
+    /// # Panics
+    ///
+    /// See [`Gn::resume`].
+    #[track_caller]
+    pub fn resume(&mut self, resumed: R) -> CoroutineState<Y, C> {
+        match self.peeked.take() {
+            Some(state) => state,
+            None => self.gn.resume(resumed),
+        }
+    }
+}
+
+// The kept state is never pinned.
+impl<C, Y, R> Unpin for Peekable<'_, C, Y, R> {}
+
+impl<C, Y, R> Coroutine<R> for Peekable<'_, C, Y, R> {
+    type Yield = Y;
+    type Return = C;
+
+    #[track_caller]
     fn resume(mut self: Pin<&mut Self>, arg: R) -> CoroutineState<Y, C> {
         (*self).resume(arg)
     }
 }
 
 impl<Y, R> YieldHandle<Y, R> {
+    /// Yields `yielded` to the caller of [`Gn::resume`], and returns the value
+    /// resumed next.
+    #[track_caller]
     pub fn yield_(&mut self, yielded: Y) -> R {
-        let co = self.inner.take().unwrap();
+        let co = (self.inner.take())
+            .expect("yielded from a generator whose resumer has already finished");
 
         let mut y = MaybeUninit::new(Payload::Yielded(yielded));
         // SAFETY: See step 3 of the type's safety notice.
@@ -235,6 +353,94 @@ mod tests {
         assert!(matches!(gn.resume(1024), CoroutineState::Complete(1024)));
     }
 
+    #[test]
+    fn decoder() {
+        let stream = [2, 2, 2, 0, 3, 3, 3, 3];
+        // Decodes length-prefixed frames, asking for as many bytes as needed.
+        let mut gn = r#gen(|y, mut input: &[u8]| {
+            let mut frames = 0;
+            while let [len, ..] = *input {
+                let frame = y.yield_(usize::from(len));
+                assert_eq!(frame.len(), usize::from(len));
+                assert!(frame.iter().all(|&b| b == len));
+                frames += 1;
+                input = y.yield_(1);
+            }
+            frames
+        });
+
+        let (mut pos, mut need) = (0, 1);
+        let frames = loop {
+            match gn.resume(&stream[pos..(pos + need).min(stream.len())]) {
+                CoroutineState::Yielded(n) => (pos, need) = (pos + need, n),
+                CoroutineState::Complete(frames) => break frames,
+            }
+        };
+        assert_eq!(frames, 3);
+    }
+
+    #[test]
+    fn peek() {
+        let mut gn = r#gen(|y, ()| {
+            y.yield_(1);
+            y.yield_(2);
+            "done"
+        })
+        .peekable();
+
+        assert_eq!(gn.peek(()), &CoroutineState::Yielded(1));
+        assert_eq!(gn.peek(()), &CoroutineState::Yielded(1));
+        assert_eq!(gn.resume(()), CoroutineState::Yielded(1));
+        assert_eq!(gn.resume(()), CoroutineState::Yielded(2));
+        assert_eq!(gn.peek(()), &CoroutineState::Complete("done"));
+        assert_eq!(gn.resume(()), CoroutineState::Complete("done"));
+    }
+
+    #[test]
+    fn owned_return() {
+        let mut gn = r#gen::<_, _, (), _>(|_, ()| alloc::vec![1, 2, 3]);
+        assert_eq!(
+            gn.resume(()),
+            CoroutineState::Complete(alloc::vec![1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn returned_once() {
+        use alloc::sync::Arc;
+
+        let token = Arc::new(());
+        let returned = token.clone();
+        let mut gn = r#gen::<_, _, (), _>(move |_, ()| returned);
+        let CoroutineState::Complete(returned) = gn.resume(()) else {
+            panic!("the coroutine never yields");
+        };
+        // The coroutine has dropped nothing of the value moved out.
+        drop(gn);
+        assert_eq!(Arc::strong_count(&token), 2);
+        drop(returned);
+        assert_eq!(Arc::strong_count(&token), 1);
+    }
+
+    #[test]
+    fn named() {
+        use alloc::format;
+
+        use super::Gn;
+
+        let mut gn = Gn::builder()
+            .name("fatfs-job-3")
+            .r#gen::<_, _, (), _>(|_, ()| ())
+            .unwrap();
+        assert_eq!(gn.name(), Some("fatfs-job-3"));
+        assert_eq!(
+            format!("{gn:?}"),
+            r#"Gn { name: Some("fatfs-job-3"), finished: false }"#
+        );
+        gn.resume(());
+        assert!(format!("{gn:?}").ends_with("finished: true }"));
+    }
+
     #[cfg(feature = "std")]
     #[test]
     #[should_panic = "What the fuck?"]
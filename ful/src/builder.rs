@@ -1,4 +1,4 @@
-use unico_stack::{Global, Stack};
+use unico_stack::{Global, IntoStack, Stack, StackDomain};
 
 use crate::{
     asym::{Gn, YieldHandle},
@@ -11,6 +11,12 @@ use crate::{
 pub struct Builder<S, P> {
     pub stack: S,
     pub panic_hook: P,
+    /// Whether to touch every page of the stack upon creation. See
+    /// [`Stack::prefault`] for more information.
+    pub prefault: bool,
+    /// The name of the coroutine for diagnostics. See [`Builder::name`] for
+    /// more information.
+    pub name: Option<&'static str>,
 }
 
 impl Default for Builder<(), AbortHook> {
@@ -18,6 +24,8 @@ impl Default for Builder<(), AbortHook> {
         Builder {
             stack: (),
             panic_hook: AbortHook,
+            prefault: false,
+            name: None,
         }
     }
 }
@@ -49,19 +57,33 @@ impl Builder<&'static Global, AbortHook> {
         Builder {
             stack: &Global,
             panic_hook: AbortHook,
+            prefault: false,
+            name: None,
         }
     }
 }
 
 impl<S, P> Builder<S, P> {
     /// Set the stack that the coroutine will be run on.
+    ///
+    /// Any [`IntoStack`] is accepted, e.g. an allocated [`Stack`], or a
+    /// `&dyn StackAllocator` overriding the global stack allocator for this
+    /// coroutine only.
     pub fn on<S2>(self, stack: S2) -> Builder<S2, P> {
         Builder {
             stack,
             panic_hook: self.panic_hook,
+            prefault: self.prefault,
+            name: self.name,
         }
     }
 
+    /// Set the stack domain that the coroutine will draw its stack from,
+    /// instead of the global stack allocator.
+    pub fn in_domain<A>(self, domain: &StackDomain<A>) -> Builder<&StackDomain<A>, P> {
+        self.on(domain)
+    }
+
     /// Set the panic hook which give a continuation to pass on when the
     /// coroutine panics. Defaults to [`AbortHook`], which simply aborts the
     /// whole process.
@@ -70,6 +92,32 @@ impl<S, P> Builder<S, P> {
         Builder {
             stack: self.stack,
             panic_hook: hook,
+            prefault: self.prefault,
+            name: self.name,
+        }
+    }
+
+    /// Set whether to touch every page of the stack before the coroutine runs
+    /// on it. Defaults to `false`.
+    ///
+    /// This trades some creation cost for predictable latency inside the
+    /// coroutine. See [`Stack::prefault`] for more information.
+    pub fn prefault(self, prefault: bool) -> Self {
+        Builder { prefault, ..self }
+    }
+
+    /// Set the name of the coroutine, e.g. `"fatfs-job-3"`, which is reported
+    /// along with its panics in [`CoroutinePanic`](crate::CoroutinePanic), and
+    /// kept by the generators, see [`Gn::name`].
+    ///
+    /// The name is stored in the control block on the stack of the coroutine,
+    /// and a generator keeps a copy as well, so both grow by the size of an
+    /// `Option<&'static str>` whether named or not. A [`Co`] doesn't know which
+    /// coroutine it continues, so it cannot tell the name.
+    pub fn name(self, name: &'static str) -> Self {
+        Builder {
+            name: Some(name),
+            ..self
         }
     }
 
@@ -97,7 +145,19 @@ impl<S, P> Builder<S, P> {
     }
 }
 
-impl<S: Into<Stack>, P: PanicHook> Builder<S, P> {
+impl<S: IntoStack, P> Builder<S, P> {
+    /// Allocates the stack, prefaulting it if asked, and returns it along with
+    /// the panic hook and the name.
+    pub(crate) fn into_parts(self) -> Result<(Stack, P, Option<&'static str>), NewError> {
+        let stack = self.stack.into_stack().map_err(NewError::Alloc)?;
+        if self.prefault {
+            stack.prefault();
+        }
+        Ok((stack, self.panic_hook, self.name))
+    }
+}
+
+impl<S: IntoStack, P: PanicHook> Builder<S, P> {
     /// Create a symmetric stackful coroutine.
     ///
     /// Unlike [`Builder::callcc`], the function will not be executed upon
@@ -151,8 +211,9 @@ impl<S: Into<Stack>, P: PanicHook> Builder<S, P> {
     where
         F: FnOnce(Co) -> Co,
     {
+        let (stack, panic_hook, name) = self.into_parts()?;
         // SAFETY: The contract is the same.
-        unsafe { Co::callcc_unchecked(func, self.stack, self.panic_hook) }
+        unsafe { Co::callcc_unchecked(func, stack, panic_hook, name) }
     }
 
     /// Create a stackful generator, a.k.a. an asymmetric coroutine.
@@ -197,7 +258,7 @@ where
 /// Unlike [`callcc`], the function will not be executed upon creation.
 pub fn spawn_on<S, F>(stack: S, func: F) -> Co
 where
-    S: Into<Stack>,
+    S: IntoStack,
     F: FnOnce(Option<Co>) -> Co + Send + 'static,
 {
     Builder::new()
@@ -216,7 +277,7 @@ where
 ///   [`Co`] not escape the lifetime of the function.
 pub unsafe fn spawn_unchecked_on<S, F>(stack: S, func: F) -> Co
 where
-    S: Into<Stack>,
+    S: IntoStack,
     F: FnOnce(Option<Co>) -> Co,
 {
     let builder = Builder::new().on(stack);
@@ -258,7 +319,7 @@ where
 /// it once.
 pub fn callcc_on<S, F>(stack: S, func: F) -> Option<Co>
 where
-    S: Into<Stack>,
+    S: IntoStack,
     F: FnOnce(Co) -> Co + Send + 'static,
 {
     Builder::new()
@@ -277,7 +338,7 @@ where
 ///   [`Co`] not escape the lifetime of the function.
 pub unsafe fn callcc_unchecked_on<S, F>(stack: S, func: F) -> Option<Co>
 where
-    S: Into<Stack>,
+    S: IntoStack,
     F: FnOnce(Co) -> Co,
 {
     let builder = Builder::new().on(stack);
@@ -290,7 +351,7 @@ where
 /// This structure also implements [`core::ops::Coroutine`] trait.
 pub fn gen_on<'a, S, F, C, Y, R>(stack: S, func: F) -> Gn<'a, C, Y, R>
 where
-    S: Into<Stack>,
+    S: IntoStack,
     F: FnOnce(&mut YieldHandle<Y, R>, R) -> C + Send + 'a,
 {
     Builder::new()
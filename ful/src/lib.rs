@@ -24,9 +24,17 @@ pub mod asym;
 mod builder;
 pub mod sym;
 
-use core::alloc::{AllocError, Layout};
+use core::{
+    alloc::{AllocError, Layout},
+    fmt,
+};
 
-pub use crate::builder::*;
+#[cfg(feature = "std")]
+pub use crate::sym::{set_coroutine_panic_hook, CoroutinePanic};
+pub use crate::{
+    builder::*,
+    sym::{on_coroutine_end, on_coroutine_start},
+};
 
 #[cfg(any(feature = "unwind", feature = "std"))]
 extern crate alloc;
@@ -34,10 +42,65 @@ extern crate alloc;
 #[cfg(any(test, feature = "std"))]
 extern crate std;
 
+/// The error returned during the creation of some stackful coroutine.
 #[derive(Debug)]
 pub enum NewError {
+    /// The given stack cannot hold the control block of the coroutine.
     StackTooSmall { expected: Layout, actual: Layout },
+    /// The base of the given stack is not aligned to the alignment of its
+    /// layout, which the stack allocator failed to honor.
+    Misaligned { align: usize, base: usize },
+    /// The global resumer failed to create the context on the stack.
     Context(AllocError),
+    /// The stack allocator failed to allocate the stack.
+    Alloc(AllocError),
+}
+
+impl fmt::Display for NewError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NewError::StackTooSmall { expected, actual } => write!(
+                f,
+                "the stack is too small: expected at least {} bytes aligned to {}, \
+                 got {} bytes aligned to {}",
+                expected.size(),
+                expected.align(),
+                actual.size(),
+                actual.align(),
+            ),
+            NewError::Misaligned { align, base } => write!(
+                f,
+                "the stack is misaligned: expected aligned to {align}, got base {base:#x}"
+            ),
+            NewError::Context(_) => f.write_str("failed to create the context"),
+            NewError::Alloc(_) => f.write_str("failed to allocate the stack"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for NewError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NewError::StackTooSmall { .. } | NewError::Misaligned { .. } => None,
+            NewError::Context(err) | NewError::Alloc(err) => Some(err),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<NewError> for std::io::Error {
+    fn from(err: NewError) -> Self {
+        use std::io::ErrorKind;
+
+        let kind = match err {
+            NewError::StackTooSmall { .. } | NewError::Misaligned { .. } => {
+                ErrorKind::InvalidInput
+            }
+            NewError::Context(_) | NewError::Alloc(_) => ErrorKind::OutOfMemory,
+        };
+        std::io::Error::new(kind, err)
+    }
 }
 
 #[cfg(all(not(feature = "std"), feature = "unwind"))]
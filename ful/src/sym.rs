@@ -1,10 +1,14 @@
 mod layout;
+mod lockstep;
 mod raw;
+mod shared;
 
 #[cfg(any(feature = "unwind", feature = "std"))]
 use alloc::boxed::Box;
 #[cfg(any(feature = "unwind", feature = "std"))]
 use core::any::Any;
+#[cfg(any(feature = "unwind", feature = "std"))]
+use core::panic::AssertUnwindSafe;
 use core::{
     mem::{self, ManuallyDrop},
     ptr::{self, NonNull},
@@ -12,9 +16,19 @@ use core::{
 
 use unico_context as cx;
 use unico_context::Transfer;
-use unico_stack::{Global, Stack};
-
-pub use self::raw::{enter_root, AbortHook, PanicHook};
+use unico_stack::{Global, IntoStack, Stack};
+
+#[cfg(feature = "std")]
+pub(crate) use self::raw::report_panic;
+#[cfg(feature = "std")]
+pub use self::raw::{set_coroutine_panic_hook, CoroutinePanic};
+pub use self::{
+    lockstep::{lockstep, Handoff},
+    raw::{enter_root, on_coroutine_end, on_coroutine_start, AbortHook, PanicHook},
+    shared::{with_context, ContextCo, ContextScope, Lent},
+};
+#[cfg(any(feature = "unwind", feature = "std"))]
+use crate::unwind;
 use crate::{Build, BuildUnchecked, Builder, NewError};
 
 /// A continuation of the current control flow.
@@ -29,6 +43,10 @@ use crate::{Build, BuildUnchecked, Builder, NewError};
 ///   with the whole stack allocation will be ***LEAKED***. It's because the
 ///   dropping process requires unwinding, and thus a `Box<dyn Any + Send>`.
 ///
+/// - Dropping a coroutine never resumed before does not run its function, nor
+///   any of its hooks. The captured environment of the function is dropped
+///   exactly once, and the stack is released, given either feature above.
+///
 /// - If this object represents the root (system) call stack instead of being
 ///   created by builders outside any scope of [`enter_root`], dropping the
 ///   object will result in a panic or blocking the whole control flow.
@@ -64,7 +82,7 @@ impl Co {
 impl<F, S, P> Build<F, S, P> for Co
 where
     F: FnOnce(Option<Co>) -> Co + Send + 'static,
-    S: Into<Stack>,
+    S: IntoStack,
     P: PanicHook,
 {
     fn build(builder: Builder<S, P>, arg: F) -> Result<Self, Self::Error> {
@@ -76,7 +94,7 @@ where
 impl<F, S, P> BuildUnchecked<F, S, P> for Co
 where
     F: FnOnce(Option<Co>) -> Co,
-    S: Into<Stack>,
+    S: IntoStack,
     P: PanicHook,
 {
     type Error = NewError;
@@ -91,9 +109,9 @@ where
         builder: Builder<S, P>,
         arg: F,
     ) -> Result<Self, Self::Error> {
-        let Builder { stack, panic_hook } = builder;
+        let (stack, panic_hook, name) = builder.into_parts()?;
         // SAFETY: The contract is the same.
-        unsafe { raw::RawCo::new_on(stack.into(), panic_hook, arg) }
+        unsafe { raw::RawCo::new_on(stack, panic_hook, name, arg) }
     }
 }
 
@@ -108,15 +126,18 @@ impl Co {
         func: F,
         stack: S,
         panic_hook: P,
+        name: Option<&'static str>,
     ) -> Result<Option<Self>, NewError>
     where
         F: FnOnce(Co) -> Co,
-        S: Into<Stack>,
+        S: IntoStack,
         P: PanicHook,
     {
         let func = |opt: Option<Co>| func(opt.unwrap());
         // SAFETY: The contract is the same.
-        unsafe { raw::RawCo::callcc_on(stack.into(), panic_hook, func) }
+        let stack = stack.into_stack().map_err(NewError::Alloc)?;
+        // SAFETY: The contract is the same.
+        unsafe { raw::RawCo::callcc_on(stack, panic_hook, name, func) }
     }
 
     /// Transfers the current control flow to this continuation.
@@ -129,6 +150,34 @@ impl Co {
         unsafe { self.resume_payloaded(ptr::null_mut()).0 }
     }
 
+    /// Similar to [`Co::resume`], but sends `data` to the destination and
+    /// receives the data sent back when the control flow returns.
+    ///
+    /// The data is received by the destination if and only if it's waiting in
+    /// this method as well, which then gets `data` in the second field of the
+    /// return value. Otherwise (e.g. the destination is just spawned or is
+    /// waiting in [`Co::resume`]), `data` is dropped once the control flow
+    /// returns to this method, or along with the call stack of the caller if
+    /// it never returns. The second field is [`None`] if the source of the
+    /// returned control flow doesn't send any data.
+    #[cfg(any(feature = "unwind", feature = "std"))]
+    pub fn transfer<T: Any + Send>(self, data: T) -> (Option<Self>, Option<Payload>) {
+        // The data stays on this stack until the destination takes it, while
+        // this control flow is suspended.
+        let mut slot: Option<Payload> = Some(Box::new(data));
+        let ptr = ptr::from_mut(&mut slot).cast();
+
+        // SAFETY: Safe resumptions only send null pointers or pointers to the
+        // slots above, whose senders are suspended right now, and the unsafe
+        // ones must maintain the validity by contract.
+        let (co, data) = unsafe { self.resume_payloaded(ptr) };
+        // The destination has taken the data, or never will.
+        drop(slot);
+        // SAFETY: See above.
+        let data = unsafe { data.cast::<Option<Payload>>().as_mut() };
+        (co, data.and_then(Option::take))
+    }
+
     /// Similar to [`Co::resume`], but maps the source of this continuation,
     /// a.k.a. the caller of this method, to another one if possible.
     ///
@@ -153,6 +202,69 @@ impl Co {
         unsafe { self.resume_payloaded_with(map).0 }
     }
 
+    /// Runs `f` on the call stack of this continuation, and returns its result
+    /// once it completes.
+    ///
+    /// The control flow switches to this continuation only for running `f` on
+    /// the free part of its stack, right below where it's suspended, and then
+    /// switches back from where it's suspended once `f` returns. The
+    /// continuation itself is left suspended in place, so it can be resumed
+    /// later as if this method were never called, and its stack doesn't grow
+    /// however many times this method is called.
+    ///
+    /// This is useful to borrow a large stack for some deep computation, such
+    /// as a recursive algorithm, without driving a whole coroutine for it. A
+    /// panic in `f` is propagated to the caller of this method.
+    #[cfg(any(feature = "unwind", feature = "std"))]
+    pub fn run_on_stack<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        /// Turns a panic into an abort while `self` is moved out.
+        struct Abort;
+
+        impl Drop for Abort {
+            fn drop(&mut self) {
+                panic!("a continuation running on its stack is lost")
+            }
+        }
+
+        let mut ret = None;
+        // `run` returns to where this continuation is suspended, which then
+        // switches back to the caller by `raw::receive`.
+        let run = |caller: Co| {
+            ret = Some(unwind::catch_unwind(AssertUnwindSafe(f)));
+            (Some(caller), raw::ran_on_stack())
+        };
+
+        let abort = Abort;
+        // SAFETY: `self` is overwritten right below with the same continuation
+        // switching back, or the process aborts.
+        let co = unsafe { ptr::read(self) };
+        // SAFETY: The payload pointers are only received by `raw::receive`.
+        let (co, _) = unsafe { co.resume_payloaded_with(run) };
+        let co = co.expect("a continuation running on its stack is lost");
+        // SAFETY: See above.
+        unsafe { ptr::write(self, co) };
+        mem::forget(abort);
+
+        match ret.expect("the function is not run on the stack") {
+            Ok(ret) => ret,
+            Err(payload) => unwind::resume_unwind(payload),
+        }
+    }
+
+    /// Terminates this continuation, and returns once it finishes.
+    ///
+    /// The call stack of the continuation is unwound from where it's waiting,
+    /// so all the variables on it are dropped in order, no matter how deep the
+    /// continuation is suspended. Then the control flow comes back to the
+    /// caller. This is exactly what dropping a [`Co`] does, but spelled out for
+    /// schedulers that force-terminate blocked coroutines.
+    ///
+    /// If neither `unwind` nor `std` feature is enabled, the continuation is
+    /// leaked instead. See [`Co`] for more information.
+    pub fn terminate(self) {
+        drop(self)
+    }
+
     /// Similar to [`Co::resume`], but with a pointer payload.
     ///
     /// # Safety
@@ -195,7 +307,7 @@ impl Co {
         //
         //    Thus, though the naming of variables will be a bit rough, the statement
         // actually proves to be true.
-        let Transfer { context, data } = unsafe { cx::resume(cx, payload) };
+        let Transfer { context, data } = unsafe { raw::receive(cx::resume(cx, payload)) };
 
         // SAFETY: `cx` is valid by contract.
         (context.map(|cx| unsafe { Co::from_inner(cx) }), data)
@@ -220,13 +332,17 @@ impl Co {
 
         // SAFETY: The proof is the same as the one in `Co::resume_payloaded`.
         let Transfer { context, data } =
-            unsafe { cx::resume_with(cx, ptr, raw::map::<M>) };
+            unsafe { raw::receive(cx::resume_with(cx, ptr, raw::map::<M>)) };
 
         // SAFETY: `cx` is valid by contract.
         (context.map(|cx| unsafe { Co::from_inner(cx) }), data)
     }
 }
 
+/// The data passed between continuations through [`Co::transfer`].
+#[cfg(any(feature = "unwind", feature = "std"))]
+pub type Payload = Box<dyn Any + Send>;
+
 impl Drop for Co {
     fn drop(&mut self) {
         #[allow(unused_variables)]
@@ -275,17 +391,40 @@ pub fn handle_exit(payload: Box<dyn Any + Send>) -> Box<dyn Any + Send> {
 
 #[cfg(test)]
 mod tests {
-    use core::convert::identity;
-    use std::{alloc::Global, string::String};
+    use core::{
+        cell::{Cell, RefCell},
+        convert::identity,
+        ptr,
+    };
+    use std::{
+        alloc::Global,
+        panic,
+        string::String,
+        sync::{Arc, Mutex},
+        vec::Vec,
+    };
 
     use unico_context::{boost::Boost, global_resumer};
     use unico_stack::global_stack_allocator;
 
-    use crate::{callcc, spawn, spawn_unchecked, sym::exit};
+    use crate::{
+        callcc, callcc_unchecked, spawn, spawn_unchecked,
+        sym::{exit, handle_exit, lockstep, with_context, Co},
+    };
 
     global_stack_allocator!(Global);
     global_resumer!(Boost);
 
+    /// Counts its own drops, which should happen exactly once on every exit
+    /// path of the coroutine holding it.
+    struct DropCounter<'a>(&'a Cell<usize>);
+
+    impl Drop for DropCounter<'_> {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
     #[test]
     fn creation() {
         spawn(Option::unwrap);
@@ -312,6 +451,150 @@ mod tests {
         std::println!("4");
     }
 
+    #[test]
+    fn prefaulted() {
+        let co = Co::builder().prefault(true).spawn(Option::unwrap);
+        assert!(co.unwrap().resume().is_none());
+    }
+
+    #[test]
+    fn run_on_stack() {
+        fn depth(n: u32) -> u32 {
+            let local = [n; 64];
+            if n == 0 {
+                0
+            } else {
+                core::hint::black_box(local)[0].min(1) + depth(n - 1)
+            }
+        }
+
+        let log = RefCell::new(Vec::new());
+        let layout = core::alloc::Layout::from_size_align(1 << 20, 4096).unwrap();
+        let co = unsafe {
+            Co::builder().on(layout).spawn_unchecked(|co| {
+                log.borrow_mut().push("started");
+                let co = co.unwrap().resume().unwrap();
+                log.borrow_mut().push("resumed");
+                co
+            })
+        };
+        let mut co = co.unwrap();
+        let here = 0u8;
+        let there = || {
+            let there = 0u8;
+            ptr::from_ref(&there).addr()
+        };
+        let first = co.run_on_stack(there);
+        assert!(ptr::from_ref(&here).addr().abs_diff(first) > 1 << 16);
+        // Nothing of the previous calls is left on the stack, which stops
+        // growing after the first call.
+        let second = co.run_on_stack(there);
+        assert_eq!(co.run_on_stack(there), second);
+        assert_eq!(co.run_on_stack(|| depth(1000)), 1000);
+        assert!(log.borrow().is_empty());
+
+        // The continuation is kept intact.
+        let mut co = co.resume().unwrap();
+        assert_eq!(*log.borrow(), ["started"]);
+        let caught = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            co.run_on_stack(|| panic!("deep"))
+        }));
+        assert!(caught.is_err());
+        assert!(co.resume().is_none());
+        assert_eq!(*log.borrow(), ["started", "resumed"]);
+    }
+
+    #[test]
+    fn in_domain() {
+        use std::alloc::System;
+
+        use unico_stack::{StackDomain, StackPool, DEFAULT_LAYOUT};
+
+        static POOL: StackPool<System> = StackPool::new(System, DEFAULT_LAYOUT);
+        static DOMAIN: StackDomain<&StackPool<System>> = StackDomain::pooled(&POOL);
+
+        for _ in 0..2 {
+            let co = Co::builder().in_domain(&DOMAIN).spawn(Option::unwrap);
+            assert!(co.unwrap().resume().is_none());
+            assert_eq!(POOL.cached(), 1);
+        }
+    }
+
+    #[test]
+    fn misaligned_stack() {
+        use core::{alloc::Layout, ptr::NonNull};
+
+        use unico_stack::Stack;
+
+        use crate::NewError;
+
+        #[repr(align(4096))]
+        struct Buffer([u8; 16384]);
+
+        unsafe fn leak(_: NonNull<u8>, _: Layout) {}
+
+        let mut buffer = Buffer([0; 16384]);
+        let layout = Layout::from_size_align(8192, 4096).unwrap();
+        // SAFETY: The buffer outlives the stack, which claims a stronger alignment
+        // than its base on purpose.
+        let stack = unsafe {
+            let base = NonNull::from(&mut buffer.0).cast::<u8>().add(1);
+            Stack::new(base, layout, leak)
+        };
+        let err = Co::builder().on(stack).spawn(Option::unwrap).unwrap_err();
+        assert!(matches!(err, NewError::Misaligned { align: 4096, .. }));
+    }
+
+    #[test]
+    fn stack_alloc_failed() {
+        use core::alloc::Layout;
+
+        use crate::NewError;
+
+        // No allocator is able to allocate half of the address space.
+        let layout = Layout::from_size_align(isize::MAX as usize / 2, 4096).unwrap();
+        let err = Co::builder().on(layout).spawn(Option::unwrap).unwrap_err();
+        assert!(matches!(err, NewError::Alloc(_)));
+        let err = Co::builder().on(layout).callcc(|co| co).unwrap_err();
+        assert!(matches!(err, NewError::Alloc(_)));
+    }
+
+    #[test]
+    fn start_end_hooks() {
+        use crate::sym::{on_coroutine_end, on_coroutine_start};
+
+        std::thread_local! {
+            static LOG: RefCell<Vec<(&'static str, usize)>> =
+                const { RefCell::new(Vec::new()) };
+        }
+        fn record(event: &'static str) {
+            let local = 0u8;
+            LOG.with_borrow_mut(|log| log.push((event, ptr::from_ref(&local).addr())));
+        }
+
+        on_coroutine_start(|| record("start")).unwrap();
+        on_coroutine_end(|| record("end")).unwrap();
+        assert!(on_coroutine_start(|| {}).is_err());
+
+        let co = spawn(|co| {
+            record("run");
+            co.unwrap()
+        });
+        assert!(LOG.with_borrow(Vec::is_empty));
+        assert!(co.resume().is_none());
+
+        let log = LOG.take();
+        let events: Vec<_> = log.iter().map(|&(event, _)| event).collect();
+        assert_eq!(events, ["start", "run", "end"]);
+        // The hooks run on the stack of the coroutine.
+        let run = log[1].1;
+        assert!(log.iter().all(|&(_, addr)| addr.abs_diff(run) < 4096));
+
+        // A coroutine dropped before it runs neither starts nor ends.
+        drop(spawn(Option::unwrap));
+        assert!(LOG.with_borrow(Vec::is_empty));
+    }
+
     #[test]
     fn capture_move() {
         let s = String::from("hello");
@@ -364,8 +647,231 @@ mod tests {
         assert!(ret.is_none());
     }
 
+    #[test]
+    fn transfer() {
+        let co = spawn(|co| {
+            let (mut co, mut data) = co.unwrap().transfer(());
+            while let Some(n) = data.and_then(|d| d.downcast::<u32>().ok()) {
+                (co, data) = co.unwrap().transfer(*n + 1);
+            }
+            co.unwrap()
+        });
+        // The first data is dropped since the coroutine is just spawned.
+        let token = Arc::new(());
+        let (co, data) = co.transfer(token.clone());
+        assert!(data.unwrap().is::<()>());
+        assert_eq!(Arc::strong_count(&token), 1);
+
+        let (co, data) = co.unwrap().transfer(1u32);
+        assert_eq!(*data.unwrap().downcast::<u32>().unwrap(), 2);
+        let (co, data) = co.unwrap().transfer(41u32);
+        assert_eq!(*data.unwrap().downcast::<u32>().unwrap(), 42);
+
+        let (co, data) = co.unwrap().transfer("stop");
+        assert!(co.is_none() && data.is_none());
+    }
+
+    #[test]
+    fn terminate() {
+        fn nested(depth: u32, drops: &Cell<usize>, co: Option<Co>) -> Co {
+            let _counter = DropCounter(drops);
+            match depth {
+                0 => co.unwrap().resume().unwrap(),
+                _ => nested(depth - 1, drops, co),
+            }
+        }
+
+        let drops = Cell::new(0);
+        let co = unsafe { spawn_unchecked(|co| nested(3, &drops, co)) };
+        let co = co.resume().unwrap();
+        assert_eq!(drops.get(), 0);
+
+        co.terminate();
+        assert_eq!(drops.get(), 4);
+    }
+
     #[test]
     fn symmetric_direct() {
         assert!(callcc(|a| spawn(move |_| a)).is_none());
     }
+
+    #[test]
+    fn lockstep_early_return() {
+        let mut log = std::vec::Vec::new();
+        let (a, b) = lockstep(
+            |handoff| {
+                let mut sent = 0;
+                while handoff.pass(sent).is_some() {
+                    sent += 1;
+                }
+                assert!(handoff.is_finished());
+                sent
+            },
+            |handoff, first| {
+                log.push(first.unwrap());
+                log.push(handoff.pass(10).unwrap());
+                "done"
+            },
+        );
+        assert_eq!((a, b), (1, "done"));
+        assert_eq!(log, [0, 1]);
+
+        let (a, b) = lockstep(|_| (), |_, first: Option<u8>| first);
+        assert_eq!((a, b), ((), None));
+    }
+
+    #[test]
+    fn shared_context() {
+        let mut log = Vec::new();
+        with_context(&mut log, |scope, log| {
+            let pong = scope.spawn(|source, mut log| {
+                let mut ping = source.unwrap();
+                for _ in 0..2 {
+                    log.push(-1);
+                    let (source, lent) = ping.switch(log);
+                    (ping, log) = (source.unwrap(), lent);
+                }
+                log.push(-1);
+                ping
+            });
+            let ping = scope.spawn(move |source, mut log| {
+                let mut pong = Some(pong);
+                for i in 0..3 {
+                    log.push(i);
+                    (pong, log) = pong.unwrap().switch(log);
+                }
+                // Pong finishes on the last switch.
+                assert!(pong.is_none());
+                source.unwrap()
+            });
+
+            let (source, mut log) = ping.switch(log);
+            assert!(source.is_none());
+            log.push(99);
+        });
+        assert_eq!(log, [0, -1, 1, -1, 2, -1, 99]);
+    }
+
+    #[test]
+    fn drop_on_completion() {
+        let drops = Cell::new(0);
+        let co = unsafe {
+            spawn_unchecked(|co| {
+                let _counter = DropCounter(&drops);
+                co.unwrap()
+            })
+        };
+        assert!(co.resume().is_none());
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    fn drop_on_panic() {
+        let drops = Cell::new(0);
+        let ret = unsafe {
+            callcc_unchecked(|co| {
+                let builder = Co::builder().hook_panic_with(move |_| co);
+                let panicked = builder.spawn_unchecked(|_| {
+                    let _counter = DropCounter(&drops);
+                    panic!("the counter should be dropped")
+                });
+                panicked.unwrap()
+            })
+        };
+        assert!(ret.is_none());
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    fn coroutine_panic_hook() {
+        static MESSAGES: Mutex<Vec<(String, Option<&str>)>> = Mutex::new(Vec::new());
+        crate::set_coroutine_panic_hook(|panic| {
+            MESSAGES
+                .lock()
+                .unwrap()
+                .push((panic.message().into(), panic.name));
+        });
+
+        let ret = callcc(|co| {
+            let builder = Co::builder().name("reporter").hook_panic_with(move |_| co);
+            builder.spawn(|_| panic!("reported")).unwrap()
+        });
+        assert!(ret.is_none());
+        let messages = MESSAGES.lock().unwrap();
+        assert!(messages.contains(&("reported".into(), Some("reporter"))));
+    }
+
+    #[test]
+    fn drop_on_exit() {
+        let drops = Cell::new(0);
+        let co = unsafe {
+            spawn_unchecked(|co| {
+                let _counter = DropCounter(&drops);
+                exit(co.unwrap())
+            })
+        };
+        assert!(co.resume().is_none());
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    fn drop_while_suspended() {
+        let drops = Cell::new(0);
+        let co = unsafe {
+            spawn_unchecked(|co| {
+                let _counter = DropCounter(&drops);
+                co.unwrap().resume().unwrap()
+            })
+        };
+        let co = co.resume().unwrap();
+        assert_eq!(drops.get(), 0);
+        drop(co);
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    fn drop_unstarted() {
+        use std::alloc::System;
+
+        use unico_stack::{StackDomain, StackPool, DEFAULT_LAYOUT};
+
+        static POOL: StackPool<System> = StackPool::new(System, DEFAULT_LAYOUT);
+        static DOMAIN: StackDomain<&StackPool<System>> = StackDomain::pooled(&POOL);
+
+        let drops = Cell::new(0);
+        let counter = DropCounter(&drops);
+        let builder = Co::builder()
+            .in_domain(&DOMAIN)
+            .hook_panic_with(|_| -> Co { unreachable!("the panic hook is called") });
+        let co = unsafe {
+            builder.spawn_unchecked(move |_| {
+                let _counter = counter;
+                unreachable!("the function is called")
+            })
+        };
+        assert_eq!(drops.get(), 0);
+        assert_eq!(POOL.cached(), 0);
+        drop(co.unwrap());
+        assert_eq!(drops.get(), 1);
+        assert_eq!(POOL.cached(), 1);
+    }
+
+    #[test]
+    fn drop_through_catch_unwind() {
+        let drops = Cell::new(0);
+        let co = unsafe {
+            spawn_unchecked(|co| {
+                let _outer = DropCounter(&drops);
+                let caught = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                    let _inner = DropCounter(&drops);
+                    co.unwrap().resume().unwrap()
+                }));
+                caught
+                    .unwrap_or_else(|payload| panic::resume_unwind(handle_exit(payload)))
+            })
+        };
+        let co = co.resume().unwrap();
+        co.terminate();
+        assert_eq!(drops.get(), 2);
+    }
 }
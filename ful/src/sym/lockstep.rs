@@ -0,0 +1,126 @@
+use core::{cell::Cell, marker::PhantomData, ptr};
+
+use super::Co;
+use crate::{callcc_unchecked, spawn_unchecked};
+
+/// The handle of one side in [`lockstep`], which passes values to its peer.
+#[derive(Debug)]
+pub struct Handoff<V> {
+    peer: Option<Co>,
+    marker: PhantomData<fn(V) -> V>,
+}
+
+impl<V> Handoff<V> {
+    fn new(peer: Option<Co>) -> Self {
+        Handoff {
+            peer,
+            marker: PhantomData,
+        }
+    }
+
+    /// Whether the peer has returned.
+    pub fn is_finished(&self) -> bool {
+        self.peer.is_none()
+    }
+
+    /// Transfers the control flow to the peer alongside with `value`, and
+    /// returns the value passed back once the peer transfers the control flow
+    /// back.
+    ///
+    /// Returns [`None`] without switching if the peer has already returned, or
+    /// after the switch if the peer returns instead of passing a value back.
+    pub fn pass(&mut self, value: V) -> Option<V> {
+        let peer = self.peer.take()?;
+        let mut slot = Some(value);
+        // SAFETY: The peer is waiting in this method as well, or in the entry of
+        // `lockstep`, both of which read the pointer before this method returns.
+        let (peer, data) =
+            unsafe { peer.resume_payloaded(ptr::from_mut(&mut slot).cast()) };
+        // SAFETY: See `Handoff::receive`.
+        unsafe { self.receive(peer, data) }
+    }
+
+    /// # Safety
+    ///
+    /// `data` must point to the slot of a peer waiting in `Handoff::pass` if
+    /// `peer` is [`Some`].
+    unsafe fn receive(&mut self, peer: Option<Co>, data: *mut ()) -> Option<V> {
+        // A returned peer resumes us without a continuation and a payload.
+        self.peer = peer;
+        self.peer.as_ref()?;
+        // SAFETY: The slot is valid by contract.
+        unsafe { (*data.cast::<Option<V>>()).take() }
+    }
+}
+
+/// Runs `a` and `b` as a pair of symmetric coroutines that alternate
+/// deterministically, and returns both of their results.
+///
+/// `a` runs first until it passes a value to `b` through [`Handoff::pass`],
+/// which starts `b` with that value. Then `b` runs until it passes a value
+/// back, and so on, like a classic producer/consumer pair. Once either side
+/// returns, the pending `pass` of its peer returns [`None`], and the peer runs
+/// to end on its own. `b` receives [`None`] if `a` returns without passing any
+/// value.
+///
+/// Both sides run on the current thread, so the functions need not to be
+/// [`Send`] or `'static`. Panics in either side abort the whole process.
+///
+/// ```rust
+/// # #![feature(allocator_api)]
+/// # unico_stack::global_stack_allocator!(std::alloc::Global);
+/// # unico_context::global_resumer!(unico_context::boost::Boost);
+/// use unico_ful::sym::lockstep;
+///
+/// let (produced, sum) = lockstep(
+///     |handoff| (1..=3).filter(|&n| handoff.pass(n).is_some()).count(),
+///     |handoff, mut value| {
+///         let mut sum = 0;
+///         while let Some(n) = value {
+///             sum += n;
+///             value = handoff.pass(0);
+///         }
+///         sum
+///     },
+/// );
+/// assert_eq!((produced, sum), (3, 6));
+/// ```
+pub fn lockstep<V, RA, RB>(
+    a: impl FnOnce(&mut Handoff<V>) -> RA,
+    b: impl FnOnce(&mut Handoff<V>, Option<V>) -> RB,
+) -> (RA, RB) {
+    let root = Cell::new(None);
+    let (mut ra, mut rb) = (None, None);
+
+    let finish = |handoff: &mut Handoff<V>| match handoff.peer.take() {
+        Some(peer) => peer,
+        None => root.take().expect("the root continuation is lost"),
+    };
+    let b = |co: Option<Co>| {
+        // Wait in the entry for the first value from `a`.
+        // SAFETY: The pointer is unused by the root control flow.
+        let (peer, data) = unsafe { co.unwrap().resume_payloaded(ptr::null_mut()) };
+        let mut handoff = Handoff::new(None);
+        // SAFETY: `a` resumes the entry in `Handoff::pass`, or by returning.
+        let value = unsafe { handoff.receive(peer, data) };
+        rb = Some(b(&mut handoff, value));
+        finish(&mut handoff)
+    };
+    // SAFETY: The coroutine is resumed to end before the function returns, and
+    // is never sent to another thread.
+    let peer = unsafe { spawn_unchecked(b) };
+    let peer = peer
+        .resume()
+        .expect("the coroutine is not waiting in its entry");
+
+    let a = |co: Co| {
+        root.set(Some(co));
+        let mut handoff = Handoff::new(Some(peer));
+        ra = Some(a(&mut handoff));
+        finish(&mut handoff)
+    };
+    // SAFETY: See above.
+    let ret = unsafe { callcc_unchecked(a) };
+    debug_assert!(ret.is_none());
+    (ra.unwrap(), rb.unwrap())
+}
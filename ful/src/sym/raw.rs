@@ -1,32 +1,64 @@
+mod hooks;
 mod panicking;
 
 #[cfg(any(feature = "unwind", feature = "std"))]
-use core::panic::AssertUnwindSafe;
+use alloc::boxed::Box;
 use core::{
     alloc::Layout,
     any::type_name,
     panic::UnwindSafe,
     ptr::{self, NonNull},
 };
+#[cfg(any(feature = "unwind", feature = "std"))]
+use core::{any::Any, panic::AssertUnwindSafe};
 
 use unico_context::{self as cx, Transfer};
 
-pub use self::panicking::*;
+pub use self::{
+    hooks::{on_coroutine_end, on_coroutine_start},
+    panicking::*,
+};
 use super::{layout::extend, Co, NewError, Stack};
 #[cfg(any(feature = "unwind", feature = "std"))]
 use crate::unwind;
 
+/// The payload of the switch made by a function run on some stack by
+/// [`Co::run_on_stack`], which is never sent by anything else.
+static RAN_ON_STACK: u8 = 0;
+
+pub(super) fn ran_on_stack() -> *mut () {
+    ptr::addr_of!(RAN_ON_STACK).cast_mut().cast()
+}
+
+/// Receives the transfer `t` to the current context, and switches straight back
+/// whenever it only comes from a function run on top of this stack by
+/// [`Co::run_on_stack`], so that nothing of that function is left on the stack.
+///
+/// # Safety
+///
+/// `t` must be returned by a switch to the current context.
+pub(super) unsafe fn receive(mut t: Transfer<()>) -> Transfer<()> {
+    while t.data == ran_on_stack() {
+        let cx = (t.context).expect("`Co::run_on_stack` switched from nowhere");
+        // SAFETY: The proof is the same as the one in `Co::resume_payloaded`.
+        t = unsafe { cx::resume(cx, ptr::null_mut()) };
+    }
+    t
+}
+
 struct Layouts {
     layout: Layout,
     offset_stack: usize,
     offset_func: usize,
     offset_hook: usize,
+    offset_name: usize,
 }
 
 pub(crate) struct RawCo<F, P: PanicHook> {
     stack: *mut Stack,
     func: *mut F,
     panic_hook: *mut P,
+    name: *mut Option<&'static str>,
 }
 
 impl<F, P: PanicHook> RawCo<F, P> {
@@ -34,11 +66,13 @@ impl<F, P: PanicHook> RawCo<F, P> {
         let func = Layout::new::<F>();
         let stack = Layout::new::<Stack>();
         let hook = Layout::new::<P>();
+        let name = Layout::new::<Option<&'static str>>();
 
         let layout = Layout::new::<()>();
         let (layout, offset_stack) = ct!(extend(layout, stack));
         let (layout, offset_func) = ct!(extend(layout, func));
         let (layout, offset_hook) = ct!(extend(layout, hook));
+        let (layout, offset_name) = ct!(extend(layout, name));
 
         assert!(offset_stack == 0);
         Some(Layouts {
@@ -46,6 +80,7 @@ impl<F, P: PanicHook> RawCo<F, P> {
             offset_stack,
             offset_func,
             offset_hook,
+            offset_name,
         })
     }
 
@@ -68,6 +103,7 @@ impl<F, P: PanicHook> RawCo<F, P> {
             stack: ptr.map_addr(|addr| addr + layouts.offset_stack).cast(),
             func: ptr.map_addr(|addr| addr + layouts.offset_func).cast(),
             panic_hook: ptr.map_addr(|addr| addr + layouts.offset_hook).cast(),
+            name: ptr.map_addr(|addr| addr + layouts.offset_name).cast(),
         }
     }
 }
@@ -84,18 +120,20 @@ where
     pub(crate) unsafe fn new_on(
         stack: Stack,
         panic_hook: P,
+        name: Option<&'static str>,
         func: F,
     ) -> Result<Co, NewError> {
-        Self::new_on_imp(stack, panic_hook, func, Self::entry::<false>)
+        Self::new_on_imp(stack, panic_hook, name, func, Self::entry::<false>)
             .map(Option::unwrap)
     }
 
     pub(crate) unsafe fn callcc_on(
         stack: Stack,
         panic_hook: P,
+        name: Option<&'static str>,
         func: F,
     ) -> Result<Option<Co>, NewError> {
-        Self::new_on_imp(stack, panic_hook, func, Self::entry::<true>)
+        Self::new_on_imp(stack, panic_hook, name, func, Self::entry::<true>)
     }
 
     /// # Safety
@@ -104,6 +142,7 @@ where
     pub(crate) unsafe fn new_on_imp(
         stack: Stack,
         panic_hook: P,
+        name: Option<&'static str>,
         func: F,
         entry: cx::Entry<()>,
     ) -> Result<Option<Co>, NewError> {
@@ -117,6 +156,12 @@ where
                 actual: stack_layout,
             });
         }
+        if stack.base().addr().get() & (stack_layout.align() - 1) != 0 {
+            return Err(NewError::Misaligned {
+                align: stack_layout.align(),
+                base: stack.base().addr().get(),
+            });
+        }
 
         let (pointer, rest_size) = {
             let layouts = Self::layouts();
@@ -144,10 +189,11 @@ where
             raw.stack.write(stack);
             raw.func.write(func);
             raw.panic_hook.write(panic_hook);
+            raw.name.write(name);
         }
 
         // SAFETY: The proof is the same as the one in `Co::resume_payloaded`.
-        let resume = unsafe { cx::resume(context, pointer) };
+        let resume = unsafe { receive(cx::resume(context, pointer)) };
         // SAFETY: `context` is valid by contract.
         Ok(resume.context.map(|cx| unsafe { Co::from_inner(cx) }))
     }
@@ -168,27 +214,41 @@ where
         // SAFETY: The task is valid by contract.
         #[cfg(any(feature = "unwind", feature = "std"))]
         let hook = unsafe { task.panic_hook.read() };
+        // SAFETY: The task is valid by contract.
+        #[cfg(feature = "std")]
+        let name = unsafe { task.name.read() };
         // SAFETY: The task is valid by contract. `func` must be read before the
         // initial resume, in case of early unwinding.
         let func = unsafe { task.func.read() };
 
+        // The end hook only pairs with a started coroutine, not a dropped one.
+        let mut started = false;
         let run = || {
-            func(if CALLCC {
+            let co = if CALLCC {
                 // SAFETY: `cx` is valid by contract.
                 Some(unsafe { Co::from_inner(cx) })
             } else {
                 // SAFETY: The proof is the same as the one in `Co::resume_payloaded`.
-                let Transfer { context, .. } = unsafe { cx::resume(cx, ptr) };
+                let Transfer { context, .. } = unsafe { receive(cx::resume(cx, ptr)) };
                 // SAFETY: `cx` is valid by contract.
                 context.map(|cx| unsafe { Co::from_inner(cx) })
-            })
+            };
+            hooks::start();
+            started = true;
+            func(co)
         };
 
         #[cfg(any(feature = "unwind", feature = "std"))]
         let context = {
             // Move the hook in the braces to make sure it drops when the control flow
             // goes out of the scope.
-            let rewind = |payload| AssertUnwindSafe(|| hook.rewind(payload));
+            let rewind = |payload: Box<dyn Any + Send>| {
+                AssertUnwindSafe(|| {
+                    #[cfg(feature = "std")]
+                    report_panic(&*payload, name);
+                    hook.rewind(payload)
+                })
+            };
             Co::into_inner('run: {
                 // Run the main function and catches its panic (or unwound `HandleDrop`)
                 // if possible.
@@ -213,6 +273,9 @@ where
         };
         #[cfg(not(any(feature = "unwind", feature = "std")))]
         let (context, next) = Co::into_inner(run());
+        if started {
+            hooks::end();
+        }
 
         // SAFETY: The proof is the same as the one in `Co::resume_payloaded`.
         unsafe { cx::resume_with(context, ptr, Self::exit) };
@@ -223,18 +286,18 @@ where
     ///
     /// `ptr` must points to a valid `RawCo` calculated from `RawCo::from_ptr`.
     #[allow(improper_ctypes_definitions)]
-    unsafe extern "C-unwind" fn exit(_: NonNull<()>, ptr: *mut ()) -> Transfer<()> {
+    unsafe extern "C-unwind" fn exit(cx: NonNull<()>, ptr: *mut ()) -> Transfer<()> {
         let task = Self::from_ptr(ptr);
+        // SAFETY: The finished context is never resumed again, and its stack is
+        // released right below, back to the allocator it comes from.
+        unsafe { cx::dealloc_context(cx) };
         // SAFETY: The task is valid by contract.
         unsafe {
             // `stack` must not be dropped in place to avoid access to dropped stack
             // memory.
             drop(task.stack.read())
         }
-        Transfer {
-            context: None,
-            data: ptr::null_mut(),
-        }
+        Transfer::new(None, ptr::null_mut())
     }
 }
 
@@ -250,10 +313,7 @@ pub(super) unsafe extern "C-unwind" fn map<M: FnOnce(Co) -> (Option<Co>, *mut ()
     let func = unsafe { payload.cast::<M>().read() };
     // SAFETY: `cx` is valid by contract.
     let (ret, payload) = func(unsafe { Co::from_inner(cx) });
-    Transfer {
-        context: ret.map(Co::into_inner),
-        data: payload,
-    }
+    Transfer::new(ret.map(Co::into_inner), payload)
 }
 
 /// Enters a scope in the root control flow so that it can be dropped via its
@@ -0,0 +1,48 @@
+use core::{
+    mem,
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicPtr, Ordering::*},
+};
+
+static START: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+static END: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Sets the global hook invoked on the stack of every coroutine, right before
+/// its function runs.
+///
+/// The hook can be set only once, and `hook` is given back if it's already
+/// set. A panic in the hook is treated as a panic of the coroutine.
+pub fn on_coroutine_start(hook: fn()) -> Result<(), fn()> {
+    set(&START, hook)
+}
+
+/// Sets the global hook invoked on the stack of every coroutine, right after
+/// its function returns or its panic is handled.
+///
+/// The hook can be set only once, and `hook` is given back if it's already
+/// set. A panic in the hook aborts the process.
+pub fn on_coroutine_end(hook: fn()) -> Result<(), fn()> {
+    set(&END, hook)
+}
+
+fn set(slot: &AtomicPtr<()>, hook: fn()) -> Result<(), fn()> {
+    slot.compare_exchange(ptr::null_mut(), hook as *mut (), AcqRel, Acquire)
+        .map(drop)
+        .map_err(|_| hook)
+}
+
+fn run(slot: &AtomicPtr<()>) {
+    if let Some(hook) = NonNull::new(slot.load(Acquire)) {
+        // SAFETY: Only `fn()`s are stored in the slots.
+        let hook = unsafe { mem::transmute::<NonNull<()>, fn()>(hook) };
+        hook()
+    }
+}
+
+pub(in crate::sym) fn start() {
+    run(&START)
+}
+
+pub(in crate::sym) fn end() {
+    run(&END)
+}
@@ -64,9 +64,91 @@ pub trait PanicHook {
 pub struct AbortHook;
 
 impl PanicHook for AbortHook {
-    #[cfg(any(feature = "unwind", feature = "std"))]
-    fn rewind(self, _: Box<dyn Any + Send>) -> Co {
-        unreachable!("Uncaught panic in the root of a symmetric coroutine. Aborting.")
+    #[cfg(feature = "std")]
+    fn rewind(self, payload: Box<dyn Any + Send>) -> Co {
+        std::eprintln!(
+            "Uncaught panic in the root of a symmetric coroutine: {}. Aborting.",
+            message(&*payload)
+        );
+        std::process::abort()
+    }
+
+    #[cfg(all(not(feature = "std"), feature = "unwind"))]
+    fn rewind(self, payload: Box<dyn Any + Send>) -> Co {
+        unreachable!(
+            "Uncaught panic in the root of a symmetric coroutine: {}. Aborting.",
+            message(&*payload)
+        )
+    }
+}
+
+/// A panic leaving some coroutine, reported to the hook set by
+/// [`set_coroutine_panic_hook`].
+#[cfg(feature = "std")]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct CoroutinePanic<'a> {
+    /// The payload of the panic.
+    pub payload: &'a (dyn Any + Send),
+    /// The name of the coroutine, if set by
+    /// [`Builder::name`](crate::Builder::name).
+    pub name: Option<&'static str>,
+}
+
+#[cfg(feature = "std")]
+impl CoroutinePanic<'_> {
+    /// The message of the panic, like what the default panic hook prints.
+    pub fn message(&self) -> &str {
+        message(self.payload)
+    }
+}
+
+#[cfg(feature = "std")]
+type CoroutinePanicHook = Box<dyn Fn(&CoroutinePanic<'_>) + Send + Sync>;
+
+#[cfg(feature = "std")]
+static COROUTINE_PANIC_HOOK: std::sync::RwLock<Option<CoroutinePanicHook>> =
+    std::sync::RwLock::new(None);
+
+/// Registers a hook called whenever a panic leaves a coroutine, replacing the
+/// previous one.
+///
+/// The standard panic hook still runs as usual when the panic begins, where
+/// only the frames of the coroutine itself are visible. This hook is called
+/// afterwards, still on the stack of the coroutine, right before the panic is
+/// passed to its [`PanicHook`] or resumed in the caller of a generator, so
+/// some richer context can be logged. A panic propagated through nested
+/// generators is thus reported once by each of them.
+///
+/// The hook must not set another hook, or it deadlocks.
+#[cfg(feature = "std")]
+pub fn set_coroutine_panic_hook(
+    hook: impl Fn(&CoroutinePanic<'_>) + Send + Sync + 'static,
+) {
+    let mut slot =
+        (COROUTINE_PANIC_HOOK.write()).unwrap_or_else(std::sync::PoisonError::into_inner);
+    *slot = Some(Box::new(hook));
+}
+
+/// Calls the hook set by [`set_coroutine_panic_hook`], if any.
+#[cfg(feature = "std")]
+pub(crate) fn report_panic(payload: &(dyn Any + Send), name: Option<&'static str>) {
+    let hook =
+        (COROUTINE_PANIC_HOOK.read()).unwrap_or_else(std::sync::PoisonError::into_inner);
+    if let Some(hook) = &*hook {
+        hook(&CoroutinePanic { payload, name })
+    }
+}
+
+/// The message of a panic payload, like what the default panic hook prints.
+#[cfg(any(feature = "unwind", feature = "std"))]
+fn message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&'static str>() {
+        Some(message) => message,
+        None => match payload.downcast_ref::<alloc::string::String>() {
+            Some(message) => message,
+            None => "Box<dyn Any>",
+        },
     }
 }
 
@@ -0,0 +1,165 @@
+use core::{
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+    ptr::{self, NonNull},
+};
+
+use super::Co;
+use crate::spawn_unchecked;
+
+/// The context shared by the coroutines of [`with_context`], lent to the one
+/// running at the moment.
+///
+/// Only one `Lent` of a context exists at a time, and it's moved along with
+/// the control flow on every [`ContextCo::switch`], so the references derived
+/// from it never live across a switch.
+#[derive(Debug)]
+pub struct Lent<'s, Ctx> {
+    ptr: NonNull<Ctx>,
+    marker: PhantomData<*mut &'s mut Ctx>,
+}
+
+impl<Ctx> Lent<'_, Ctx> {
+    fn new(ptr: NonNull<Ctx>) -> Self {
+        Lent {
+            ptr,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<Ctx> Deref for Lent<'_, Ctx> {
+    type Target = Ctx;
+
+    fn deref(&self) -> &Ctx {
+        // SAFETY: The context is exclusively borrowed by `with_context`, and
+        // this is the only `Lent` of it for now.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<Ctx> DerefMut for Lent<'_, Ctx> {
+    fn deref_mut(&mut self) -> &mut Ctx {
+        // SAFETY: See above.
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+/// A continuation in [`with_context`], which takes the shared context along
+/// when switched to.
+///
+/// Dropping it before the coroutine finishes leaks the coroutine with its
+/// stack, rather than unwinding it while the context is lent elsewhere.
+#[derive(Debug)]
+pub struct ContextCo<'s, Ctx> {
+    co: ManuallyDrop<Co>,
+    marker: PhantomData<*mut &'s mut Ctx>,
+}
+
+impl<'s, Ctx> ContextCo<'s, Ctx> {
+    fn new(co: Co) -> Self {
+        ContextCo {
+            co: ManuallyDrop::new(co),
+            marker: PhantomData,
+        }
+    }
+
+    /// Transfers the control flow to this continuation, and lends it `ctx`
+    /// until some other one switches back.
+    ///
+    /// Returns the source of the returned control flow, which is [`None`] if
+    /// it comes from a coroutine that has finished, and the context lent back.
+    pub fn switch(self, ctx: Lent<'s, Ctx>) -> (Option<Self>, Lent<'s, Ctx>) {
+        let co = ManuallyDrop::into_inner(self.co);
+        let ptr = ctx.ptr;
+        // SAFETY: Every continuation in the scope waits in this method or in the
+        // entry of `ContextScope::spawn`, both of which take the pointer as the
+        // `Lent` moved in, or ignore it.
+        let (source, data) = unsafe { co.resume_payloaded(ptr.as_ptr().cast()) };
+        // A finished coroutine switches back without any payload.
+        debug_assert!(data.is_null() || data == ptr.as_ptr().cast());
+        (source.map(Self::new), Lent::new(ptr))
+    }
+}
+
+/// The scope of [`with_context`], where the coroutines sharing the context are
+/// spawned.
+#[derive(Debug)]
+pub struct ContextScope<'s, 'env: 's, Ctx> {
+    ptr: NonNull<Ctx>,
+    scope: PhantomData<&'s mut &'s ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+impl<'s, Ctx> ContextScope<'s, '_, Ctx> {
+    /// Creates a symmetric coroutine in this scope, which starts running `func`
+    /// with the source and the context of the first switch to it.
+    ///
+    /// Once `func` returns, the control flow is transferred to the continuation
+    /// it returns, where the context is lent as well.
+    pub fn spawn<F>(&'s self, func: F) -> ContextCo<'s, Ctx>
+    where
+        F: FnOnce(Option<ContextCo<'s, Ctx>>, Lent<'s, Ctx>) -> ContextCo<'s, Ctx> + 's,
+    {
+        let ptr = self.ptr;
+        let entry = move |co: Option<Co>| {
+            // Wait in the entry for the first switch.
+            // SAFETY: The pointer is unused by the spawner.
+            let (source, _) = unsafe { co.unwrap().resume_payloaded(ptr::null_mut()) };
+            let next = func(source.map(ContextCo::new), Lent::new(ptr));
+            ManuallyDrop::into_inner(next.co)
+        };
+        // SAFETY: The continuations cannot escape the scope, nor be sent to
+        // another thread. Those not finished by the end of the scope are
+        // leaked, never resumed again.
+        let co = unsafe { spawn_unchecked(entry) };
+        let co = co
+            .resume()
+            .expect("the coroutine is not waiting in its entry");
+        ContextCo::new(co)
+    }
+}
+
+/// Runs `func` with a scope of symmetric coroutines sharing `ctx`, which is
+/// lent to the running one and passed along on every switch.
+///
+/// This is the typed alternative to passing a raw pointer through the payload
+/// of [`Co::resume_payloaded`] by hand. The context is accessed through a
+/// [`Lent`], which is moved into [`ContextCo::switch`] and given back when the
+/// control flow returns, so the borrow checker rules out any reference to the
+/// context living across a switch, where another coroutine may mutate it.
+///
+/// The coroutines run on the current thread, so neither the context nor the
+/// functions need to be [`Send`]. Panics in the coroutines abort the whole
+/// process.
+///
+/// ```rust
+/// # #![feature(allocator_api)]
+/// # unico_stack::global_stack_allocator!(std::alloc::Global);
+/// # unico_context::global_resumer!(unico_context::boost::Boost);
+/// use unico_ful::sym::with_context;
+///
+/// let mut count = 0;
+/// with_context(&mut count, |scope, count| {
+///     let co = scope.spawn(|source, mut count| {
+///         *count += 1;
+///         source.unwrap()
+///     });
+///     let (source, count) = co.switch(count);
+///     assert!(source.is_none());
+///     assert_eq!(*count, 1);
+/// });
+/// assert_eq!(count, 1);
+/// ```
+pub fn with_context<'env, Ctx, R>(
+    ctx: &'env mut Ctx,
+    func: impl for<'s> FnOnce(&'s ContextScope<'s, 'env, Ctx>, Lent<'s, Ctx>) -> R,
+) -> R {
+    let scope = ContextScope {
+        ptr: NonNull::from(ctx),
+        scope: PhantomData,
+        env: PhantomData,
+    };
+    func(&scope, Lent::new(scope.ptr))
+}
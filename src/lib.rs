@@ -1,9 +1,16 @@
 #![doc = include_str!("../README.md")]
 #![no_std]
 
+pub use unico_async::sync;
 pub use unico_context as context;
-pub use unico_ful::{Build, BuildUnchecked, Builder, NewError};
+pub use unico_ful::{
+    on_coroutine_end, on_coroutine_start, Build, BuildUnchecked, Builder, NewError,
+};
+#[cfg(feature = "std")]
+pub use unico_ful::{set_coroutine_panic_hook, CoroutinePanic};
 pub use unico_stack as stack;
+#[cfg(feature = "signal")]
+pub use unico_stack::install_signal_stack;
 #[cfg(feature = "sym")]
 pub mod sym {
     pub use unico_async::sym::*;
@@ -18,5 +25,11 @@ pub mod asym {
     pub use unico_async::asym::*;
     pub use unico_ful::asym::*;
 }
+#[cfg(feature = "tokio")]
+pub use unico_async::io;
+#[cfg(feature = "std")]
+pub use unico_async::runtime;
+#[cfg(all(feature = "asym", feature = "std"))]
+pub use unico_async::test;
 #[cfg(feature = "asym")]
 pub use unico_ful::{gen_on, r#gen};
@@ -0,0 +1,348 @@
+//! A stack allocator carving the stacks out of one large memory mapping.
+
+use core::{
+    alloc::{AllocError, Layout},
+    cell::UnsafeCell,
+    ffi::c_void,
+    fmt, hint, mem,
+    ptr::{self, NonNull},
+    sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering::*},
+};
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+
+use crate::{Stack, StackAllocator};
+
+/// The bookkeeping data placed at the start of the mapping, shared by the
+/// allocator and the stacks handed out.
+struct Header {
+    mapping: NonNull<c_void>,
+    len: usize,
+    page: usize,
+    stride: usize,
+    count: usize,
+    /// One for the allocator, and one for every stack handed out. The mapping
+    /// is unmapped by whoever drops the last one.
+    refs: AtomicUsize,
+    lock: AtomicBool,
+    lists: UnsafeCell<Lists>,
+}
+
+/// The stacks available in the arena, accessed with [`Header::lock`] held.
+struct Lists {
+    /// The intrusive list of the stacks released for reuse.
+    free: Option<NonNull<Slot>>,
+    /// The number of the stacks ever handed out, below which every stack has
+    /// its guard page protected.
+    fresh: usize,
+}
+
+/// The bookkeeping data placed right after the usable memory of every stack
+/// handed out, which is also a node of the intrusive free list.
+struct Slot {
+    header: NonNull<Header>,
+    next: Option<NonNull<Slot>>,
+}
+
+/// A stack allocator reserving one memory mapping for a batch of stacks, each
+/// of which is protected by a guard page below it.
+///
+/// Allocating every stack with its own mapping costs a few syscalls each, and
+/// a couple of memory areas in the kernel, which adds up when spinning up
+/// hundreds of coroutines at once. The arena maps the memory of all of them
+/// in a single `mmap` instead, and only protects the guard page of a stack the
+/// first time it's handed out. Released stacks are kept in a free list for
+/// reuse, and the mapping is unmapped once the arena and all of its stacks
+/// are dropped.
+///
+/// The memory is reserved without committing it, so the pages of a stack are
+/// backed only when touched by a coroutine. Requests larger than the stacks of
+/// the arena, or made when all of them are in use, fail.
+///
+/// ```rust
+/// use core::alloc::Layout;
+///
+/// use unico_stack::{ArenaStackAllocator, StackAllocator};
+///
+/// let arena = ArenaStackAllocator::new(16 << 20, 64 << 10).unwrap();
+/// assert!(arena.capacity() > 200);
+///
+/// let layout = Layout::from_size_align(32 << 10, 16).unwrap();
+/// let stacks: Vec<_> = (0..100).map(|_| arena.allocate(layout).unwrap()).collect();
+/// assert_eq!(arena.used(), 100);
+/// drop(stacks);
+/// assert_eq!(arena.used(), 0);
+/// ```
+pub struct ArenaStackAllocator {
+    header: NonNull<Header>,
+}
+
+// SAFETY: The lists are only accessed with `lock` held, and the mapping is
+// owned by the arena and its stacks together.
+unsafe impl Send for ArenaStackAllocator {}
+unsafe impl Sync for ArenaStackAllocator {}
+
+impl ArenaStackAllocator {
+    /// Maps `total_size` bytes of memory, and splits it into as many stacks of
+    /// `stack_size` bytes as possible, each with a guard page.
+    ///
+    /// Both sizes are rounded up to the page size, and the first page holds the
+    /// bookkeeping data of the arena. Every stack reserves a few bytes for its
+    /// own, so the usable size of the stacks is slightly less than
+    /// `stack_size`.
+    pub fn new(total_size: usize, stack_size: usize) -> IoResult<Self> {
+        // SAFETY: `sysconf` has no contract.
+        let page = match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+            page @ 1.. => page as usize,
+            _ => return Err(IoError::last_os_error()),
+        };
+        let too_small =
+            || IoError::new(ErrorKind::InvalidInput, "no stack fits the arena");
+        let stack_size = stack_size
+            .max(mem::size_of::<Slot>() + 1)
+            .checked_next_multiple_of(page)
+            .ok_or_else(too_small)?;
+        let stride = stack_size.checked_add(page).ok_or_else(too_small)?;
+        let count = match total_size.checked_next_multiple_of(page) {
+            Some(len) if len > page => (len - page) / stride,
+            _ => 0,
+        };
+        if count == 0 {
+            return Err(too_small());
+        }
+        let len = page + count * stride;
+
+        // SAFETY: An anonymous mapping has no contract.
+        let mapping = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+                -1,
+                0,
+            )
+        };
+        let mapping = match NonNull::new(mapping) {
+            Some(mapping) if mapping.as_ptr() != libc::MAP_FAILED => mapping,
+            _ => return Err(IoError::last_os_error()),
+        };
+
+        let header = mapping.cast::<Header>();
+        // SAFETY: The header lies in the first page of the mapping, which is
+        // page-aligned.
+        unsafe {
+            header.write(Header {
+                mapping,
+                len,
+                page,
+                stride,
+                count,
+                refs: AtomicUsize::new(1),
+                lock: AtomicBool::new(false),
+                lists: UnsafeCell::new(Lists {
+                    free: None,
+                    fresh: 0,
+                }),
+            })
+        };
+        Ok(ArenaStackAllocator { header })
+    }
+
+    fn header(&self) -> &Header {
+        // SAFETY: The header lives until the last reference is dropped, one of
+        // which is held by `self`.
+        unsafe { self.header.as_ref() }
+    }
+
+    /// The number of the stacks in the arena.
+    pub fn capacity(&self) -> usize {
+        self.header().count
+    }
+
+    /// The number of the stacks handed out by now.
+    pub fn used(&self) -> usize {
+        self.header().refs.load(Relaxed) - 1
+    }
+
+    /// The usable size of every stack.
+    pub fn stack_size(&self) -> usize {
+        self.header().usable()
+    }
+}
+
+impl Header {
+    fn usable(&self) -> usize {
+        (self.stride - self.page - mem::size_of::<Slot>())
+            & !(mem::align_of::<Slot>() - 1)
+    }
+
+    /// The guard page of the stack at `index`, right below its memory.
+    fn guard(&self, index: usize) -> NonNull<u8> {
+        // SAFETY: The stack lies in the mapping after the first page.
+        unsafe {
+            self.mapping
+                .cast::<u8>()
+                .add(self.page + index * self.stride)
+        }
+    }
+
+    fn with_lists<R>(&self, func: impl FnOnce(&mut Lists) -> R) -> R {
+        while (self.lock)
+            .compare_exchange_weak(false, true, Acquire, Relaxed)
+            .is_err()
+        {
+            hint::spin_loop();
+        }
+        // SAFETY: The lists are exclusively accessed with `lock` held.
+        let ret = func(unsafe { &mut *self.lists.get() });
+        self.lock.store(false, Release);
+        ret
+    }
+
+    /// Takes a released stack, or protects the guard page of a fresh one.
+    fn take(&self, header: NonNull<Header>) -> Option<NonNull<Slot>> {
+        self.with_lists(|lists| {
+            if let Some(mut slot) = lists.free {
+                // SAFETY: The slot is in the free list and thus owned by us.
+                lists.free = unsafe { slot.as_mut().next.take() };
+                return Some(slot);
+            }
+            if lists.fresh == self.count {
+                return None;
+            }
+            let guard = self.guard(lists.fresh);
+            // SAFETY: The guard page lies in the mapping, and is never touched by
+            // anyone else.
+            let status = unsafe {
+                libc::mprotect(guard.as_ptr().cast(), self.page, libc::PROT_NONE)
+            };
+            if status != 0 {
+                return None;
+            }
+            lists.fresh += 1;
+            // SAFETY: The slot lies in the memory of the stack, right after the
+            // usable part, and is well-aligned.
+            let slot = unsafe { guard.add(self.page + self.usable()) }.cast::<Slot>();
+            // SAFETY: See above.
+            unsafe { slot.write(Slot { header, next: None }) };
+            Some(slot)
+        })
+    }
+
+    /// # Safety
+    ///
+    /// `this` must be alive and no longer used by the caller.
+    unsafe fn drop_ref(this: NonNull<Header>) {
+        // SAFETY: The header is alive by the contract.
+        let header = unsafe { this.as_ref() };
+        if header.refs.fetch_sub(1, Release) == 1 {
+            fence(Acquire);
+            let (mapping, len) = (header.mapping, header.len);
+            // SAFETY: This was the last reference to the mapping.
+            unsafe { libc::munmap(mapping.as_ptr(), len) };
+        }
+    }
+}
+
+/// # Safety
+///
+/// `pointer` must be a stack handed out by [`ArenaStackAllocator`].
+unsafe fn release(pointer: NonNull<u8>, layout: Layout) {
+    // SAFETY: The slot lies right after the usable memory according to
+    // `Header::take`.
+    let mut slot = unsafe { pointer.add(layout.size()) }.cast::<Slot>();
+    // SAFETY: The slot is initialized in `Header::take`.
+    let this = unsafe { slot.as_ref() }.header;
+    // SAFETY: The header is alive as long as the stack is.
+    let header = unsafe { this.as_ref() };
+    header.with_lists(|lists| {
+        // SAFETY: The slot is exclusively owned by the stack being dropped.
+        unsafe { slot.as_mut().next = lists.free };
+        lists.free = Some(slot);
+    });
+    // SAFETY: The reference of the stack is no longer used.
+    unsafe { Header::drop_ref(this) };
+}
+
+impl Drop for ArenaStackAllocator {
+    fn drop(&mut self) {
+        // SAFETY: The reference of the allocator is no longer used.
+        unsafe { Header::drop_ref(self.header) };
+    }
+}
+
+impl fmt::Debug for ArenaStackAllocator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ArenaStackAllocator")
+            .field("stack_size", &self.stack_size())
+            .field("capacity", &self.capacity())
+            .field("used", &self.used())
+            .finish()
+    }
+}
+
+// SAFETY: Every stack lies in the mapping above its guard page, and is handed
+// out to one user at a time until it's pushed back to the free list.
+unsafe impl StackAllocator for ArenaStackAllocator {
+    fn allocate(&self, layout: Layout) -> Result<Stack, AllocError> {
+        let header = self.header();
+        let size = header.usable();
+        if layout.size() > size || layout.align() > header.page {
+            return Err(AllocError);
+        }
+        let slot = header.take(self.header).ok_or(AllocError)?;
+        header.refs.fetch_add(1, Relaxed);
+
+        // SAFETY: The usable memory lies right below the slot.
+        let base = unsafe { slot.cast::<u8>().sub(size) };
+        let returned =
+            Layout::from_size_align(size, header.page).map_err(|_| AllocError)?;
+        // SAFETY: The memory is exclusively owned by the returned stack until it's
+        // pushed back in `release`.
+        Ok(unsafe { Stack::new(base, returned, release) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+    use std::vec::Vec;
+
+    use super::ArenaStackAllocator;
+    use crate::StackAllocator;
+
+    #[test]
+    fn arena() {
+        let arena = ArenaStackAllocator::new(4096 * 64, 4096 * 4).unwrap();
+        let count = arena.capacity();
+        assert!(count >= 10);
+        assert!(arena.stack_size() < 4096 * 4);
+
+        let layout = Layout::from_size_align(4096, 16).unwrap();
+        let stacks: Vec<_> = (0..count)
+            .map(|_| arena.allocate(layout).unwrap())
+            .collect();
+        assert!(arena.allocate(layout).is_err());
+        for stack in &stacks {
+            let size = stack.layout().size();
+            assert_eq!(size, arena.stack_size());
+            assert_eq!(stack.base().addr().get() % 4096, 0);
+            // The whole usable memory is writable.
+            unsafe { stack.base().write_bytes(0xa5, size) };
+        }
+        let last = stacks[count - 1].base();
+
+        // The released stacks are reused, and outlive the arena.
+        drop(stacks);
+        assert_eq!(arena.used(), 0);
+        let stack = arena.allocate(layout).unwrap();
+        assert_eq!(stack.base(), last);
+        drop(arena);
+        unsafe { stack.base().write_bytes(0, stack.layout().size()) };
+
+        let too_large = Layout::from_size_align(4096 * 4, 16).unwrap();
+        let arena = ArenaStackAllocator::new(4096 * 64, 4096 * 4).unwrap();
+        assert!(arena.allocate(too_large).is_err());
+        assert!(ArenaStackAllocator::new(4096, 4096).is_err());
+    }
+}
@@ -0,0 +1,208 @@
+//! A stack allocator rounding the requests up to some size classes, each of
+//! which is pooled separately.
+
+use core::{
+    alloc::{AllocError, Layout},
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering::Relaxed},
+};
+
+use crate::{Stack, StackAllocator, StackPool};
+
+/// The counters of a size class of [`SizeClassPool`].
+struct Counters {
+    allocations: AtomicUsize,
+    reuses: AtomicUsize,
+}
+
+/// The statistics of a size class of [`SizeClassPool`], returned by
+/// [`SizeClassPool::per_class_stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClassStats {
+    /// The layout of the stacks in this class.
+    pub layout: Layout,
+    /// The number of the stacks handed out from this class so far.
+    pub allocations: usize,
+    /// The number of the allocations served by a cached stack.
+    pub reuses: usize,
+    /// The number of the stacks cached for reuse by now.
+    pub cached: usize,
+}
+
+/// A stack allocator with a [`StackPool`] for each size class, which serves
+/// every request with the smallest class fitting it.
+///
+/// A single pool only reuses the stacks for the requests fitting its layout,
+/// and forwards the larger ones to the underlying allocator every time. With
+/// the requests rounded up to a few classes instead, coroutines requesting
+/// varied sizes still reuse the stacks of each other, without wasting the
+/// largest size on every one of them. Requests larger than every class are
+/// forwarded to the allocator of the largest class.
+///
+/// The classes should be tuned with [`SizeClassPool::per_class_stats`], e.g.
+/// so that the classes with few reuses are merged into their neighbors.
+///
+/// ```rust
+/// use core::alloc::Layout;
+/// use std::alloc::System;
+///
+/// use unico_stack::{SizeClassPool, StackAllocator, StackPool};
+///
+/// const fn class(size: usize) -> StackPool<System> {
+///     match Layout::from_size_align(size, 4096) {
+///         Ok(layout) => StackPool::new(System, layout),
+///         Err(_) => panic!("invalid size class"),
+///     }
+/// }
+///
+/// static POOL: SizeClassPool<System, 3> =
+///     SizeClassPool::new([class(64 << 10), class(256 << 10), class(1 << 20)]);
+/// let pool = &POOL;
+///
+/// let request = Layout::from_size_align(100 << 10, 16).unwrap();
+/// drop(pool.allocate(request).unwrap());
+/// let stack = pool.allocate(request).unwrap();
+/// assert!(stack.layout().size() >= 100 << 10);
+///
+/// let stats = POOL.per_class_stats();
+/// assert_eq!((stats[1].allocations, stats[1].reuses), (2, 1));
+/// ```
+pub struct SizeClassPool<A: 'static, const N: usize> {
+    classes: [StackPool<A>; N],
+    counters: [Counters; N],
+    oversized: AtomicUsize,
+}
+
+impl<A, const N: usize> SizeClassPool<A, N> {
+    /// Creates a pool with a size class for each of `classes`, in any order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there's no class at all.
+    pub const fn new(classes: [StackPool<A>; N]) -> Self {
+        assert!(N > 0, "no size class in the pool");
+        SizeClassPool {
+            classes,
+            counters: [const {
+                Counters {
+                    allocations: AtomicUsize::new(0),
+                    reuses: AtomicUsize::new(0),
+                }
+            }; N],
+            oversized: AtomicUsize::new(0),
+        }
+    }
+
+    /// The pools of the size classes, in the order they're given.
+    pub fn classes(&self) -> &[StackPool<A>; N] {
+        &self.classes
+    }
+
+    /// The statistics of the size classes, in the order they're given.
+    pub fn per_class_stats(&self) -> [ClassStats; N] {
+        core::array::from_fn(|index| {
+            let counters = &self.counters[index];
+            ClassStats {
+                layout: self.classes[index].layout(),
+                allocations: counters.allocations.load(Relaxed),
+                reuses: counters.reuses.load(Relaxed),
+                cached: self.classes[index].cached(),
+            }
+        })
+    }
+
+    /// The number of the requests larger than every class so far, which are
+    /// not pooled.
+    pub fn oversized(&self) -> usize {
+        self.oversized.load(Relaxed)
+    }
+
+    /// The index of the smallest class fitting `layout`.
+    fn class_of(&self, layout: Layout) -> Option<usize> {
+        (0..N)
+            .filter(|index| self.classes[*index].fits(layout))
+            .min_by_key(|index| self.classes[*index].layout().size())
+    }
+
+    /// The index of the largest class.
+    fn largest(&self) -> usize {
+        (0..N)
+            .max_by_key(|index| self.classes[*index].layout().size())
+            .unwrap()
+    }
+}
+
+impl<A: fmt::Debug, const N: usize> fmt::Debug for SizeClassPool<A, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SizeClassPool")
+            .field("classes", &self.per_class_stats())
+            .field("oversized", &self.oversized())
+            .finish()
+    }
+}
+
+// SAFETY: The stacks are allocated by the pools of the classes.
+unsafe impl<A: StackAllocator, const N: usize> StackAllocator
+    for &'static SizeClassPool<A, N>
+{
+    fn allocate(&self, layout: Layout) -> Result<Stack, AllocError> {
+        let Some(index) = self.class_of(layout) else {
+            self.oversized.fetch_add(1, Relaxed);
+            return self.classes[self.largest()].alloc().allocate(layout);
+        };
+
+        let (stack, reused) = self.classes[index].allocate_reused(layout)?;
+        let counters = &self.counters[index];
+        counters.allocations.fetch_add(1, Relaxed);
+        if reused {
+            counters.reuses.fetch_add(1, Relaxed);
+        }
+        Ok(stack)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::alloc::Layout;
+    use std::alloc::System;
+
+    use super::SizeClassPool;
+    use crate::{StackAllocator, StackPool};
+
+    const fn class(size: usize) -> StackPool<System> {
+        match Layout::from_size_align(size, 4096) {
+            Ok(layout) => StackPool::new(System, layout),
+            Err(_) => panic!("invalid size class"),
+        }
+    }
+
+    #[test]
+    fn size_classes() {
+        static POOL: SizeClassPool<System, 3> =
+            SizeClassPool::new([class(1 << 20), class(64 << 10), class(256 << 10)]);
+        let pool = &POOL;
+
+        let small = Layout::from_size_align(4096, 16).unwrap();
+        let medium = Layout::from_size_align(100 << 10, 16).unwrap();
+        drop(pool.allocate(small).unwrap());
+        drop(pool.allocate(medium).unwrap());
+        // Rounded up to the same class, and reused.
+        let medium = Layout::from_size_align(200 << 10, 16).unwrap();
+        let stack = pool.allocate(medium).unwrap();
+        assert!(stack.layout().size() >= 200 << 10);
+        assert!(stack.layout().size() < 256 << 10);
+        drop(stack);
+
+        let stats = pool.per_class_stats();
+        let counts = stats.map(|stats| (stats.allocations, stats.reuses, stats.cached));
+        assert_eq!(counts, [(0, 0, 0), (1, 0, 1), (2, 1, 1)]);
+
+        // Requests larger than every class are not pooled.
+        let large = Layout::from_size_align(2 << 20, 16).unwrap();
+        drop(pool.allocate(large).unwrap());
+        assert_eq!(pool.oversized(), 1);
+        assert_eq!(pool.classes()[0].cached(), 0);
+    }
+}
@@ -0,0 +1,133 @@
+//! Stack allocators dedicated to some subsystems apart from the global one.
+
+use core::{
+    alloc::{AllocError, Layout},
+    fmt,
+};
+
+use crate::{IntoStack, Stack, StackAllocator, StackPool, DEFAULT_LAYOUT};
+
+/// A handle to the stack allocator of some subsystem, which the coroutines of
+/// the subsystem draw their stacks from instead of the global allocator.
+///
+/// Every domain allocates its stacks with its own layout, so subsystems sharing
+/// a process can have their own stack sizes and pools without interfering with
+/// each other. Code that doesn't specify a domain keeps using the global stack
+/// allocator.
+///
+/// ```rust
+/// use std::alloc::System;
+///
+/// use unico_stack::{Stack, StackDomain, StackPool, DEFAULT_LAYOUT};
+///
+/// static POOL_A: StackPool<System> = StackPool::new(System, DEFAULT_LAYOUT);
+/// static DOMAIN_A: StackDomain<&StackPool<System>> = StackDomain::pooled(&POOL_A);
+///
+/// drop(Stack::from(&DOMAIN_A));
+/// assert_eq!(POOL_A.cached(), 1);
+/// ```
+pub struct StackDomain<A> {
+    alloc: A,
+    layout: Layout,
+}
+
+impl<A> StackDomain<A> {
+    /// Creates a domain allocating stacks of [`DEFAULT_LAYOUT`] from `alloc`.
+    pub const fn new(alloc: A) -> Self {
+        Self::with_layout(alloc, DEFAULT_LAYOUT)
+    }
+
+    /// Creates a domain allocating stacks of `layout` from `alloc`.
+    pub const fn with_layout(alloc: A, layout: Layout) -> Self {
+        StackDomain { alloc, layout }
+    }
+
+    /// The layout requested for every stack allocated in this domain.
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+}
+
+impl<A: 'static> StackDomain<&'static StackPool<A>> {
+    /// Creates a domain reusing the stacks of `pool`.
+    ///
+    /// Since the pooled stacks are handed out as a whole, the domain requests
+    /// nothing beyond the alignment of the pool, so that no request falls back
+    /// to the underlying allocator of the pool.
+    pub const fn pooled(pool: &'static StackPool<A>) -> Self {
+        // SAFETY: The alignment is the one of a valid layout.
+        let layout = unsafe { Layout::from_size_align_unchecked(0, pool.align()) };
+        Self::with_layout(pool, layout)
+    }
+}
+
+impl<A: StackAllocator> StackDomain<A> {
+    /// Allocates a stack in this domain.
+    pub fn allocate(&self) -> Result<Stack, AllocError> {
+        self.alloc.allocate(self.layout)
+    }
+}
+
+impl<A: StackAllocator> IntoStack for &StackDomain<A> {
+    fn into_stack(self) -> Result<Stack, AllocError> {
+        self.allocate()
+    }
+}
+
+impl<A: StackAllocator> From<&StackDomain<A>> for Stack {
+    fn from(domain: &StackDomain<A>) -> Self {
+        match domain.allocate() {
+            Ok(stack) => stack,
+            Err(_) => panic!(
+                "failed to allocate in the domain with layout {:?}",
+                domain.layout
+            ),
+        }
+    }
+}
+
+impl<A: fmt::Debug> fmt::Debug for StackDomain<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StackDomain")
+            .field("alloc", &self.alloc)
+            .field("layout", &self.layout)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::alloc::Layout;
+    use std::alloc::System;
+
+    use super::StackDomain;
+    use crate::{Stack, StackPool};
+
+    #[test]
+    fn isolated() {
+        const SMALL: Layout = match Layout::from_size_align(4096 * 4, 4096) {
+            Ok(layout) => layout,
+            Err(_) => panic!(),
+        };
+        const LARGE: Layout = match Layout::from_size_align(4096 * 16, 4096) {
+            Ok(layout) => layout,
+            Err(_) => panic!(),
+        };
+        static POOL_A: StackPool<System> = StackPool::new(System, SMALL);
+        static POOL_B: StackPool<System> = StackPool::new(System, LARGE);
+        static A: StackDomain<&StackPool<System>> = StackDomain::pooled(&POOL_A);
+        static B: StackDomain<&StackPool<System>> = StackDomain::pooled(&POOL_B);
+
+        let a = Stack::from(&A);
+        let b = Stack::from(&B);
+        assert!(a.layout().size() < SMALL.size());
+        assert!(b.layout().size() > SMALL.size());
+
+        drop(a);
+        assert_eq!((POOL_A.cached(), POOL_B.cached()), (1, 0));
+        drop(b);
+        assert_eq!((POOL_A.cached(), POOL_B.cached()), (1, 1));
+    }
+}
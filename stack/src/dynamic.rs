@@ -0,0 +1,81 @@
+use core::alloc::{AllocError, Layout};
+use std::{boxed::Box, sync::OnceLock};
+
+use crate::{Stack, StackAllocator};
+
+/// A stack allocator chosen at runtime.
+pub type DynStackAllocator = Box<dyn StackAllocator + Send + Sync>;
+
+static DYNAMIC: OnceLock<DynStackAllocator> = OnceLock::new();
+
+/// Sets the stack allocator used by [`Dynamic`].
+///
+/// The allocator can be set only once, so that the stacks allocated by it are
+/// always able to be dropped. If some allocator is already set, `alloc` is
+/// given back in the error.
+pub fn set_global_stack_allocator(
+    alloc: DynStackAllocator,
+) -> Result<(), DynStackAllocator> {
+    DYNAMIC.set(alloc)
+}
+
+/// The stack allocator set by [`set_global_stack_allocator`].
+///
+/// It can be used directly, or registered as the global stack allocator so
+/// that the allocator can be chosen at startup, e.g. from the configuration:
+///
+/// ```rust
+/// # #![feature(allocator_api)]
+/// use std::alloc::System;
+///
+/// use unico_stack::{
+///     global_stack_allocator, set_global_stack_allocator, Dynamic, StackPool,
+///     DEFAULT_LAYOUT,
+/// };
+///
+/// global_stack_allocator!(Dynamic);
+///
+/// static POOL: StackPool<System> = StackPool::new(System, DEFAULT_LAYOUT);
+///
+/// let pooled = std::env::var_os("USE_STACK_POOL").is_some();
+/// let _ = set_global_stack_allocator(if pooled {
+///     Box::new(&POOL)
+/// } else {
+///     Box::new(System)
+/// });
+/// ```
+///
+/// Allocations fail before any allocator is set. Compared to registering a
+/// concrete allocator to [`global_stack_allocator`], every allocation costs an
+/// extra atomic load and a dynamic dispatch.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Dynamic;
+
+// SAFETY: The stack is allocated by the allocator set above, which is never
+// dropped.
+unsafe impl StackAllocator for Dynamic {
+    fn allocate(&self, layout: Layout) -> Result<Stack, AllocError> {
+        DYNAMIC.get().ok_or(AllocError)?.allocate(layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+    use std::{alloc::System, boxed::Box};
+
+    use super::{set_global_stack_allocator, Dynamic};
+    use crate::StackAllocator;
+
+    #[test]
+    fn dynamic() {
+        let layout = Layout::from_size_align(4096, 16).unwrap();
+        assert!(Dynamic.allocate(layout).is_err());
+
+        assert!(set_global_stack_allocator(Box::new(System)).is_ok());
+        assert!(set_global_stack_allocator(Box::new(System)).is_err());
+
+        let stack = Dynamic.allocate(layout).unwrap();
+        assert_eq!(stack.layout().align(), 16);
+    }
+}
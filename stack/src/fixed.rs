@@ -0,0 +1,308 @@
+//! Stack allocators on top of fixed blocks of memory, which require no dynamic
+//! allocation at all.
+//!
+//! Every stack handed out by these allocators has its occupancy flag placed
+//! right after its usable memory, so that the flag can be found and cleared by
+//! the dropper of [`Stack`] without any reference to the allocator.
+
+use core::{
+    alloc::{AllocError, Layout},
+    cell::UnsafeCell,
+    fmt, mem,
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, Ordering::*},
+};
+
+use crate::{IntoStack, Stack, StackAllocator};
+
+/// The alignment of [`StaticStack`].
+pub const STATIC_STACK_ALIGN: usize = 4096;
+
+/// # Safety
+///
+/// `pointer` must be a stack handed out by [`acquire`].
+unsafe fn release(pointer: NonNull<u8>, layout: Layout) {
+    // SAFETY: The flag lies right after the usable memory according to `acquire`.
+    let flag = unsafe { &*pointer.as_ptr().add(layout.size()).cast::<AtomicBool>() };
+    flag.store(false, Release);
+}
+
+/// # Safety
+///
+/// `memory` must point to a valid block of `size` bytes followed by a flag,
+/// which both live for `'static`.
+unsafe fn acquire(
+    memory: NonNull<u8>,
+    size: usize,
+    flag: &'static AtomicBool,
+    layout: Layout,
+    align: usize,
+) -> Option<Stack> {
+    if layout.size() > size || layout.align() > align {
+        return None;
+    }
+    flag.compare_exchange(false, true, Acquire, Relaxed).ok()?;
+    let returned = Layout::from_size_align(size, align).ok()?;
+    // SAFETY: The memory is valid by contract, and is exclusively owned by the
+    // returned stack until the flag is cleared in `release`.
+    Some(unsafe { Stack::new(memory, returned, release) })
+}
+
+/// A single stack of `N` bytes, which can be placed in a `static` item.
+///
+/// This structure implements [`StackAllocator`], which hands out its memory if
+/// it's not in use.
+///
+/// A reference of it can be directly converted into a [`Stack`], or used as a
+/// global stack allocator through another `static` item:
+///
+/// ```rust
+/// use unico_stack::{global_stack_allocator, Stack, StaticStack};
+///
+/// static STACK: StaticStack<{ 4096 * 6 }> = StaticStack::new();
+/// static STACK_REF: &StaticStack<{ 4096 * 6 }> = &STACK;
+/// global_stack_allocator!(STACK_REF);
+///
+/// let stack = Stack::from(&STACK);
+/// assert!(STACK.is_used());
+/// drop(stack);
+/// assert!(!STACK.is_used());
+/// ```
+#[repr(C, align(4096))]
+pub struct StaticStack<const N: usize> {
+    memory: UnsafeCell<[u8; N]>,
+    // This field must be placed right after `memory`.
+    used: AtomicBool,
+}
+
+// SAFETY: The memory is only accessed through the stack exclusively acquired
+// with `used`.
+unsafe impl<const N: usize> Sync for StaticStack<N> {}
+
+impl<const N: usize> StaticStack<N> {
+    /// Creates a new stack of `N` bytes.
+    pub const fn new() -> Self {
+        StaticStack {
+            memory: UnsafeCell::new([0; N]),
+            used: AtomicBool::new(false),
+        }
+    }
+
+    /// Whether the stack is handed out by now.
+    pub fn is_used(&self) -> bool {
+        self.used.load(Relaxed)
+    }
+}
+
+impl<const N: usize> Default for StaticStack<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> fmt::Debug for StaticStack<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StaticStack")
+            .field("size", &N)
+            .field("used", &self.is_used())
+            .finish()
+    }
+}
+
+// SAFETY: See `acquire` for more information.
+unsafe impl<const N: usize> StackAllocator for &'static StaticStack<N> {
+    fn allocate(&self, layout: Layout) -> Result<Stack, AllocError> {
+        let memory = NonNull::new(self.memory.get().cast()).ok_or(AllocError)?;
+        // SAFETY: `self` is `'static`, and `used` is placed right after `memory`.
+        unsafe { acquire(memory, N, &self.used, layout, STATIC_STACK_ALIGN) }
+            .ok_or(AllocError)
+    }
+}
+
+impl<const N: usize> IntoStack for &'static StaticStack<N> {
+    fn into_stack(self) -> Result<Stack, AllocError> {
+        let layout = Layout::from_size_align(N, STATIC_STACK_ALIGN).unwrap();
+        self.allocate(layout)
+    }
+}
+
+impl<const N: usize> From<&'static StaticStack<N>> for Stack {
+    fn from(stack: &'static StaticStack<N>) -> Self {
+        match stack.into_stack() {
+            Ok(stack) => stack,
+            Err(_) => panic!("the static stack is already in use"),
+        }
+    }
+}
+
+/// A stack allocator handing out fixed-size slots of a user-provided region of
+/// memory.
+///
+/// Note that the slots are not protected by guard pages, so an overflowing
+/// stack will silently corrupt its neighbor.
+pub struct StackRegion {
+    base: NonNull<u8>,
+    stride: usize,
+    size: usize,
+    align: usize,
+    count: usize,
+}
+
+// SAFETY: The slots are only accessed through the stacks exclusively acquired
+// with their flags.
+unsafe impl Send for StackRegion {}
+unsafe impl Sync for StackRegion {}
+
+impl StackRegion {
+    /// Splits `region` into as many slots of `slot` as possible.
+    ///
+    /// Every slot reserves a few bytes for its occupancy flag, so the usable
+    /// size of the stacks is slightly less than `slot.size()`.
+    pub fn new(region: &'static mut [u8], slot: Layout) -> Self {
+        let stride = slot.pad_to_align().size();
+        let size = stride.saturating_sub(mem::size_of::<AtomicBool>());
+        let offset = region.as_ptr().align_offset(slot.align());
+        let count = match (region.len().checked_sub(offset), size) {
+            (Some(_), 0) | (None, _) => 0,
+            (Some(len), _) => len / stride,
+        };
+
+        let base = NonNull::from(region).cast::<u8>();
+        // SAFETY: `offset` is within the region, or `count` is zero.
+        let base = unsafe { base.add(offset.min(count * stride)) };
+        let this = StackRegion {
+            base,
+            stride,
+            size,
+            align: slot.align(),
+            count,
+        };
+        for index in 0..count {
+            // SAFETY: The flag lies in the region, and `AtomicBool` has no
+            // alignment requirements.
+            unsafe { this.flag_ptr(index).write(AtomicBool::new(false)) };
+        }
+        this
+    }
+
+    /// The number of the slots in the region.
+    pub fn capacity(&self) -> usize {
+        self.count
+    }
+
+    /// The number of the slots handed out by now.
+    pub fn used(&self) -> usize {
+        (0..self.count)
+            .filter(|&index| self.flag(index).load(Relaxed))
+            .count()
+    }
+
+    fn slot(&self, index: usize) -> NonNull<u8> {
+        // SAFETY: The slot lies in the region.
+        unsafe { self.base.add(index * self.stride) }
+    }
+
+    fn flag_ptr(&self, index: usize) -> NonNull<AtomicBool> {
+        // SAFETY: The flag lies in the region, right after the usable memory.
+        unsafe { self.slot(index).add(self.size).cast() }
+    }
+
+    fn flag(&self, index: usize) -> &'static AtomicBool {
+        // SAFETY: The region is `'static`, and the flag is initialized in
+        // `StackRegion::new`.
+        unsafe { self.flag_ptr(index).as_ref() }
+    }
+}
+
+impl fmt::Debug for StackRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StackRegion")
+            .field("base", &self.base)
+            .field("size", &self.size)
+            .field("align", &self.align)
+            .field("capacity", &self.count)
+            .finish()
+    }
+}
+
+// SAFETY: See `acquire` for more information.
+unsafe impl StackAllocator for StackRegion {
+    fn allocate(&self, layout: Layout) -> Result<Stack, AllocError> {
+        (0..self.count)
+            .find_map(|index| {
+                let (slot, flag) = (self.slot(index), self.flag(index));
+                // SAFETY: The slot and its flag lie in the `'static` region.
+                unsafe { acquire(slot, self.size, flag, layout, self.align) }
+            })
+            .ok_or(AllocError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::alloc::Layout;
+    use std::boxed::Box;
+
+    use super::{StackRegion, StaticStack};
+    use crate::StackAllocator;
+
+    #[test]
+    fn static_stack() {
+        static STACK: StaticStack<8192> = StaticStack::new();
+
+        let layout = Layout::from_size_align(4096, 16).unwrap();
+        let stack = (&STACK).allocate(layout).unwrap();
+        assert!(STACK.is_used());
+        assert!((&STACK).allocate(layout).is_err());
+        assert_eq!(stack.layout().size(), 8192);
+
+        drop(stack);
+        assert!(!STACK.is_used());
+        let too_large = Layout::from_size_align(8193, 16).unwrap();
+        assert!((&STACK).allocate(too_large).is_err());
+    }
+
+    #[test]
+    fn region() {
+        let memory = Box::leak(Box::new([0u8; 4096 * 4 + 100]));
+        let slot = Layout::from_size_align(4096, 64).unwrap();
+        let region = StackRegion::new(memory, slot);
+        assert!(region.capacity() >= 3);
+
+        let request = Layout::from_size_align(2048, 16).unwrap();
+        let stacks: [_; 3] = core::array::from_fn(|_| region.allocate(request).unwrap());
+        assert_eq!(region.used(), 3);
+        for stack in &stacks {
+            assert_eq!(stack.base().as_ptr() as usize % 64, 0);
+            assert!(stack.layout().size() >= 2048);
+        }
+
+        drop(stacks);
+        assert_eq!(region.used(), 0);
+        assert!(region
+            .allocate(Layout::from_size_align(4096, 16).unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn prefault() {
+        let memory = Box::leak(Box::new([0u8; 4096 * 3]));
+        memory
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, byte)| *byte = i as u8);
+        let region =
+            StackRegion::new(memory, Layout::from_size_align(4096 * 3, 16).unwrap());
+
+        let stack = region
+            .allocate(Layout::from_size_align(4096, 16).unwrap())
+            .unwrap();
+        stack.prefault();
+        let len = stack.layout().size();
+        // SAFETY: The memory is owned by the stack.
+        let bytes = unsafe { core::slice::from_raw_parts(stack.base().as_ptr(), len) };
+        assert!(bytes.iter().enumerate().all(|(i, &byte)| byte == i as u8));
+    }
+}
@@ -5,6 +5,7 @@
 #![deny(trivial_casts)]
 #![deny(trivial_numeric_casts)]
 #![allow(internal_features)]
+#![cfg_attr(feature = "default-global-stack", feature(linkage))]
 #![feature(allocator_api)]
 #![feature(allow_internal_unstable)]
 #![feature(slice_ptr_get)]
@@ -14,16 +15,43 @@
 //! We have [a stack structure](Stack) that keep track of its own memory, and
 //! a trait represents [a stack allocator](StackAllocator).
 
+#[cfg(feature = "mmap")]
+mod arena;
+mod class;
+mod domain;
+#[cfg(feature = "std")]
+mod dynamic;
+mod fixed;
+mod pool;
+mod secure;
+#[cfg(feature = "signal")]
+mod signal;
+
 use core::{
     alloc::{AllocError, Allocator, Layout},
     mem::{self, MaybeUninit},
     ptr::NonNull,
 };
 
+#[cfg(feature = "mmap")]
+pub use crate::arena::*;
+#[cfg(feature = "std")]
+pub use crate::dynamic::*;
+#[cfg(feature = "signal")]
+pub use crate::signal::*;
+pub use crate::{class::*, domain::*, fixed::*, pool::*, secure::*};
+
+#[cfg(feature = "std")]
+extern crate std;
+
 // SAFETY: The alignment is a power of 2.
 pub const DEFAULT_LAYOUT: Layout =
     unsafe { Layout::from_size_align_unchecked(4096 * 6, 4096) };
 
+/// The stride of [`Stack::prefault`], which is the smallest page size among
+/// the common platforms.
+const PREFAULT_STRIDE: usize = 4096;
+
 /// The raw stack structure.
 ///
 /// The structure owns its memory, and drops the memory if it's dropped. The
@@ -40,7 +68,8 @@ impl Stack {
     /// # Safety
     ///
     /// `pointer` must contains a valid block of memory of `layout`, and must be
-    /// able to be dropped by `drop`.
+    /// able to be dropped by `drop`. `pointer` should be aligned to
+    /// `layout.align()`, or the stack will be rejected by coroutines.
     pub unsafe fn new(
         pointer: NonNull<u8>,
         layout: Layout,
@@ -62,6 +91,27 @@ impl Stack {
     pub fn layout(&self) -> Layout {
         self.layout
     }
+
+    /// Touches every page of the stack so that the memory is committed before
+    /// any coroutine runs on it.
+    ///
+    /// Freshly mapped memory is usually committed lazily, so the first deep
+    /// call in a coroutine may hit a series of page faults in the middle of
+    /// its work. Prefaulting moves this cost to the creation instead, which
+    /// makes the latency of the coroutine itself more predictable.
+    ///
+    /// The content of the stack is kept intact.
+    pub fn prefault(&self) {
+        let base = self.pointer.as_ptr();
+        for offset in (0..self.layout.size()).step_by(PREFAULT_STRIDE) {
+            // SAFETY: The byte lies in the memory owned by the stack, which is not
+            // `Sync` and thus not touched by other threads meanwhile.
+            unsafe {
+                let byte = base.add(offset);
+                byte.write_volatile(byte.read_volatile());
+            }
+        }
+    }
 }
 
 impl Drop for Stack {
@@ -89,21 +139,61 @@ impl From<Layout> for Stack {
     }
 }
 
-impl<A: StackAllocator> From<&A> for Stack {
+impl<A: StackAllocator + ?Sized> From<&A> for Stack {
     fn from(alloc: &A) -> Self {
         Self::from((alloc, DEFAULT_LAYOUT))
     }
 }
 
-impl<A: StackAllocator> From<(&A, Layout)> for Stack {
+impl<A: StackAllocator + ?Sized> From<(&A, Layout)> for Stack {
     fn from((alloc, layout): (&A, Layout)) -> Self {
-        match alloc.allocate(layout) {
+        match (alloc, layout).into_stack() {
             Ok(stack) => stack,
             Err(_) => panic!("failed to allocate with layout {layout:?}"),
         }
     }
 }
 
+/// The sources of a [`Stack`], which allocate it on demand and may fail to.
+///
+/// Coroutines are built from these sources, so that running out of memory is
+/// reported as an error instead of a panic. The [`From`] implementations of
+/// [`Stack`] on the same sources panic instead.
+pub trait IntoStack {
+    /// Allocates the stack, if not yet.
+    fn into_stack(self) -> Result<Stack, AllocError>;
+}
+
+impl IntoStack for Stack {
+    fn into_stack(self) -> Result<Stack, AllocError> {
+        Ok(self)
+    }
+}
+
+impl IntoStack for () {
+    fn into_stack(self) -> Result<Stack, AllocError> {
+        DEFAULT_LAYOUT.into_stack()
+    }
+}
+
+impl IntoStack for Layout {
+    fn into_stack(self) -> Result<Stack, AllocError> {
+        (&Global, self).into_stack()
+    }
+}
+
+impl<A: StackAllocator + ?Sized> IntoStack for &A {
+    fn into_stack(self) -> Result<Stack, AllocError> {
+        (self, DEFAULT_LAYOUT).into_stack()
+    }
+}
+
+impl<A: StackAllocator + ?Sized> IntoStack for (&A, Layout) {
+    fn into_stack(self) -> Result<Stack, AllocError> {
+        self.0.allocate(self.1)
+    }
+}
+
 /// Generic stack allocators.
 ///
 /// # Safety
@@ -112,6 +202,11 @@ impl<A: StackAllocator> From<(&A, Layout)> for Stack {
 /// function should be satisfied by the implementor, ***EXCEPT*** that the
 /// actual layout returned can be slightly different than the requested layout
 /// with extra allocated space containing the dropper itself.
+///
+/// The requested alignment must be honored: the returned layout must be
+/// aligned to at least `layout.align()`, and so must be the base of the stack.
+/// Coroutines refuse to be created on a stack whose base doesn't match the
+/// alignment of its layout.
 pub unsafe trait StackAllocator {
     /// Allocate a block of memory of `layout` as the returned stack alongside
     /// with its deallocator/dropper/destructor.
@@ -232,11 +327,19 @@ unsafe impl StackAllocator for Global {
 /// This macro works just like `#[global_allocator]` attribute, except it only
 /// receives the path of the target static variable, while the actual definition
 /// can lie elsewhere.
+///
+/// With the `default-global-stack` feature, the global heap allocator is
+/// installed as a weak default, which is overridden by any stack allocator
+/// defined with this macro.
 #[allow_internal_unstable(allocator_api)]
 #[macro_export]
 macro_rules! global_stack_allocator {
     ($name:path) => {
-        #[no_mangle]
+        $crate::global_stack_allocator!(@attrs [] $name);
+    };
+    (@attrs [$(#[$attr:meta])*] $name:path) => {
+        $(#[$attr])*
+        #[unsafe(no_mangle)]
         #[doc(hidden)]
         unsafe fn __rust_unico_allocate_stack(
             layout: core::alloc::Layout,
@@ -245,3 +348,13 @@ macro_rules! global_stack_allocator {
         }
     };
 }
+
+#[cfg(feature = "default-global-stack")]
+extern crate alloc;
+
+/// The weak default of the global stack allocator, in a module apart from the
+/// declaration of the symbol.
+#[cfg(feature = "default-global-stack")]
+mod fallback {
+    crate::global_stack_allocator!(@attrs [#[linkage = "weak"]] alloc::alloc::Global);
+}
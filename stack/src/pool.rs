@@ -0,0 +1,288 @@
+//! A stack allocator caching the stacks released by its users.
+
+use core::{
+    alloc::{AllocError, Layout},
+    cell::UnsafeCell,
+    fmt, hint, mem,
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering::*},
+};
+
+use crate::{Stack, StackAllocator};
+
+/// The bookkeeping data placed right after the usable memory of every pooled
+/// stack, which is also a node of the intrusive free list.
+struct Slot<A: 'static> {
+    pool: &'static StackPool<A>,
+    inner: Stack,
+    next: Option<NonNull<Slot<A>>>,
+}
+
+/// A stack allocator that keeps the stacks of some fixed layout for reuse
+/// instead of deallocating them.
+///
+/// The pool should be placed in a `static` item, since every stack handed out
+/// keeps a reference to it. Stacks are allocated from the underlying allocator
+/// on demand, or in advance with [`StackPool::prewarm`] to avoid the latency
+/// of the first allocations. Requests that don't fit in the layout of the pool
+/// are forwarded to the underlying allocator directly.
+///
+/// ```rust
+/// use std::alloc::System;
+///
+/// use unico_stack::{global_stack_allocator, StackPool, DEFAULT_LAYOUT};
+///
+/// static POOL: StackPool<System> = StackPool::new(System, DEFAULT_LAYOUT);
+/// static POOL_REF: &StackPool<System> = &POOL;
+/// global_stack_allocator!(POOL_REF);
+///
+/// POOL.prewarm(16).unwrap();
+/// assert_eq!(POOL.cached(), 16);
+/// ```
+pub struct StackPool<A: 'static> {
+    alloc: A,
+    layout: Layout,
+    lock: AtomicBool,
+    free: UnsafeCell<Option<NonNull<Slot<A>>>>,
+    cached: AtomicUsize,
+}
+
+// SAFETY: The free list is only accessed with `lock` held, and the cached
+// stacks are not shared with anyone else.
+unsafe impl<A: Send> Send for StackPool<A> {}
+unsafe impl<A: Sync> Sync for StackPool<A> {}
+
+impl<A> StackPool<A> {
+    /// Creates an empty pool of stacks allocated from `alloc` with `layout`.
+    pub const fn new(alloc: A, layout: Layout) -> Self {
+        StackPool {
+            alloc,
+            layout,
+            lock: AtomicBool::new(false),
+            free: UnsafeCell::new(None),
+            cached: AtomicUsize::new(0),
+        }
+    }
+
+    /// The layout of the stacks allocated from the underlying allocator.
+    ///
+    /// The usable size of the stacks handed out is slightly less than it.
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// The underlying allocator.
+    pub(crate) fn alloc(&self) -> &A {
+        &self.alloc
+    }
+
+    pub(crate) const fn align(&self) -> usize {
+        self.layout.align()
+    }
+
+    /// Whether the stacks of this pool fit `layout`, judging from the layout
+    /// they're allocated with.
+    pub(crate) fn fits(&self, layout: Layout) -> bool {
+        let size = (self.layout.size()).saturating_sub(mem::size_of::<Slot<A>>())
+            & !(mem::align_of::<Slot<A>>() - 1);
+        layout.size() <= size && layout.align() <= self.layout.align()
+    }
+
+    /// The number of the stacks cached for reuse by now.
+    pub fn cached(&self) -> usize {
+        self.cached.load(Relaxed)
+    }
+
+    fn with_free<R>(&self, func: impl FnOnce(&mut Option<NonNull<Slot<A>>>) -> R) -> R {
+        let lock = &self.lock;
+        while lock
+            .compare_exchange_weak(false, true, Acquire, Relaxed)
+            .is_err()
+        {
+            hint::spin_loop();
+        }
+        // SAFETY: The free list is exclusively accessed with `lock` held.
+        let ret = func(unsafe { &mut *self.free.get() });
+        self.lock.store(false, Release);
+        ret
+    }
+
+    fn push(&self, mut slot: NonNull<Slot<A>>) {
+        self.with_free(|free| {
+            // SAFETY: The slot is released and thus exclusively owned by us.
+            unsafe { slot.as_mut().next = free.take() };
+            *free = Some(slot);
+        });
+        self.cached.fetch_add(1, Relaxed);
+    }
+
+    fn pop(&self) -> Option<NonNull<Slot<A>>> {
+        let slot = self.with_free(|free| {
+            let mut slot = (*free)?;
+            // SAFETY: The slot is in the free list and thus owned by us.
+            *free = unsafe { slot.as_mut().next.take() };
+            Some(slot)
+        })?;
+        self.cached.fetch_sub(1, Relaxed);
+        Some(slot)
+    }
+}
+
+impl<A: StackAllocator> StackPool<A> {
+    /// Allocates `count` stacks in advance and caches them for reuse.
+    ///
+    /// Every page of the stacks is touched so that the memory is committed by
+    /// the operating system before any coroutine runs on them.
+    pub fn prewarm(&'static self, count: usize) -> Result<(), AllocError> {
+        for _ in 0..count {
+            let stack = self.hand_out(self.new_slot()?)?;
+            stack.prefault();
+            // The stack is pushed back to the free list.
+            drop(stack);
+        }
+        Ok(())
+    }
+
+    fn new_slot(&'static self) -> Result<NonNull<Slot<A>>, AllocError> {
+        let inner = self.alloc.allocate(self.layout)?;
+        let (base, layout) = (inner.base(), inner.layout());
+        if layout.align() < mem::align_of::<Slot<A>>() {
+            return Err(AllocError);
+        }
+        let size = (layout.size())
+            .checked_sub(mem::size_of::<Slot<A>>())
+            .ok_or(AllocError)?
+            & !(mem::align_of::<Slot<A>>() - 1);
+
+        // SAFETY: The slot lies in the memory of `inner` and is well-aligned.
+        let slot = unsafe { base.add(size) }.cast::<Slot<A>>();
+        // SAFETY: See above.
+        unsafe {
+            slot.write(Slot {
+                pool: self,
+                inner,
+                next: None,
+            })
+        };
+        Ok(slot)
+    }
+
+    /// Hands out a stack fitting `layout`, and tells whether it's reused from
+    /// the cache.
+    pub(crate) fn allocate_reused(
+        &'static self,
+        layout: Layout,
+    ) -> Result<(Stack, bool), AllocError> {
+        // Checked first, so that no pooled stack is taken or created in vain.
+        if !self.fits(layout) {
+            return Ok((self.alloc.allocate(layout)?, false));
+        }
+        let (slot, reused) = match self.pop() {
+            Some(slot) => (slot, true),
+            None => (self.new_slot()?, false),
+        };
+        Ok((self.hand_out(slot)?, reused))
+    }
+
+    /// Turns the usable memory of `slot` into a stack, which pushes the slot
+    /// back to the free list when dropped.
+    fn hand_out(&'static self, slot: NonNull<Slot<A>>) -> Result<Stack, AllocError> {
+        let (base, size) = Self::usable(slot);
+        // SAFETY: The slot is initialized in `new_slot`.
+        let align = unsafe { slot.as_ref() }.inner.layout().align();
+        let returned = Layout::from_size_align(size, align).map_err(|_| AllocError)?;
+        // SAFETY: The memory is exclusively owned by the returned stack until it's
+        // pushed back in `release`.
+        Ok(unsafe { Stack::new(base, returned, release::<A>) })
+    }
+
+    /// The usable memory of the stack where `slot` lies.
+    fn usable(slot: NonNull<Slot<A>>) -> (NonNull<u8>, usize) {
+        // SAFETY: The slot is initialized in `new_slot`.
+        let base = unsafe { slot.as_ref() }.inner.base();
+        (base, slot.addr().get() - base.addr().get())
+    }
+}
+
+/// # Safety
+///
+/// `pointer` must be a stack handed out by [`StackPool`].
+unsafe fn release<A: 'static>(pointer: NonNull<u8>, layout: Layout) {
+    // SAFETY: The slot lies right after the usable memory according to
+    // `StackPool::allocate`.
+    let slot = unsafe { pointer.add(layout.size()) }.cast::<Slot<A>>();
+    // SAFETY: The slot is initialized in `StackPool::new_slot`.
+    let pool = unsafe { slot.as_ref() }.pool;
+    pool.push(slot);
+}
+
+impl<A> Drop for StackPool<A> {
+    fn drop(&mut self) {
+        while let Some(slot) = self.pop() {
+            // SAFETY: The slot is removed from the free list, and the memory where
+            // it lies is deallocated only after it's moved out.
+            drop(unsafe { slot.read() });
+        }
+    }
+}
+
+impl<A: fmt::Debug> fmt::Debug for StackPool<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StackPool")
+            .field("alloc", &self.alloc)
+            .field("layout", &self.layout)
+            .field("cached", &self.cached())
+            .finish()
+    }
+}
+
+// SAFETY: The pooled stacks are allocated from the underlying allocator, and
+// the slots are never handed out to the users.
+unsafe impl<A: StackAllocator> StackAllocator for &'static StackPool<A> {
+    fn allocate(&self, layout: Layout) -> Result<Stack, AllocError> {
+        self.allocate_reused(layout).map(|(stack, _)| stack)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::alloc::Layout;
+    use std::{alloc::System, vec::Vec};
+
+    use super::StackPool;
+    use crate::{StackAllocator, DEFAULT_LAYOUT};
+
+    #[test]
+    fn pool() {
+        static POOL: StackPool<System> = StackPool::new(System, DEFAULT_LAYOUT);
+        let pool = &POOL;
+
+        pool.prewarm(2).unwrap();
+        assert_eq!(pool.cached(), 2);
+
+        let request = Layout::from_size_align(4096, 16).unwrap();
+        let stacks: Vec<_> = (0..3).map(|_| pool.allocate(request).unwrap()).collect();
+        assert_eq!(pool.cached(), 0);
+        for stack in &stacks {
+            assert!(stack.layout().size() >= 4096);
+            assert_eq!(stack.base().addr().get() % 4096, 0);
+        }
+        drop(stacks);
+        assert_eq!(pool.cached(), 3);
+
+        // Requests too large for the pool are not pooled.
+        let large = Layout::from_size_align(DEFAULT_LAYOUT.size() * 2, 16).unwrap();
+        let stack = pool.allocate(large).unwrap();
+        assert!(stack.layout().size() > DEFAULT_LAYOUT.size());
+        drop(stack);
+        assert_eq!(pool.cached(), 3);
+
+        // Nor do they create any pooled stack in vain.
+        static EMPTY: StackPool<System> = StackPool::new(System, DEFAULT_LAYOUT);
+        let empty = &EMPTY;
+        drop(empty.allocate(large).unwrap());
+        assert_eq!(empty.cached(), 0);
+    }
+}
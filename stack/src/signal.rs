@@ -0,0 +1,149 @@
+//! Alternate signal stacks, on which the signal handlers can run even if the
+//! current stack has overflowed.
+
+use core::{
+    ffi::c_void,
+    fmt,
+    mem::MaybeUninit,
+    ptr::{self, NonNull},
+};
+use std::io::{Error as IoError, Result as IoResult};
+
+/// An alternate signal stack installed on the current thread, which is
+/// uninstalled when dropped.
+///
+/// Signal handlers run on it only if they're registered with `SA_ONSTACK`,
+/// which is necessary for the handlers of `SIGSEGV` caused by hitting the
+/// guard page of a coroutine's stack.
+pub struct SignalStack {
+    mapping: NonNull<c_void>,
+    guard: usize,
+    size: usize,
+    old: libc::stack_t,
+}
+
+/// Installs an alternate signal stack of at least `size` bytes on the current
+/// thread, which is protected by a guard page below it.
+///
+/// The previous signal stack, if any, is restored when the returned object is
+/// dropped.
+pub fn install_signal_stack(size: usize) -> IoResult<SignalStack> {
+    // SAFETY: `sysconf` has no contract.
+    let guard = match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        page @ 1.. => page as usize,
+        _ => return Err(IoError::last_os_error()),
+    };
+    let size = size.max(libc::MINSIGSTKSZ).next_multiple_of(guard);
+
+    // SAFETY: An anonymous mapping has no contract.
+    let mapping = unsafe {
+        libc::mmap(
+            ptr::null_mut(),
+            guard + size,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        )
+    };
+    let mapping = match NonNull::new(mapping) {
+        Some(mapping) if mapping.as_ptr() != libc::MAP_FAILED => mapping,
+        _ => return Err(IoError::last_os_error()),
+    };
+
+    let mut old = MaybeUninit::uninit();
+    let new = libc::stack_t {
+        // SAFETY: The usable memory lies in the mapping right after the guard.
+        ss_sp: unsafe { mapping.byte_add(guard) }.as_ptr(),
+        ss_flags: 0,
+        ss_size: size,
+    };
+    // SAFETY: The guard page lies in the mapping, and `new` describes the rest
+    // of it.
+    let status = unsafe {
+        match libc::mprotect(mapping.as_ptr(), guard, libc::PROT_NONE) {
+            0 => libc::sigaltstack(&new, old.as_mut_ptr()),
+            status => status,
+        }
+    };
+    if status != 0 {
+        let err = IoError::last_os_error();
+        // SAFETY: The mapping is not installed.
+        unsafe { libc::munmap(mapping.as_ptr(), guard + size) };
+        return Err(err);
+    }
+
+    Ok(SignalStack {
+        mapping,
+        guard,
+        size,
+        // SAFETY: `old` is filled by `sigaltstack`.
+        old: unsafe { old.assume_init() },
+    })
+}
+
+impl SignalStack {
+    /// The usable memory of the signal stack.
+    pub fn memory(&self) -> NonNull<[u8]> {
+        // SAFETY: The usable memory lies in the mapping right after the guard.
+        let base = unsafe { self.mapping.byte_add(self.guard) };
+        NonNull::slice_from_raw_parts(base.cast(), self.size)
+    }
+}
+
+impl Drop for SignalStack {
+    fn drop(&mut self) {
+        // SAFETY: `old` is filled by `sigaltstack`.
+        if unsafe { libc::sigaltstack(&self.old, ptr::null_mut()) } == 0 {
+            // SAFETY: The mapping is no longer installed.
+            unsafe { libc::munmap(self.mapping.as_ptr(), self.guard + self.size) };
+        }
+        // Otherwise, the mapping is leaked since it may still be in use, e.g.
+        // when dropped in a signal handler running on it.
+    }
+}
+
+impl fmt::Debug for SignalStack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SignalStack")
+            .field("memory", &self.memory())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{mem::MaybeUninit, ptr};
+
+    use super::install_signal_stack;
+
+    fn current() -> libc::stack_t {
+        let mut current = MaybeUninit::uninit();
+        // SAFETY: Only the current signal stack is queried.
+        assert_eq!(
+            unsafe { libc::sigaltstack(ptr::null(), current.as_mut_ptr()) },
+            0
+        );
+        // SAFETY: `current` is filled by `sigaltstack`.
+        unsafe { current.assume_init() }
+    }
+
+    #[test]
+    fn signal_stack() {
+        let before = current();
+        let stack = install_signal_stack(100).unwrap();
+        let memory = stack.memory();
+        assert!(memory.len() >= libc::MINSIGSTKSZ);
+
+        let installed = current();
+        assert_eq!(installed.ss_sp, memory.as_mut_ptr().cast());
+        assert_eq!(installed.ss_size, memory.len());
+
+        drop(stack);
+        let after = current();
+        assert_eq!(
+            (after.ss_sp, after.ss_flags),
+            (before.ss_sp, before.ss_flags)
+        );
+    }
+}
@@ -0,0 +1,20 @@
+//! Builds and runs without any `global_resumer!` or `global_stack_allocator!`,
+//! relying on the defaults installed by the `default-boost` and
+//! `default-global-stack` features.
+
+use futures_lite::future::yield_now;
+use unico::{
+    asym::{sync, AsymWait},
+    runtime::block_on,
+};
+
+#[test]
+fn defaults() {
+    assert_eq!(unico::context::backend_info().name, "boost");
+
+    let sum = block_on(sync(|| {
+        yield_now().wait();
+        (1..=10).sum::<u32>()
+    }));
+    assert_eq!(sum, 55);
+}